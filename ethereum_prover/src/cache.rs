@@ -1,8 +1,14 @@
 use std::path::PathBuf;
 
-use alloy::{
-    primitives::B256,
-    rpc::types::{Block as RpcBlock, TransactionReceipt, debug::ExecutionWitness},
+use alloy::rpc::types::{Block as RpcBlock, TransactionReceipt, debug::ExecutionWitness};
+use anyhow::Context as _;
+
+use crate::{
+    prover::{
+        debugger::DebugReport, diff_exec::DiffExecReport, profiler::CycleProfile,
+        tracer::BlockTraces,
+    },
+    witness_file::WitnessFile,
 };
 
 #[derive(Debug, Clone)]
@@ -15,7 +21,14 @@ struct BlockCachePaths {
     dir: PathBuf,
     block_json: PathBuf,
     execution_witness_json: PathBuf,
-    receipts_dir: PathBuf,
+    receipts_json: PathBuf,
+    invalid_proof_bin: PathBuf,
+    debug_report_json: PathBuf,
+    diff_exec_report_json: PathBuf,
+    call_traces_json: PathBuf,
+    prestate_traces_json: PathBuf,
+    tx_stats_json: PathBuf,
+    cpu_witness_bin: PathBuf,
 }
 
 impl CacheStorage {
@@ -69,32 +82,140 @@ impl CacheStorage {
         Ok(Some((block, witness)))
     }
 
-    pub fn save_receipt(
+    /// Stores the receipts of all transactions of the block, in block order.
+    pub fn save_receipts(
         &self,
         block_number: u64,
-        receipt: TransactionReceipt,
+        receipts: &[TransactionReceipt],
     ) -> anyhow::Result<()> {
         let paths = self.ensure_block_dir(block_number)?;
-        let tx_hash = receipt.transaction_hash;
-        let receipt_path = paths.receipts_dir.join(format!("{:?}.json", tx_hash));
-        let data = serde_json::to_string_pretty(&receipt)?;
-        std::fs::write(receipt_path, data)?;
+        let data = serde_json::to_string_pretty(receipts)?;
+        std::fs::write(paths.receipts_json, data)?;
         Ok(())
     }
 
-    pub fn load_receipt(
+    pub fn load_receipts(
+        &self,
+        block_number: u64,
+    ) -> anyhow::Result<Option<Vec<TransactionReceipt>>> {
+        let paths = self.block_paths(block_number);
+        if !paths.receipts_json.exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read_to_string(paths.receipts_json)?;
+        let receipts = serde_json::from_str(&data)?;
+        Ok(Some(receipts))
+    }
+
+    /// Stores a proof that failed verification next to the block inputs, so that it can be debugged.
+    pub fn save_invalid_proof(
+        &self,
+        block_number: u64,
+        proof_bytes: &[u8],
+    ) -> anyhow::Result<PathBuf> {
+        let paths = self.ensure_block_dir(block_number)?;
+        std::fs::write(&paths.invalid_proof_bin, proof_bytes)?;
+        Ok(paths.invalid_proof_bin)
+    }
+
+    /// Stores the divergences found by the transaction debugger next to the block inputs.
+    pub fn save_debug_report(&self, report: &DebugReport) -> anyhow::Result<PathBuf> {
+        let paths = self.ensure_block_dir(report.block_number)?;
+        let data = serde_json::to_string_pretty(report)?;
+        std::fs::write(&paths.debug_report_json, data)?;
+        Ok(paths.debug_report_json)
+    }
+
+    /// Stores the result of the differential execution with revm next to the block inputs.
+    pub fn save_diff_exec_report(&self, report: &DiffExecReport) -> anyhow::Result<PathBuf> {
+        let paths = self.ensure_block_dir(report.block_number)?;
+        let data = serde_json::to_string_pretty(report)?;
+        std::fs::write(&paths.diff_exec_report_json, data)?;
+        Ok(paths.diff_exec_report_json)
+    }
+
+    /// Stores the execution traces of a debugged block next to the block inputs: call frames and
+    /// pre-state in the format of geth's `callTracer` and `prestateTracer` respectively, plus
+    /// per-transaction opcode counts and storage writes. Returns the block directory.
+    pub fn save_debug_traces(
         &self,
         block_number: u64,
-        tx_hash: &B256,
-    ) -> anyhow::Result<Option<TransactionReceipt>> {
+        traces: &BlockTraces,
+    ) -> anyhow::Result<PathBuf> {
+        let paths = self.ensure_block_dir(block_number)?;
+        std::fs::write(
+            &paths.call_traces_json,
+            serde_json::to_string_pretty(&traces.calls)?,
+        )?;
+        std::fs::write(
+            &paths.prestate_traces_json,
+            serde_json::to_string_pretty(&traces.prestate)?,
+        )?;
+        std::fs::write(
+            &paths.tx_stats_json,
+            serde_json::to_string_pretty(&traces.stats)?,
+        )?;
+        Ok(paths.dir)
+    }
+
+    /// Stores the CPU witness generated for a block next to the block inputs.
+    pub fn save_cpu_witness(&self, witness: &WitnessFile) -> anyhow::Result<PathBuf> {
+        let paths = self.ensure_block_dir(witness.block_number)?;
+        witness.write(&paths.cpu_witness_bin)?;
+        Ok(paths.cpu_witness_bin)
+    }
+
+    pub fn load_cpu_witness(&self, block_number: u64) -> anyhow::Result<Option<WitnessFile>> {
         let paths = self.block_paths(block_number);
-        let receipt_path = paths.receipts_dir.join(format!("{:?}.json", tx_hash));
-        if !receipt_path.exists() {
+        if !paths.cpu_witness_bin.exists() {
+            return Ok(None);
+        }
+        WitnessFile::read(&paths.cpu_witness_bin).map(Some)
+    }
+
+    /// Stores the cycle profile of a block in `profiles/<block_number>/`: the attribution of the
+    /// cycles in `cycle_profile.json` and the folded stacks for flamegraphs in `cycles.folded`.
+    /// Profiles are kept apart from the block inputs, which may be removed once the block is
+    /// processed. Returns the profile directory.
+    pub fn save_cycle_profile(&self, profile: &CycleProfile) -> anyhow::Result<PathBuf> {
+        let dir = self
+            .root
+            .join("profiles")
+            .join(profile.block_number.to_string());
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("cycle_profile.json"),
+            serde_json::to_string_pretty(profile)?,
+        )?;
+        std::fs::write(dir.join("cycles.folded"), profile.folded())?;
+        Ok(dir)
+    }
+
+    /// Returns the last block processed by the continuous block stream in `catch_up` mode.
+    pub fn load_checkpoint(&self) -> anyhow::Result<Option<u64>> {
+        let path = self.checkpoint_path();
+        if !path.exists() {
             return Ok(None);
         }
-        let data = std::fs::read_to_string(receipt_path)?;
-        let receipt = serde_json::from_str(&data)?;
-        Ok(Some(receipt))
+        let data = std::fs::read_to_string(&path)?;
+        let block_number = data
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid checkpoint in {}", path.display()))?;
+        Ok(Some(block_number))
+    }
+
+    pub fn save_checkpoint(&self, block_number: u64) -> anyhow::Result<()> {
+        // Write to a temporary file first, so that the checkpoint is never left half-written.
+        let path = self.checkpoint_path();
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, block_number.to_string())?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    fn checkpoint_path(&self) -> PathBuf {
+        self.root.join("last_processed_block")
     }
 
     fn block_paths(&self, block_number: u64) -> BlockCachePaths {
@@ -103,14 +224,20 @@ impl CacheStorage {
             dir: dir.clone(),
             block_json: dir.join("block.json"),
             execution_witness_json: dir.join("execution_witness.json"),
-            receipts_dir: dir.join("receipts"),
+            receipts_json: dir.join("receipts.json"),
+            invalid_proof_bin: dir.join("invalid_proof.bin"),
+            debug_report_json: dir.join("debug_report.json"),
+            diff_exec_report_json: dir.join("diff_exec_report.json"),
+            call_traces_json: dir.join("call_traces.json"),
+            prestate_traces_json: dir.join("prestate_traces.json"),
+            tx_stats_json: dir.join("tx_stats.json"),
+            cpu_witness_bin: dir.join("cpu_witness.bin"),
         }
     }
 
     fn ensure_block_dir(&self, block_number: u64) -> anyhow::Result<BlockCachePaths> {
         let paths = self.block_paths(block_number);
         std::fs::create_dir_all(&paths.dir)?;
-        std::fs::create_dir_all(&paths.receipts_dir)?;
         Ok(paths)
     }
 
@@ -158,7 +285,8 @@ impl CacheStorage {
 
 #[cfg(test)]
 mod tests {
-    use super::{CacheStorage, RpcBlock};
+    use super::{CacheStorage, RpcBlock, WitnessFile};
+    use alloy::primitives::B256;
     use tempfile::tempdir;
 
     #[test]
@@ -199,4 +327,34 @@ mod tests {
             .expect("witness exists");
         assert_eq!(loaded.headers.len(), witness.headers.len());
     }
+
+    #[test]
+    fn cache_roundtrips_cpu_witness() {
+        let dir = tempdir().expect("create tempdir");
+        let cache = CacheStorage::new(dir.path()).expect("create cache");
+        assert!(cache.load_cpu_witness(7).expect("load witness").is_none());
+
+        let witness = WitnessFile {
+            block_number: 7,
+            app_bin_hash: B256::repeat_byte(1),
+            words: vec![1, 2, 3],
+        };
+        cache.save_cpu_witness(&witness).expect("save witness");
+        let loaded = cache
+            .load_cpu_witness(7)
+            .expect("load witness")
+            .expect("witness exists");
+        assert_eq!(loaded, witness);
+    }
+
+    #[test]
+    fn cache_roundtrips_checkpoint() {
+        let dir = tempdir().expect("create tempdir");
+        let cache = CacheStorage::new(dir.path()).expect("create cache");
+        assert_eq!(cache.load_checkpoint().expect("load checkpoint"), None);
+
+        cache.save_checkpoint(100).expect("save checkpoint");
+        cache.save_checkpoint(110).expect("save checkpoint");
+        assert_eq!(cache.load_checkpoint().expect("load checkpoint"), Some(110));
+    }
 }
@@ -1,4 +1,5 @@
 use anyhow::Context as _;
+use async_trait::async_trait;
 use base64::Engine;
 use flate2::Compression;
 use flate2::write::GzEncoder;
@@ -8,12 +9,29 @@ use std::io::Write;
 use std::time::Duration;
 
 use crate::metrics::METRICS;
+
+pub(crate) mod outbox;
+
 const ETHPROOFS_STAGING_URL: &str = "https://staging--ethproofs.netlify.app/api/v0/";
 const ETHPROOFS_PRODUCTION_URL: &str = "https://ethproofs.netlify.app/api/v0/";
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
-const MAX_ATTEMPTS: usize = 3;
-const BASE_BACKOFF_MS: u64 = 200;
+
+/// Proof status updates accepted by EthProofs.
+#[async_trait]
+pub trait EthProofsApi: Send + Sync {
+    async fn queue_proof(&self, block_number: u64) -> anyhow::Result<()>;
+
+    async fn proving_proof(&self, block_number: u64) -> anyhow::Result<()>;
+
+    async fn send_proof(
+        &self,
+        block_number: u64,
+        proof_bytes: &[u8],
+        proving_time_secs: f64,
+        cycles: u64,
+    ) -> anyhow::Result<()>;
+}
 
 #[derive(Clone, Debug)]
 pub struct EthproofsClient {
@@ -43,7 +61,42 @@ impl EthproofsClient {
         }
     }
 
-    pub async fn queue_proof(&self, block_number: u64) -> anyhow::Result<()> {
+    /// Makes a single request; failed updates are retried by the upload task via the outbox.
+    async fn post<T: Serialize>(
+        &self,
+        endpoint: &str,
+        payload: &T,
+        context: &'static str,
+    ) -> anyhow::Result<()> {
+        let latency = METRICS.ethproofs_request_duration.start();
+        let response = self
+            .client
+            .post(endpoint)
+            .bearer_auth(&self.auth_token)
+            .json(payload)
+            .send()
+            .await;
+        latency.observe();
+
+        let status = match response {
+            Ok(response) => response.status(),
+            Err(err) => {
+                METRICS.ethproofs_request_failure_total.inc();
+                return Err(err).context(context);
+            }
+        };
+        if !status.is_success() {
+            METRICS.ethproofs_request_failure_total.inc();
+            return Err(StatusError(status)).context(context);
+        }
+        METRICS.ethproofs_request_success_total.inc();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EthProofsApi for EthproofsClient {
+    async fn queue_proof(&self, block_number: u64) -> anyhow::Result<()> {
         let payload = ProofRequest {
             block_number,
             cluster_id: self.cluster_id,
@@ -54,7 +107,7 @@ impl EthproofsClient {
         Ok(())
     }
 
-    pub async fn proving_proof(&self, block_number: u64) -> anyhow::Result<()> {
+    async fn proving_proof(&self, block_number: u64) -> anyhow::Result<()> {
         let payload = ProofRequest {
             block_number,
             cluster_id: self.cluster_id,
@@ -65,14 +118,14 @@ impl EthproofsClient {
         Ok(())
     }
 
-    pub async fn send_proof(
+    async fn send_proof(
         &self,
         block_number: u64,
         proof_bytes: &[u8],
         proving_time_secs: f64,
         cycles: u64,
     ) -> anyhow::Result<()> {
-        let encoded_proof = encode_proof(proof_bytes)?;
+        let encoded_proof = encode_proof_payload(proof_bytes)?;
         let payload = EthProofPayload {
             block_number,
             cluster_id: self.cluster_id,
@@ -86,70 +139,6 @@ impl EthproofsClient {
             .await?;
         Ok(())
     }
-
-    async fn post<T: Serialize>(
-        &self,
-        endpoint: &str,
-        payload: &T,
-        context: &'static str,
-    ) -> anyhow::Result<()> {
-        let latency = METRICS.ethproofs_request_duration.start();
-        for attempt in 1..=MAX_ATTEMPTS {
-            let response = self
-                .client
-                .post(endpoint)
-                .bearer_auth(&self.auth_token)
-                .json(payload)
-                .send()
-                .await;
-
-            match response {
-                Ok(response) => {
-                    let status = response.status();
-                    if status.is_success() {
-                        METRICS.ethproofs_request_success_total.inc();
-                        latency.observe();
-                        return Ok(());
-                    }
-                    if should_retry_status(status) && attempt < MAX_ATTEMPTS {
-                        tracing::warn!(
-                            "ethproofs request failed with status {}, retrying (attempt {}/{})",
-                            status,
-                            attempt,
-                            MAX_ATTEMPTS
-                        );
-                    } else {
-                        METRICS.ethproofs_request_failure_total.inc();
-                        latency.observe();
-                        return Err(anyhow::anyhow!(
-                            "{context}: request failed with status {status}"
-                        ));
-                    }
-                }
-                Err(err) => {
-                    if should_retry_error(&err) && attempt < MAX_ATTEMPTS {
-                        tracing::warn!(
-                            "ethproofs request error: {}, retrying (attempt {}/{})",
-                            err,
-                            attempt,
-                            MAX_ATTEMPTS
-                        );
-                    } else {
-                        METRICS.ethproofs_request_failure_total.inc();
-                        latency.observe();
-                        return Err(err).context(context);
-                    }
-                }
-            }
-
-            let backoff_ms = BASE_BACKOFF_MS.saturating_mul(1 << (attempt - 1));
-            tokio::time::sleep(Duration::from_millis(backoff_ms)).await;
-        }
-
-        METRICS.ethproofs_request_failure_total.inc();
-        latency.observe();
-        Err(anyhow::anyhow!("{context}: request failed after retries"))
-    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -168,7 +157,8 @@ pub struct ProofRequest {
     pub cluster_id: u64,
 }
 
-fn encode_proof(proof_bytes: &[u8]) -> anyhow::Result<String> {
+/// Encodes proof bytes the way EthProofs expects them in the payload: gzip, then base64.
+fn encode_proof_payload(proof_bytes: &[u8]) -> anyhow::Result<String> {
     let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
     encoder.write_all(proof_bytes)?;
     let compressed = encoder.finish()?;
@@ -176,25 +166,50 @@ fn encode_proof(proof_bytes: &[u8]) -> anyhow::Result<String> {
     Ok(encoded)
 }
 
+/// Proof bytes as they are submitted to EthProofs, for tests of other modules.
+#[cfg(test)]
+pub(crate) fn proof_payload_for_tests(proof_bytes: &[u8]) -> String {
+    encode_proof_payload(proof_bytes).expect("encode proof payload")
+}
+
+/// EthProofs responded to a request with an unsuccessful status.
+#[derive(Debug, thiserror::Error)]
+#[error("request failed with status {0}")]
+pub struct StatusError(pub StatusCode);
+
+/// Returns whether a failed EthProofs request may succeed if retried.
+///
+/// Only network errors, server errors and rate limiting are retried; anything else (e.g. an
+/// update rejected as invalid or unauthorized) would fail the same way again.
+pub fn is_retryable(err: &anyhow::Error) -> bool {
+    if let Some(StatusError(status)) = err.downcast_ref() {
+        return should_retry_status(*status);
+    }
+    err.downcast_ref::<reqwest::Error>()
+        .is_some_and(should_retry_error)
+}
+
 fn should_retry_status(status: StatusCode) -> bool {
     status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
 }
 
 fn should_retry_error(err: &reqwest::Error) -> bool {
-    err.is_timeout() || err.is_connect()
+    err.is_timeout() || err.is_connect() || err.is_request()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::encode_proof;
+    use super::{StatusError, encode_proof_payload, is_retryable};
+    use anyhow::Context as _;
     use base64::Engine as _;
     use flate2::read::GzDecoder;
+    use reqwest::StatusCode;
     use std::io::Read;
 
     #[test]
-    fn encode_proof_roundtrips() {
+    fn encode_proof_payload_roundtrips() {
         let input = b"proof-bytes-test-vector";
-        let encoded = encode_proof(input).expect("encode proof");
+        let encoded = encode_proof_payload(input).expect("encode proof");
         let decoded = base64::engine::general_purpose::STANDARD
             .decode(encoded)
             .expect("decode base64");
@@ -203,4 +218,19 @@ mod tests {
         decoder.read_to_end(&mut output).expect("decompress");
         assert_eq!(output, input);
     }
+
+    #[test]
+    fn only_transient_failures_are_retried() {
+        let failure = |status| {
+            Err::<(), _>(StatusError(status))
+                .context("ethproofs submission failed")
+                .unwrap_err()
+        };
+        assert!(is_retryable(&failure(StatusCode::INTERNAL_SERVER_ERROR)));
+        assert!(is_retryable(&failure(StatusCode::TOO_MANY_REQUESTS)));
+        assert!(!is_retryable(&failure(StatusCode::BAD_REQUEST)));
+        assert!(!is_retryable(&failure(StatusCode::UNAUTHORIZED)));
+        assert!(!is_retryable(&failure(StatusCode::UNPROCESSABLE_ENTITY)));
+        assert!(!is_retryable(&anyhow::anyhow!("failed to read proof")));
+    }
 }
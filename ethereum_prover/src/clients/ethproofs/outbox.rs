@@ -0,0 +1,387 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+
+use crate::observability;
+
+/// Delay before the first retry of a failed submission.
+const BASE_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+/// Upper bound for the delay between retries.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// EthProofs status update that has not been acknowledged yet.
+///
+/// Variants are ordered in the same way they must be submitted for a single block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum UpdateKind {
+    Queued,
+    Proving,
+    Proved,
+}
+
+impl UpdateKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            UpdateKind::Queued => "queued",
+            UpdateKind::Proving => "proving",
+            UpdateKind::Proved => "proved",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct OutboxEntry {
+    pub block_number: u64,
+    pub kind: UpdateKind,
+    /// Number of failed submission attempts so far.
+    pub attempts: u32,
+    /// Unix timestamp (in milliseconds) before which the entry must not be retried.
+    pub next_attempt_at_ms: u64,
+    /// Proof details, only set for [`UpdateKind::Proved`]. Proof bytes are stored separately.
+    pub proof: Option<ProofDetails>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ProofDetails {
+    pub cycles: u64,
+    pub proving_time_secs: f64,
+}
+
+/// Persistent queue of EthProofs updates that failed to be submitted.
+///
+/// Each entry is stored as `<root>/<block_number>-<kind>.json`; proofs additionally have
+/// their bytes stored in `<root>/<block_number>-proved.bin`, so that the outbox doesn't
+/// depend on any other storage. Entries of a single block are always retried in order.
+#[derive(Debug)]
+pub(crate) struct Outbox {
+    root: PathBuf,
+    entries: BTreeMap<(u64, UpdateKind), OutboxEntry>,
+}
+
+impl Outbox {
+    /// Opens the outbox, loading all the entries left from previous runs.
+    pub fn open(root: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+
+        let mut entries = BTreeMap::new();
+        for dir_entry in std::fs::read_dir(&root)? {
+            let path = dir_entry?.path();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("json") => {}
+                // Left over from a write interrupted by a crash.
+                Some("tmp") => {
+                    std::fs::remove_file(&path)?;
+                    continue;
+                }
+                _ => continue,
+            }
+            match read_entry(&path) {
+                Ok(entry) => {
+                    entries.insert((entry.block_number, entry.kind), entry);
+                }
+                Err(err) => {
+                    // A single unreadable entry must not prevent the prover from starting.
+                    observability::capture_anyhow(&err);
+                    let quarantine_path = with_added_extension(&path, "corrupt");
+                    tracing::error!(
+                        "{err:#}; moving it to {} and skipping it",
+                        quarantine_path.display()
+                    );
+                    std::fs::rename(&path, &quarantine_path)?;
+                }
+            }
+        }
+
+        Ok(Self { root, entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn has_pending_for(&self, block_number: u64) -> bool {
+        self.entries
+            .range((block_number, UpdateKind::Queued)..=(block_number, UpdateKind::Proved))
+            .next()
+            .is_some()
+    }
+
+    /// Adds an update that hasn't been acknowledged yet.
+    ///
+    /// `attempts` is the number of submissions already made: `1` for an update that has just
+    /// failed, `0` for an update deferred behind an earlier update of the same block.
+    pub fn push(
+        &mut self,
+        block_number: u64,
+        kind: UpdateKind,
+        proof: Option<(ProofDetails, &[u8])>,
+        attempts: u32,
+        now_ms: u64,
+    ) -> anyhow::Result<()> {
+        let (proof, proof_bytes) = proof.unzip();
+        if let Some(proof_bytes) = proof_bytes {
+            write_atomically(&self.proof_path(block_number), proof_bytes)?;
+        }
+        let entry = OutboxEntry {
+            block_number,
+            kind,
+            attempts,
+            next_attempt_at_ms: now_ms.saturating_add(retry_backoff(attempts).as_millis() as u64),
+            proof,
+        };
+        self.persist(&entry)?;
+        self.entries.insert((block_number, kind), entry);
+        Ok(())
+    }
+
+    /// Returns the entries that should be retried at `now_ms`.
+    /// Only the oldest entry of each block is considered, to keep updates ordered.
+    pub fn due_entries(&self, now_ms: u64) -> Vec<OutboxEntry> {
+        self.heads()
+            .filter(|entry| entry.next_attempt_at_ms <= now_ms)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the delay until the next entry becomes due, if there are any entries.
+    pub fn next_due_in(&self, now_ms: u64) -> Option<Duration> {
+        self.heads()
+            .map(|entry| entry.next_attempt_at_ms.saturating_sub(now_ms))
+            .min()
+            .map(Duration::from_millis)
+    }
+
+    pub fn load_proof(&self, block_number: u64) -> anyhow::Result<Vec<u8>> {
+        std::fs::read(self.proof_path(block_number)).with_context(|| {
+            format!("failed to read proof for block {block_number} from the outbox")
+        })
+    }
+
+    /// Records another failed attempt for the entry and schedules the next retry.
+    pub fn record_failure(&mut self, entry: &OutboxEntry, now_ms: u64) -> anyhow::Result<()> {
+        let Some(stored) = self.entries.get_mut(&(entry.block_number, entry.kind)) else {
+            return Ok(());
+        };
+        stored.attempts = stored.attempts.saturating_add(1);
+        stored.next_attempt_at_ms =
+            now_ms.saturating_add(retry_backoff(stored.attempts).as_millis() as u64);
+        let stored = stored.clone();
+        self.persist(&stored)
+    }
+
+    /// Removes an acknowledged entry.
+    pub fn remove(&mut self, entry: &OutboxEntry) -> anyhow::Result<()> {
+        self.entries.remove(&(entry.block_number, entry.kind));
+        let entry_path = self.entry_path(entry.block_number, entry.kind);
+        if entry_path.exists() {
+            std::fs::remove_file(entry_path)?;
+        }
+        if entry.kind == UpdateKind::Proved {
+            let proof_path = self.proof_path(entry.block_number);
+            if proof_path.exists() {
+                std::fs::remove_file(proof_path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes all pending entries of the block, returning how many were removed.
+    pub fn remove_block(&mut self, block_number: u64) -> anyhow::Result<usize> {
+        let entries: Vec<_> = self
+            .entries
+            .range((block_number, UpdateKind::Queued)..=(block_number, UpdateKind::Proved))
+            .map(|(_, entry)| entry.clone())
+            .collect();
+        for entry in &entries {
+            self.remove(entry)?;
+        }
+        Ok(entries.len())
+    }
+
+    /// Iterates over the oldest pending entry of each block.
+    fn heads(&self) -> impl Iterator<Item = &OutboxEntry> {
+        let mut last_block = None;
+        self.entries.values().filter(move |entry| {
+            let is_head = last_block != Some(entry.block_number);
+            last_block = Some(entry.block_number);
+            is_head
+        })
+    }
+
+    fn persist(&self, entry: &OutboxEntry) -> anyhow::Result<()> {
+        let data = serde_json::to_string_pretty(entry)?;
+        write_atomically(
+            &self.entry_path(entry.block_number, entry.kind),
+            data.as_bytes(),
+        )
+    }
+
+    fn entry_path(&self, block_number: u64, kind: UpdateKind) -> PathBuf {
+        self.root
+            .join(format!("{block_number}-{}.json", kind.as_str()))
+    }
+
+    fn proof_path(&self, block_number: u64) -> PathBuf {
+        self.root.join(format!("{block_number}-proved.bin"))
+    }
+}
+
+fn read_entry(path: &Path) -> anyhow::Result<OutboxEntry> {
+    let data = std::fs::read_to_string(path)?;
+    serde_json::from_str(&data)
+        .with_context(|| format!("failed to parse outbox entry {}", path.display()))
+}
+
+fn write_atomically(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    // Write to a temporary file first, so that a crash never leaves a half-written file behind.
+    let tmp_path = with_added_extension(path, "tmp");
+    std::fs::write(&tmp_path, data)
+        .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("failed to move {} into place", path.display()))?;
+    Ok(())
+}
+
+fn with_added_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    path.into()
+}
+
+/// Capped exponential backoff for the given number of failed attempts.
+/// Updates that were never attempted are due immediately.
+fn retry_backoff(attempts: u32) -> Duration {
+    if attempts == 0 {
+        return Duration::ZERO;
+    }
+    let exponent = attempts.saturating_sub(1).min(16);
+    BASE_RETRY_BACKOFF
+        .saturating_mul(1 << exponent)
+        .min(MAX_RETRY_BACKOFF)
+}
+
+pub(crate) fn unix_millis_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{MAX_RETRY_BACKOFF, Outbox, ProofDetails, UpdateKind, retry_backoff};
+
+    #[test]
+    fn retry_backoff_is_capped() {
+        assert_eq!(retry_backoff(0), Duration::ZERO);
+        assert_eq!(retry_backoff(1), Duration::from_secs(5));
+        assert_eq!(retry_backoff(2), Duration::from_secs(10));
+        assert_eq!(retry_backoff(20), MAX_RETRY_BACKOFF);
+        assert_eq!(retry_backoff(u32::MAX), MAX_RETRY_BACKOFF);
+    }
+
+    #[test]
+    fn outbox_survives_reopening() {
+        let dir = tempfile::tempdir().expect("create tempdir");
+        let details = ProofDetails {
+            cycles: 100,
+            proving_time_secs: 2.0,
+        };
+        {
+            let mut outbox = Outbox::open(dir.path()).expect("open outbox");
+            outbox
+                .push(
+                    10,
+                    UpdateKind::Proved,
+                    Some((details.clone(), b"proof")),
+                    1,
+                    0,
+                )
+                .expect("push entry");
+            outbox
+                .push(11, UpdateKind::Queued, None, 1, 0)
+                .expect("push entry");
+        }
+
+        let mut outbox = Outbox::open(dir.path()).expect("reopen outbox");
+        assert_eq!(outbox.len(), 2);
+        assert!(outbox.has_pending_for(10));
+        assert!(!outbox.has_pending_for(12));
+
+        let entries = outbox.due_entries(u64::MAX);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].proof, Some(details));
+        assert_eq!(outbox.load_proof(10).expect("load proof"), b"proof");
+
+        outbox.remove(&entries[0]).expect("remove entry");
+        let mut outbox = Outbox::open(dir.path()).expect("reopen outbox");
+        assert_eq!(outbox.len(), 1);
+        assert!(outbox.load_proof(10).is_err());
+
+        assert_eq!(outbox.remove_block(11).expect("remove block"), 1);
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn corrupt_entries_are_quarantined() {
+        let dir = tempfile::tempdir().expect("create tempdir");
+        {
+            let mut outbox = Outbox::open(dir.path()).expect("open outbox");
+            outbox
+                .push(10, UpdateKind::Queued, None, 1, 0)
+                .expect("push entry");
+        }
+        std::fs::write(dir.path().join("11-queued.json"), "{\"block_number\": 1").unwrap();
+        std::fs::write(dir.path().join("12-queued.json.tmp"), "{").unwrap();
+
+        let outbox = Outbox::open(dir.path()).expect("reopen outbox");
+        assert_eq!(outbox.len(), 1);
+        assert!(outbox.has_pending_for(10));
+        assert!(dir.path().join("11-queued.json.corrupt").exists());
+        assert!(!dir.path().join("11-queued.json").exists());
+        assert!(!dir.path().join("12-queued.json.tmp").exists());
+    }
+
+    #[test]
+    fn outbox_keeps_updates_of_a_block_ordered() {
+        let dir = tempfile::tempdir().expect("create tempdir");
+        let mut outbox = Outbox::open(dir.path()).expect("open outbox");
+        outbox
+            .push(10, UpdateKind::Proving, None, 0, 0)
+            .expect("push entry");
+        outbox
+            .push(10, UpdateKind::Queued, None, 1, 0)
+            .expect("push entry");
+
+        assert!(outbox.due_entries(0).is_empty());
+        assert_eq!(outbox.next_due_in(0), Some(retry_backoff(1)));
+
+        let due = outbox.due_entries(u64::MAX);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].kind, UpdateKind::Queued);
+
+        outbox.record_failure(&due[0], 0).expect("record failure");
+        assert_eq!(outbox.due_entries(u64::MAX)[0].attempts, 2);
+        // The next retry of the queued update also delays the proving update.
+        assert_eq!(outbox.next_due_in(0), Some(retry_backoff(2)));
+
+        // The deferred update was never attempted, so it is due as soon as it's unblocked.
+        outbox.remove(&due[0]).expect("remove entry");
+        let due = outbox.due_entries(0);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].kind, UpdateKind::Proving);
+        assert_eq!(due[0].attempts, 0);
+    }
+}
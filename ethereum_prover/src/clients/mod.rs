@@ -1 +1,2 @@
 pub(crate) mod ethproofs;
+pub(crate) mod rpc;
@@ -0,0 +1,473 @@
+//! Ethereum RPC endpoints with health tracking and failover.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use alloy::providers::DynProvider;
+use anyhow::Context as _;
+use smart_config::value::ExposeSecret as _;
+use tokio::time::Instant;
+use url::Url;
+
+use crate::metrics::METRICS;
+use crate::observability;
+use crate::types::{RpcEndpointConfig, RpcRole};
+
+const MAX_RPC_ATTEMPTS: usize = 3;
+const BASE_RPC_BACKOFF_MS: u64 = 200;
+/// An endpoint is skipped for this long after a failure; the cooldown doubles with every
+/// consecutive failure.
+const BASE_UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(5);
+const MAX_UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// Connects to the RPC; `ws://`/`wss://` URLs get a WebSocket transport, others use HTTP.
+pub(crate) async fn connect_provider(rpc_url: &Url) -> anyhow::Result<DynProvider> {
+    let provider = alloy::providers::ProviderBuilder::new()
+        .connect(rpc_url.as_str())
+        .await
+        .context("failed to connect to RPC")?;
+    Ok(DynProvider::new(provider))
+}
+
+/// Set of RPC endpoints, in the order of preference.
+#[derive(Debug, Clone)]
+pub(crate) struct RpcPool {
+    endpoints: Vec<Arc<RpcEndpoint>>,
+    cross_check: bool,
+}
+
+impl RpcPool {
+    /// Connects to the endpoints. An endpoint that can't be connected to is left out with a
+    /// warning, so that a backup being down doesn't prevent the prover from starting; the pool
+    /// only fails if a role is left without endpoints.
+    pub async fn connect(configs: &[RpcEndpointConfig], cross_check: bool) -> anyhow::Result<Self> {
+        let mut endpoints = Vec::with_capacity(configs.len());
+        for (index, config) in configs.iter().enumerate() {
+            let url = config
+                .url
+                .expose_secret()
+                .parse::<Url>()
+                .with_context(|| format!("invalid URL of RPC endpoint #{index}"))?;
+            let name = endpoint_name(index, &url);
+            let operation = format!("connect to RPC endpoint {name}");
+            let provider = match retry_rpc_call_with_config(
+                &operation,
+                MAX_RPC_ATTEMPTS,
+                BASE_RPC_BACKOFF_MS,
+                || connect_provider(&url),
+            )
+            .await
+            {
+                Ok(provider) => provider,
+                Err(err) => {
+                    observability::capture_anyhow(&err);
+                    tracing::warn!("Leaving out RPC endpoint {name}: {err:#}");
+                    continue;
+                }
+            };
+            endpoints.push(RpcEndpoint::new(
+                index,
+                name,
+                url,
+                config.roles.clone(),
+                provider,
+            ));
+        }
+        Self::new(endpoints, cross_check)
+    }
+
+    fn new(endpoints: Vec<RpcEndpoint>, cross_check: bool) -> anyhow::Result<Self> {
+        for role in [RpcRole::Head, RpcRole::Witness] {
+            anyhow::ensure!(
+                endpoints
+                    .iter()
+                    .any(|endpoint| endpoint.roles.contains(&role)),
+                "no connected RPC endpoint has the `{role:?}` role"
+            );
+        }
+        anyhow::ensure!(
+            !cross_check || endpoints.len() >= 2,
+            "cross-checking block hashes requires at least two RPC endpoints"
+        );
+        Ok(Self {
+            endpoints: endpoints.into_iter().map(Arc::new).collect(),
+            cross_check,
+        })
+    }
+
+    /// Whether block hashes must be confirmed by a second endpoint before proving.
+    pub fn cross_check(&self) -> bool {
+        self.cross_check
+    }
+
+    /// Returns the endpoint to use for `role`: the first healthy one or, if all of them
+    /// are unhealthy, the one that recovers first.
+    pub fn select(&self, role: RpcRole) -> Arc<RpcEndpoint> {
+        self.select_where(|endpoint| endpoint.roles.contains(&role))
+            .expect("every role has an endpoint, checked on creation")
+    }
+
+    /// Returns an endpoint other than `endpoint` to cross-check its responses against.
+    pub fn select_other(&self, endpoint: &RpcEndpoint) -> Option<Arc<RpcEndpoint>> {
+        self.select_where(|other| other.index != endpoint.index)
+    }
+
+    fn endpoint_count(&self, role: RpcRole) -> usize {
+        self.endpoints
+            .iter()
+            .filter(|endpoint| endpoint.roles.contains(&role))
+            .count()
+    }
+
+    fn select_where(&self, filter: impl Fn(&RpcEndpoint) -> bool) -> Option<Arc<RpcEndpoint>> {
+        let now = Instant::now();
+        // `None` (healthy) sorts first, and ties are resolved in the configured order.
+        self.endpoints
+            .iter()
+            .filter(|endpoint| filter(endpoint))
+            .min_by_key(|endpoint| endpoint.unhealthy_until().filter(|&until| until > now))
+            .cloned()
+    }
+}
+
+pub(crate) struct RpcEndpoint {
+    index: usize,
+    /// Name used in logs; unlike the URL, it doesn't contain credentials.
+    name: String,
+    url: Url,
+    roles: Vec<RpcRole>,
+    provider: DynProvider,
+    health: Mutex<EndpointHealth>,
+}
+
+impl fmt::Debug for RpcEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcEndpoint")
+            .field("name", &self.name)
+            .field("roles", &self.roles)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for RpcEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl RpcEndpoint {
+    fn new(
+        index: usize,
+        name: String,
+        url: Url,
+        roles: Vec<RpcRole>,
+        provider: DynProvider,
+    ) -> Self {
+        Self {
+            index,
+            name,
+            url,
+            roles,
+            provider,
+            health: Mutex::default(),
+        }
+    }
+
+    pub fn provider(&self) -> &DynProvider {
+        &self.provider
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Updates the health of the endpoint with the result of a request to it.
+    pub fn record<T>(&self, result: &anyhow::Result<T>) {
+        let mut health = self.health.lock().expect("endpoint health lock poisoned");
+        match result {
+            Ok(_) => {
+                if health.consecutive_failures > 0 {
+                    tracing::info!("RPC endpoint {self} is healthy again");
+                }
+                *health = EndpointHealth::default();
+            }
+            Err(err) => {
+                METRICS.rpc_endpoint_failure_total.inc();
+                let cooldown = health.record_failure(Instant::now());
+                tracing::warn!(
+                    "RPC endpoint {self} is considered unhealthy for {}s: {err}",
+                    cooldown.as_secs()
+                );
+            }
+        }
+    }
+
+    fn unhealthy_until(&self) -> Option<Instant> {
+        self.health
+            .lock()
+            .expect("endpoint health lock poisoned")
+            .unhealthy_until
+    }
+}
+
+#[derive(Debug, Default)]
+struct EndpointHealth {
+    consecutive_failures: u32,
+    unhealthy_until: Option<Instant>,
+}
+
+impl EndpointHealth {
+    fn record_failure(&mut self, now: Instant) -> Duration {
+        self.consecutive_failures += 1;
+        let cooldown = BASE_UNHEALTHY_COOLDOWN
+            .saturating_mul(1 << (self.consecutive_failures - 1).min(16))
+            .min(MAX_UNHEALTHY_COOLDOWN);
+        self.unhealthy_until = Some(now + cooldown);
+        cooldown
+    }
+}
+
+fn endpoint_name(index: usize, url: &Url) -> String {
+    format!("#{index} ({})", url.host_str().unwrap_or("unknown host"))
+}
+
+/// Performs an RPC call with retries. Every attempt goes to the endpoint selected for `role`,
+/// so after a failure the call is retried against the next healthy endpoint.
+pub(crate) async fn retry_rpc_call<T, F, Fut>(
+    rpc: &RpcPool,
+    role: RpcRole,
+    operation: &str,
+    mut call: F,
+) -> anyhow::Result<T>
+where
+    F: FnMut(Arc<RpcEndpoint>) -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    // Give every endpoint a chance before giving up.
+    let max_attempts = MAX_RPC_ATTEMPTS.max(rpc.endpoint_count(role));
+    retry_rpc_call_with_config(operation, max_attempts, BASE_RPC_BACKOFF_MS, || {
+        let endpoint = rpc.select(role);
+        let response = call(endpoint.clone());
+        async move {
+            let result = response.await;
+            endpoint.record(&result);
+            result
+        }
+    })
+    .await
+}
+
+async fn retry_rpc_call_with_config<T, F, Fut>(
+    operation: &str,
+    max_attempts: usize,
+    base_backoff_ms: u64,
+    mut call: F,
+) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    anyhow::ensure!(
+        max_attempts > 0,
+        "retry policy requires at least one attempt"
+    );
+
+    for attempt in 1..=max_attempts {
+        match call().await {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < max_attempts => {
+                let backoff_ms = base_backoff_ms.saturating_mul(1_u64 << (attempt - 1));
+                tracing::warn!(
+                    "{operation} failed: {err}. Retrying attempt {}/{} in {}ms",
+                    attempt + 1,
+                    max_attempts,
+                    backoff_ms
+                );
+                tokio::time::sleep(Duration::from_millis(backoff_ms)).await;
+            }
+            Err(err) => return Err(err).with_context(|| operation.to_owned()),
+        }
+    }
+
+    unreachable!("retry loop always returns on success or on the final failure")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future;
+
+    use alloy::providers::DynProvider;
+    use tokio::time::Instant;
+
+    use super::{
+        BASE_UNHEALTHY_COOLDOWN, EndpointHealth, MAX_UNHEALTHY_COOLDOWN, RpcEndpoint, RpcPool,
+        endpoint_name, retry_rpc_call, retry_rpc_call_with_config,
+    };
+    use crate::types::{RpcEndpointConfig, RpcRole};
+
+    fn endpoint(index: usize, url: &str, roles: &[RpcRole]) -> RpcEndpoint {
+        let url: url::Url = url.parse().unwrap();
+        let provider =
+            DynProvider::new(alloy::providers::ProviderBuilder::new().connect_http(url.clone()));
+        RpcEndpoint::new(
+            index,
+            endpoint_name(index, &url),
+            url,
+            roles.to_vec(),
+            provider,
+        )
+    }
+
+    #[tokio::test]
+    async fn retry_rpc_call_retries_until_success() {
+        let mut attempts = 0;
+
+        let value = retry_rpc_call_with_config("fetch block", 3, 0, || {
+            attempts += 1;
+            future::ready(if attempts < 3 {
+                Err(anyhow::anyhow!("transient RPC error"))
+            } else {
+                Ok(42_u64)
+            })
+        })
+        .await
+        .expect("retry succeeds");
+
+        assert_eq!(value, 42);
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn retry_rpc_call_returns_final_error() {
+        let mut attempts = 0;
+
+        let err = retry_rpc_call_with_config("fetch head", 3, 0, || {
+            attempts += 1;
+            future::ready(Err::<u64, _>(anyhow::anyhow!("still failing")))
+        })
+        .await
+        .expect_err("retry should fail");
+
+        assert_eq!(attempts, 3);
+        assert!(err.to_string().contains("fetch head"));
+        assert!(
+            err.chain()
+                .any(|cause| cause.to_string().contains("still failing"))
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_rpc_call_fails_over_to_healthy_endpoint() {
+        let rpc = RpcPool::new(
+            vec![
+                endpoint(
+                    0,
+                    "http://primary.example.com",
+                    &[RpcRole::Head, RpcRole::Witness],
+                ),
+                endpoint(1, "http://head.example.com", &[RpcRole::Head]),
+                endpoint(2, "http://witness.example.com", &[RpcRole::Witness]),
+            ],
+            false,
+        )
+        .unwrap();
+
+        let mut used = Vec::new();
+        let value = retry_rpc_call(&rpc, RpcRole::Witness, "fetch witness", |endpoint| {
+            used.push(endpoint.to_string());
+            future::ready(
+                if endpoint.url().host_str() == Some("primary.example.com") {
+                    Err(anyhow::anyhow!("method not found"))
+                } else {
+                    Ok(42_u64)
+                },
+            )
+        })
+        .await
+        .expect("failover succeeds");
+
+        assert_eq!(value, 42);
+        assert_eq!(
+            used,
+            ["#0 (primary.example.com)", "#2 (witness.example.com)"]
+        );
+        // The failed endpoint is avoided until its cooldown ends.
+        assert_eq!(
+            rpc.select(RpcRole::Head).to_string(),
+            "#1 (head.example.com)"
+        );
+        assert_eq!(
+            rpc.select_other(&rpc.select(RpcRole::Witness))
+                .unwrap()
+                .to_string(),
+            "#1 (head.example.com)"
+        );
+    }
+
+    #[tokio::test]
+    async fn endpoints_that_fail_to_connect_are_left_out() {
+        let config = |url: &str, roles: &[RpcRole]| RpcEndpointConfig {
+            url: url.into(),
+            roles: roles.to_vec(),
+        };
+        // Nothing listens on port 1, so the WebSocket connection is refused.
+        let configs = [
+            config(
+                "http://primary.example.com",
+                &[RpcRole::Head, RpcRole::Witness],
+            ),
+            config("ws://127.0.0.1:1", &[RpcRole::Head]),
+        ];
+
+        let rpc = RpcPool::connect(&configs, false).await.unwrap();
+        assert_eq!(rpc.endpoint_count(RpcRole::Head), 1);
+        assert_eq!(
+            rpc.select(RpcRole::Head).to_string(),
+            "#0 (primary.example.com)"
+        );
+
+        let err = RpcPool::connect(&configs[1..], false).await.unwrap_err();
+        assert!(
+            err.to_string().contains("no connected RPC endpoint"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn pool_requires_every_role_and_two_endpoints_for_cross_check() {
+        let err = RpcPool::new(
+            vec![endpoint(0, "http://head.example.com", &[RpcRole::Head])],
+            false,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Witness"), "{err}");
+
+        let err = RpcPool::new(
+            vec![endpoint(
+                0,
+                "http://primary.example.com",
+                &[RpcRole::Head, RpcRole::Witness],
+            )],
+            true,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("two RPC endpoints"), "{err}");
+    }
+
+    #[test]
+    fn unhealthy_cooldown_grows_with_consecutive_failures() {
+        let now = Instant::now();
+        let mut health = EndpointHealth::default();
+        assert_eq!(health.record_failure(now), BASE_UNHEALTHY_COOLDOWN);
+        assert_eq!(health.record_failure(now), BASE_UNHEALTHY_COOLDOWN * 2);
+        for _ in 0..10 {
+            health.record_failure(now);
+        }
+        assert_eq!(health.record_failure(now), MAX_UNHEALTHY_COOLDOWN);
+        assert_eq!(health.unhealthy_until, Some(now + MAX_UNHEALTHY_COOLDOWN));
+    }
+}
@@ -1,3 +1,4 @@
+use alloy::primitives::B256;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
@@ -13,6 +14,43 @@ pub struct Cli {
 
 #[derive(Subcommand, Debug)]
 pub enum Command {
-    Block { block_number: Option<u64> },
+    Block {
+        block_number: Option<u64>,
+    },
     Run,
+    /// Process a list of historical blocks, e.g. to reprocess them after an STF fix.
+    Range {
+        /// First block of the range (inclusive).
+        #[arg(long, required_unless_present = "blocks_file", requires = "to")]
+        from: Option<u64>,
+        /// Last block of the range (inclusive).
+        #[arg(long, required_unless_present = "blocks_file", requires = "from")]
+        to: Option<u64>,
+        /// Process only every N-th block of the range.
+        #[arg(long, default_value_t = 1)]
+        step: u64,
+        /// File with block numbers to process, one per line.
+        #[arg(long, conflicts_with_all = ["from", "to", "step"])]
+        blocks_file: Option<PathBuf>,
+    },
+    /// Execute a block with both the STF and revm over the same execution witness, and compare
+    /// the per-transaction results and the post-state root.
+    DiffExec {
+        block_number: Option<u64>,
+    },
+    /// Verify a proof file: raw bincode (as in the proof store), gzip-compressed bincode,
+    /// or base64-encoded gzip (as submitted to EthProofs).
+    Verify {
+        #[arg(long)]
+        proof: PathBuf,
+        /// Defaults to `recursion_unified_setup.bin` next to the app binary.
+        #[arg(long)]
+        setup: Option<PathBuf>,
+        /// Defaults to `recursion_unified_layouts.bin` next to the app binary.
+        #[arg(long)]
+        layouts: Option<PathBuf>,
+        /// Fail unless the proof commits to the block with this hash.
+        #[arg(long)]
+        expected_block_hash: Option<B256>,
+    },
 }
@@ -5,7 +5,11 @@ use smart_config::{
 };
 use std::path::PathBuf;
 
-use crate::types::{CachePolicy, EthProofsSubmission, Mode, OnFailure};
+use crate::prover::cpu_witness::DEFAULT_CYCLE_LIMIT;
+use crate::types::{
+    BlockSelection, CachePolicy, EthProofsSubmission, Mode, OnFailure, RpcEndpointConfig,
+    WitnessSink, WitnessSource, WitnessValidation,
+};
 
 mod cli;
 pub use cli::{Cli, Command};
@@ -23,6 +27,63 @@ pub struct EthProverConfig {
     #[config(with = Serde![str])]
     pub mode: Mode,
 
+    /// Artificial proving latency in milliseconds, used only in `mock_prove` mode.
+    #[config(default_t = 0)]
+    pub mock_prover_latency_ms: u64,
+
+    /// Maximum number of RISC-V cycles a block may take to execute in `cpu_witness` mode.
+    /// Blocks that don't finish within the limit fail.
+    #[config(default_t = DEFAULT_CYCLE_LIMIT)]
+    pub cpu_cycle_limit: u64,
+
+    /// Profile the RISC-V execution of every block in `cpu_witness` mode, attributing cycles to
+    /// functions, transactions, precompiles and delegations. Slows down witness generation.
+    #[config(default_t = false)]
+    pub profile_cycles: bool,
+
+    /// ELF of `app_bin_path` with symbols, used by the cycle profiler.
+    /// Defaults to `app.elf` next to `app_bin_path`.
+    #[config(default_t = None)]
+    pub profiler_elf_path: Option<PathBuf>,
+
+    /// Number of cycles between two stack samples of the cycle profiler.
+    #[config(default_t = 100)]
+    pub profiler_sample_interval: u64,
+
+    /// GPU device groups for `gpu_prove` mode, e.g. `[[0, 1], [2, 3]]`. A prover is created for
+    /// every group, and every block goes to the next free group. A device may only belong to one
+    /// group. When empty, a single prover uses the default devices.
+    #[config(default_t = Vec::new())]
+    #[config(with = Serde![array])]
+    pub gpu_device_groups: Vec<Vec<usize>>,
+
+    /// Number of blocks fetched at the same time.
+    #[config(default_t = 1)]
+    pub fetch_concurrency: usize,
+
+    /// Number of fetched blocks that may wait to be prepared.
+    #[config(default_t = 1)]
+    pub fetch_queue_size: usize,
+
+    /// Number of blocks prepared for proving (witness validation and oracle build) at the same time.
+    #[config(default_t = 1)]
+    pub prepare_concurrency: usize,
+
+    /// Number of prepared blocks that may wait to be proven.
+    #[config(default_t = 1)]
+    pub prepare_queue_size: usize,
+
+    /// Target time from the block timestamp to its proof, in seconds. In continuous mode with `head`
+    /// block selection, blocks that can no longer be proven within the target are dropped.
+    /// No blocks are dropped if unset; not allowed with `catch_up` block selection.
+    #[config(default_t = None)]
+    pub latency_target_secs: Option<u64>,
+
+    /// Verify every generated proof before it is stored or submitted; invalid proofs are withheld.
+    /// Uses `recursion_unified_setup.bin` and `recursion_unified_layouts.bin` next to `app_bin_path`.
+    #[config(default_t = false)]
+    pub verify_proofs: bool,
+
     /// Cache policy for prover artifacts.
     #[config(default_t = CachePolicy::OnFailure)]
     #[config(with = Serde![str])]
@@ -43,6 +104,21 @@ pub struct EthProverConfig {
     #[config(default_t = 0)]
     pub prover_id: u64,
 
+    /// How the continuous mode picks the next block: `head` or `catch_up`.
+    #[config(default_t = BlockSelection::Head)]
+    #[config(with = Serde![str])]
+    pub block_selection: BlockSelection,
+
+    /// Maximum lag (in blocks) behind the head in `catch_up` mode.
+    /// Once the lag is exceeded, the missed blocks are skipped and the stream jumps to the head.
+    #[config(default_t = 1000)]
+    pub max_catch_up_lag: u64,
+
+    /// Number of blocks on top of a block before it's processed in continuous mode.
+    /// `0` means that the head is processed right away.
+    #[config(default_t = 0)]
+    pub confirmations: u64,
+
     /// Action to perform on failure.
     #[config(default_t = OnFailure::Exit)]
     #[config(with = Serde![str])]
@@ -52,6 +128,42 @@ pub struct EthProverConfig {
     #[config(default_t = None)]
     pub rpc_url: Option<SecretString>,
 
+    /// Additional Ethereum RPC endpoints, each with a `url` and optional `roles`: `head` (tracking
+    /// the chain head) and/or `witness` (fetching blocks and execution witnesses), both by default.
+    /// Requests fail over between the endpoints with the required role; `rpc_url` is tried first.
+    #[config(default_t = Vec::new())]
+    #[config(with = Serde![array])]
+    pub rpc_endpoints: Vec<RpcEndpointConfig>,
+
+    /// Check that a second RPC endpoint agrees on the block hash before proving a block.
+    #[config(default_t = false)]
+    pub rpc_cross_check: bool,
+
+    /// How execution witnesses are obtained: `rpc` (`debug_executionWitness`), `local` (built from
+    /// the prestate tracer, `eth_getProof` and `eth_getCode`, for nodes without `debug_executionWitness`)
+    /// or `auto` (`rpc`, falling back to `local`).
+    #[config(default_t = WitnessSource::Rpc)]
+    #[config(with = Serde![str])]
+    pub witness_source: WitnessSource,
+
+    /// What to do when the execution witness of a block is incomplete (missing trie nodes or
+    /// bytecodes, or ancestor headers that don't link up): `off`, `warn` or `enforce` (fail the block).
+    #[config(default_t = WitnessValidation::Warn)]
+    #[config(with = Serde![str])]
+    pub witness_validation: WitnessValidation,
+
+    /// Where CPU witnesses generated in `cpu_witness` mode are persisted: `off` or `cache`
+    /// (`cpu_witness.bin` in the block cache directory, with a checksum and the `app.bin` hash).
+    #[config(default_t = WitnessSink::Off)]
+    #[config(with = Serde![str])]
+    pub witness_sink: WitnessSink,
+
+    /// Whether the transaction debugger traces the execution of debugged blocks. Call frames,
+    /// pre-state of the accessed accounts and storage, and opcode counts are stored in the block
+    /// cache directory.
+    #[config(default_t = false)]
+    pub debug_trace_transactions: bool,
+
     /// EthProofs token.
     #[config(default_t = None)]
     pub ethproofs_token: Option<SecretString>,
@@ -132,8 +244,10 @@ impl EthProverConfig {
 
 #[cfg(test)]
 mod tests {
+    use smart_config::value::ExposeSecret as _;
+
     use super::EthProverConfig;
-    use crate::types::{CachePolicy, Mode, OnFailure};
+    use crate::types::{CachePolicy, Mode, OnFailure, RpcRole, WitnessSink};
 
     #[test]
     fn load_config_from_yaml() {
@@ -146,6 +260,14 @@ eth_prover:
   block_mod: 10
   prover_id: 2
   on_failure: exit
+  witness_sink: cache
+  gpu_device_groups: [[0, 1], [2, 3]]
+  fetch_concurrency: 4
+  latency_target_secs: 600
+  rpc_endpoints:
+    - url: "http://witness.example.com"
+      roles: [witness]
+    - url: "http://backup.example.com"
 "#;
         std::fs::write(&config_path, contents).expect("write config");
 
@@ -155,5 +277,22 @@ eth_prover:
         assert_eq!(config.block_mod, 10);
         assert_eq!(config.prover_id, 2);
         assert!(matches!(config.on_failure, OnFailure::Exit));
+        assert_eq!(config.witness_sink, WitnessSink::Cache);
+        assert_eq!(config.gpu_device_groups, [vec![0, 1], vec![2, 3]]);
+        assert_eq!(config.fetch_concurrency, 4);
+        assert_eq!(config.fetch_queue_size, 1);
+        assert_eq!(config.latency_target_secs, Some(600));
+        assert_eq!(config.rpc_endpoints.len(), 2);
+        assert_eq!(
+            config.rpc_endpoints[0].url.expose_secret(),
+            "http://witness.example.com"
+        );
+        assert!(!format!("{:?}", config.rpc_endpoints).contains("example.com"));
+        assert_eq!(config.rpc_endpoints[0].roles, [RpcRole::Witness]);
+        assert_eq!(
+            config.rpc_endpoints[1].roles,
+            [RpcRole::Head, RpcRole::Witness]
+        );
+        assert!(!config.rpc_cross_check);
     }
 }
@@ -1,15 +1,39 @@
 #![feature(allocator_api)]
 
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
 use anyhow::Context as _;
 use smart_config::value::ExposeSecret;
-use url::Url;
+use tokio::{sync::mpsc::Receiver, task::JoinSet};
 
 use crate::{
     cache::CacheStorage,
-    clients::ethproofs::EthproofsClient,
+    clients::{
+        ethproofs::{EthproofsClient, outbox::Outbox},
+        rpc::RpcPool,
+    },
     config::{Cli, Command, EthProverConfig},
-    prover::{cpu_witness::CpuWitnessGenerator, gpu_prover::Prover},
-    types::Mode,
+    proof_store::ProofStore,
+    prover::{
+        backend::{PreparedBlock, ProvingBackend},
+        cpu_prover::CpuProver,
+        cpu_witness::CpuWitnessGenerator,
+        diff_exec::diff_exec,
+        gpu_prover::Prover,
+        mock_prover::MockProver,
+        profiler::{CycleProfiler, Symbols},
+        types::EthBlockInput,
+    },
+    tasks::{CalculationUpdate, stale_blocks::StalePolicy},
+    types::{
+        BlockSelection, EthProofsSubmission, Mode, OnFailure, RpcEndpointConfig, StageLimits,
+        WitnessSink,
+    },
+    verifier::ProofVerifier,
 };
 
 pub mod config;
@@ -18,10 +42,24 @@ pub(crate) mod cache;
 pub(crate) mod clients;
 pub mod metrics;
 pub(crate) mod observability;
+pub mod proof_store;
 pub mod prover;
 pub(crate) mod tasks;
 pub(crate) mod types;
 pub(crate) mod utils;
+pub mod verifier;
+pub mod witness_file;
+
+/// Root directory for cached blocks and stored proofs.
+const CACHE_ROOT: &str = ".cache";
+/// Default name of the ELF with the symbols of `app.bin`, expected next to it.
+const PROFILER_ELF_NAME: &str = "app.elf";
+/// How to produce the ELF for the cycle profiler; it's not shipped with the artifacts.
+const PROFILER_ELF_HINT: &str = "The ELF isn't shipped with the artifacts, as the profiler needs a build with frame pointers: \
+     add `\"-C\", \"force-frame-pointers=yes\"` to the rustflags of the RISC-V target in \
+     `zksync-os/zksync_os/.cargo/config.toml`, run `./dump_bin.sh --type pectra` in `zksync-os/zksync_os`, \
+     copy the ELF it was built from (`zksync-os/zksync_os/target/<target>/<profile>/zksync_os`) to this path \
+     or set `profiler_elf_path`, and use the `app.bin` of the same build as `app_bin_path`";
 
 #[derive(Debug, Default)]
 pub struct Runner {}
@@ -32,30 +70,101 @@ impl Runner {
     }
 
     pub async fn run(self, cli: Cli, config: EthProverConfig) -> anyhow::Result<()> {
-        let mut join_set = tokio::task::JoinSet::new();
+        if let Command::Verify {
+            proof,
+            setup,
+            layouts,
+            expected_block_hash,
+        } = &cli.command
+        {
+            let setup = setup.clone().unwrap_or_else(|| {
+                config
+                    .app_bin_path
+                    .with_file_name(verifier::SETUP_FILE_NAME)
+            });
+            let layouts = layouts.clone().unwrap_or_else(|| {
+                config
+                    .app_bin_path
+                    .with_file_name(verifier::LAYOUTS_FILE_NAME)
+            });
+            let proof = proof.clone();
+            let expected_block_hash = *expected_block_hash;
+            return tokio::task::spawn_blocking(move || {
+                verifier::verify_proof_file(&proof, &setup, &layouts, expected_block_hash)
+            })
+            .await
+            .context("verification task panicked")?;
+        }
+
+        if matches!(config.mode, Mode::MockProve)
+            && matches!(config.ethproofs_submission, EthProofsSubmission::Prod)
+        {
+            anyhow::bail!(
+                "mock proofs can't be submitted to the production EthProofs instance, use `staging` or `off` `ethproofs_submission` in mock_prove mode"
+            );
+        }
+
+        let mut join_set = JoinSet::new();
 
-        let cache_storage = CacheStorage::new(".cache").context("failed to initialize cache")?;
-        let rpc_url = config
+        let cache_storage = CacheStorage::new(CACHE_ROOT).context("failed to initialize cache")?;
+        let proof_store = ProofStore::new(Path::new(CACHE_ROOT).join("proofs"))
+            .context("failed to initialize proof store")?;
+        let app_bin_hash =
+            proof_store::app_bin_hash(&config.app_bin_path).context("failed to hash app binary")?;
+        // `rpc_url` is the preferred endpoint and serves all roles.
+        let rpc_endpoints = config
             .rpc_url
-            .clone()
-            .map(|u| u.expose_secret().to_string())
-            .map(|u| u.parse::<Url>().context("invalid RPC URL"))
-            .transpose()?;
+            .iter()
+            .map(|url| RpcEndpointConfig {
+                url: url.clone(),
+                roles: RpcEndpointConfig::all_roles(),
+            })
+            .chain(config.rpc_endpoints.iter().cloned())
+            .collect::<Vec<_>>();
+        let rpc = if rpc_endpoints.is_empty() {
+            anyhow::ensure!(
+                !config.rpc_cross_check,
+                "`rpc_cross_check` requires RPC endpoints to be configured"
+            );
+            None
+        } else {
+            Some(
+                RpcPool::connect(&rpc_endpoints, config.rpc_cross_check)
+                    .await
+                    .context("failed to set up RPC endpoints")?,
+            )
+        };
+
+        let fetch_limits = StageLimits::new(config.fetch_concurrency, config.fetch_queue_size)
+            .context("invalid fetch stage limits")?;
+        let prepare_limits =
+            StageLimits::new(config.prepare_concurrency, config.prepare_queue_size)
+                .context("invalid prepare stage limits")?;
 
-        let (block_stream_receiver, should_create_cache_manager) = match cli.command {
+        let mut range_blocks = None;
+        let mut checkpoint = None;
+        let (block_stream_receiver, should_create_cache_manager) = match &cli.command {
             Command::Run => {
-                let Some(rpc_url) = rpc_url.clone() else {
+                let Some(rpc) = rpc.clone() else {
                     anyhow::bail!("RPC URL is required for continuous mode");
                 };
 
                 // Create and run continuous block stream
                 let (stream, receiver) = tasks::block_stream::ContinuousBlockStream::new(
-                    rpc_url,
-                    config.prover_id,
-                    config.block_mod,
+                    rpc,
                     cache_storage.clone(),
-                    config.cache_policy,
+                    tasks::block_stream::ContinuousStreamConfig {
+                        prover_id: config.prover_id,
+                        block_mod: config.block_mod,
+                        block_selection: config.block_selection,
+                        max_catch_up_lag: config.max_catch_up_lag,
+                        confirmations: config.confirmations,
+                        fetch_limits,
+                        witness_source: config.witness_source,
+                        cache_policy: config.cache_policy,
+                    },
                 );
+                checkpoint = stream.checkpoint_tracker();
                 join_set.spawn(observability::bind_task(
                     "continuous_block_stream",
                     stream.run(),
@@ -64,9 +173,10 @@ impl Runner {
             }
             Command::Block { block_number } => {
                 let (stream, receiver) = tasks::block_stream::SingleBlockStream::new(
-                    block_number,
-                    rpc_url.clone(),
+                    *block_number,
+                    rpc.clone(),
                     cache_storage.clone(),
+                    config.witness_source,
                     config.cache_policy,
                 );
                 // Single block mode is used for debugging, so we don't want to remove cache artifacts
@@ -76,20 +186,195 @@ impl Runner {
                 ));
                 (receiver, false)
             }
+            Command::Range {
+                from,
+                to,
+                step,
+                blocks_file,
+            } => {
+                let blocks = match (blocks_file, from, to) {
+                    (Some(blocks_file), _, _) => {
+                        let contents = std::fs::read_to_string(blocks_file).with_context(|| {
+                            format!("failed to read blocks file {}", blocks_file.display())
+                        })?;
+                        tasks::block_stream::parse_blocks_file(&contents)?
+                    }
+                    (None, Some(from), Some(to)) => {
+                        tasks::block_stream::range_blocks(*from, *to, *step)?
+                    }
+                    _ => anyhow::bail!("either --from and --to or --blocks-file must be provided"),
+                };
+                tracing::info!(
+                    "Processing {} blocks from {} to {}",
+                    blocks.len(),
+                    blocks[0],
+                    blocks[blocks.len() - 1]
+                );
+                range_blocks = Some(blocks.clone());
+
+                let (stream, receiver) = tasks::block_stream::RangeBlockStream::new(
+                    blocks,
+                    fetch_limits,
+                    rpc.clone(),
+                    cache_storage.clone(),
+                    config.witness_source,
+                    config.cache_policy,
+                );
+                join_set.spawn(observability::bind_task("range_block_stream", stream.run()));
+                (receiver, true)
+            }
+            Command::DiffExec { block_number } => {
+                let (stream, mut receiver) = tasks::block_stream::SingleBlockStream::new(
+                    *block_number,
+                    rpc.clone(),
+                    cache_storage.clone(),
+                    config.witness_source,
+                    config.cache_policy,
+                );
+                stream.run().await?;
+                let input = receiver
+                    .recv()
+                    .await
+                    .context("block stream didn't provide the block")?;
+                return run_diff_exec(input, config.app_bin_path, &cache_storage).await;
+            }
+            Command::Verify { .. } => {
+                unreachable!("`verify` command is handled before the pipeline is created")
+            }
+        };
+
+        // Backfills always continue past failed blocks, which are then listed in the range summary.
+        let on_failure = if range_blocks.is_some() {
+            OnFailure::Continue
+        } else {
+            config.on_failure
+        };
+
+        // Only blocks of the continuous mode are proven against the clock; backfills and debugged
+        // blocks are never stale.
+        let stale_policy = match (config.latency_target_secs, &cli.command) {
+            (Some(_), Command::Run) if config.block_selection == BlockSelection::CatchUp => {
+                // The backlog is older than any sensible target, so every block would be dropped.
+                anyhow::bail!(
+                    "`latency_target_secs` can't be used with `catch_up` block selection, which processes every block"
+                );
+            }
+            (Some(latency_target_secs), Command::Run) => Some(Arc::new(StalePolicy::new(
+                Duration::from_secs(latency_target_secs),
+            ))),
+            (Some(_), _) => {
+                tracing::warn!(
+                    "Stale blocks are only dropped in continuous mode, ignoring `latency_target_secs`"
+                );
+                None
+            }
+            (None, _) => None,
+        };
+
+        let proof_verifier = match (config.verify_proofs, config.mode) {
+            (true, Mode::GpuProve | Mode::CpuProve) => Some(
+                ProofVerifier::load(
+                    &config
+                        .app_bin_path
+                        .with_file_name(verifier::SETUP_FILE_NAME),
+                    &config
+                        .app_bin_path
+                        .with_file_name(verifier::LAYOUTS_FILE_NAME),
+                )
+                .context("failed to load proof verifier")?,
+            ),
+            (true, _) => {
+                tracing::warn!(
+                    "Proof verification is only supported in gpu_prove and cpu_prove modes, ignoring `verify_proofs`"
+                );
+                None
+            }
+            (false, _) => None,
         };
 
+        if !config.gpu_device_groups.is_empty() && !matches!(config.mode, Mode::GpuProve) {
+            tracing::warn!(
+                "GPU device groups are only used in gpu_prove mode, ignoring `gpu_device_groups`"
+            );
+        }
+
+        let (mut preparation, prepared_receiver) = tasks::preparation::PreparationTask::new(
+            block_stream_receiver,
+            prepare_limits,
+            on_failure,
+            config.witness_validation,
+        );
+        if let Some(stale_policy) = &stale_policy {
+            preparation = preparation.with_stale_policy(stale_policy.clone());
+        }
+        join_set.spawn(observability::bind_task("preparation", preparation.run()));
+
         let mut mode_command_receiver = match config.mode {
             Mode::CpuWitness => {
-                let cpu_witness_generator = CpuWitnessGenerator::new(config.app_bin_path);
-                let (task, command_receiver) = tasks::cpu_witness::CpuWitnessTask::new(
+                let profiler = if config.profile_cycles {
+                    let elf_path = config
+                        .profiler_elf_path
+                        .clone()
+                        .unwrap_or_else(|| config.app_bin_path.with_file_name(PROFILER_ELF_NAME));
+                    anyhow::ensure!(
+                        elf_path.exists(),
+                        "cycle profiler needs the ELF `app.bin` was built from, but {} doesn't exist. {PROFILER_ELF_HINT}",
+                        elf_path.display()
+                    );
+                    let symbols = Symbols::load(&elf_path)
+                        .context("failed to load symbols for the cycle profiler")?;
+                    Some(CycleProfiler::new(
+                        config.app_bin_path.clone(),
+                        symbols,
+                        config.profiler_sample_interval,
+                    ))
+                } else {
+                    None
+                };
+                let mut cpu_witness_generator = CpuWitnessGenerator::new(config.app_bin_path)
+                    .with_cycle_limit(config.cpu_cycle_limit)
+                    .with_debugger(
+                        rpc.clone(),
+                        cache_storage.clone(),
+                        config.debug_trace_transactions,
+                    );
+                if let Some(profiler) = profiler {
+                    cpu_witness_generator =
+                        cpu_witness_generator.with_profiler(profiler, cache_storage.clone());
+                }
+                spawn_proving_task(
+                    &mut join_set,
                     cpu_witness_generator,
-                    block_stream_receiver,
-                    config.on_failure,
-                    rpc_url.clone(),
-                    cache_storage.clone(),
-                );
-                join_set.spawn(observability::bind_task("cpu_witness", task.run()));
-                command_receiver
+                    prepared_receiver,
+                    on_failure,
+                    stale_policy,
+                )
+            }
+            Mode::GpuProve if !config.gpu_device_groups.is_empty() => {
+                let groups = tasks::device_groups::device_groups(&config.gpu_device_groups)
+                    .context("invalid `gpu_device_groups`")?;
+                let mut provers = Vec::with_capacity(groups.len());
+                for group in groups {
+                    tracing::info!("Creating GPU prover for device group {}", group.label);
+                    let app_bin_path = config.app_bin_path.clone();
+                    let devices = group.devices.clone();
+                    let gpu_prover = observability::spawn_blocking_on_current_hub(move || {
+                        Prover::for_devices(app_bin_path.as_path(), None, devices)
+                            .context("failed to create prover")
+                    })
+                    .await
+                    .context("prover creation task panicked")??;
+                    tracing::info!("GPU prover for device group {} created", group.label);
+                    provers.push((group, gpu_prover));
+                }
+
+                tasks::device_groups::spawn_device_group_tasks(
+                    &mut join_set,
+                    provers,
+                    prepared_receiver,
+                    on_failure,
+                    stale_policy,
+                )
             }
             Mode::GpuProve => {
                 // TODO: support worker threads? Though it's likely not needed anytime soon.
@@ -102,23 +387,82 @@ impl Runner {
                 .context("prover creation task panicked")??;
                 tracing::info!("GPU prover created");
 
-                let (task, command_receiver) = tasks::gpu_prove::GpuProveTask::new(
+                spawn_proving_task(
+                    &mut join_set,
                     gpu_prover,
-                    block_stream_receiver,
-                    config.on_failure,
+                    prepared_receiver,
+                    on_failure,
+                    stale_policy,
+                )
+            }
+            Mode::CpuProve => {
+                tracing::info!("Creating CPU prover");
+                let app_bin_path = config.app_bin_path.clone();
+                let cpu_prover = observability::spawn_blocking_on_current_hub(move || {
+                    CpuProver::new(app_bin_path.as_path(), None)
+                        .context("failed to create CPU prover")
+                })
+                .await
+                .context("CPU prover creation task panicked")??;
+                tracing::info!("CPU prover created");
+
+                spawn_proving_task(
+                    &mut join_set,
+                    cpu_prover,
+                    prepared_receiver,
+                    on_failure,
+                    stale_policy,
+                )
+            }
+            Mode::MockProve => {
+                let mock_prover = MockProver::new(
+                    config.app_bin_path,
+                    Duration::from_millis(config.mock_prover_latency_ms),
                 );
-                join_set.spawn(observability::bind_task("gpu_prove", task.run()));
-                command_receiver
+                spawn_proving_task(
+                    &mut join_set,
+                    mock_prover,
+                    prepared_receiver,
+                    on_failure,
+                    stale_policy,
+                )
             }
         };
 
+        if let Some(verifier) = proof_verifier {
+            let (task, new_command_receiver) =
+                tasks::proof_verification::ProofVerificationTask::new(
+                    mode_command_receiver,
+                    verifier,
+                    cache_storage.clone(),
+                    on_failure,
+                );
+            mode_command_receiver = new_command_receiver;
+            join_set.spawn(observability::bind_task("proof_verification", task.run()));
+        }
+
+        // Reorgs are only relevant when following the chain head.
+        if matches!(cli.command, Command::Run)
+            && let Some(rpc) = &rpc
+        {
+            let (task, new_command_receiver) =
+                tasks::reorg_monitor::ReorgMonitorTask::new(mode_command_receiver, rpc.clone());
+            mode_command_receiver = new_command_receiver;
+            join_set.spawn(observability::bind_task("reorg_monitor", task.run()));
+        }
+
         if should_create_cache_manager {
             let (cache_manager_task, new_command_receiver) = {
-                let (task, mode_command_receiver) = tasks::cache_manager::CacheManagerTask::new(
+                let (mut task, mode_command_receiver) = tasks::cache_manager::CacheManagerTask::new(
                     mode_command_receiver,
-                    cache_storage,
+                    cache_storage.clone(),
                     config.cache_policy,
                 );
+                // Results reach the cache manager once they are verified, so it also tracks
+                // which blocks are done.
+                if let Some(checkpoint) = checkpoint {
+                    task = task.with_checkpoint(checkpoint);
+                }
                 (task, mode_command_receiver)
             };
             mode_command_receiver = new_command_receiver;
@@ -128,6 +472,46 @@ impl Runner {
             ));
         }
 
+        // Comes after the cache manager, so that the witness outlives the removed block inputs.
+        match (config.witness_sink, config.mode) {
+            (WitnessSink::Off, _) => {}
+            (WitnessSink::Cache, Mode::CpuWitness) => {
+                let (task, new_command_receiver) = tasks::witness_sink::WitnessSinkTask::new(
+                    mode_command_receiver,
+                    cache_storage,
+                    app_bin_hash,
+                );
+                mode_command_receiver = new_command_receiver;
+                join_set.spawn(observability::bind_task("witness_sink", task.run()));
+            }
+            (WitnessSink::Cache, _) => {
+                tracing::warn!(
+                    "Witnesses are only generated in cpu_witness mode, ignoring `witness_sink`"
+                );
+            }
+        }
+
+        let (proof_store_task, new_command_receiver) = tasks::proof_store::ProofStoreTask::new(
+            mode_command_receiver,
+            proof_store,
+            app_bin_hash,
+        );
+        mode_command_receiver = new_command_receiver;
+        join_set.spawn(observability::bind_task(
+            "proof_store",
+            proof_store_task.run(),
+        ));
+
+        let range_summary_receiver = if let Some(blocks) = range_blocks {
+            let (task, new_command_receiver, summary_receiver) =
+                tasks::range_summary::RangeSummaryTask::new(mode_command_receiver, blocks);
+            mode_command_receiver = new_command_receiver;
+            join_set.spawn(observability::bind_task("range_summary", task.run()));
+            Some(summary_receiver)
+        } else {
+            None
+        };
+
         if config.ethproofs_submission.enabled() {
             let Some(token) = config.ethproofs_token.clone() else {
                 anyhow::bail!("EthProofs submission token is required when submission is enabled");
@@ -142,9 +526,12 @@ impl Runner {
                 token.expose_secret().to_string(),
                 cluster_id,
             );
+            let outbox = Outbox::open(Path::new(CACHE_ROOT).join("ethproofs_outbox"))
+                .context("failed to open EthProofs outbox")?;
             let task = tasks::eth_proofs_upload::EthProofsUploadTask::new(
                 ethproofs_client,
                 mode_command_receiver,
+                outbox,
             );
             join_set.spawn(observability::bind_task("ethproofs_upload", task.run()));
         } else {
@@ -177,6 +564,210 @@ impl Runner {
             }
         }
 
+        if let Some(mut summary_receiver) = range_summary_receiver
+            && let Ok(summary) = summary_receiver.try_recv()
+        {
+            anyhow::ensure!(
+                summary.failed.is_empty(),
+                "{} of {} blocks in the range failed: {:?}",
+                summary.failed.len(),
+                summary.failed.len() + summary.succeeded.len(),
+                summary.failed
+            );
+        }
+
         Ok(())
     }
 }
+
+/// Executes a block with both the STF and revm, and stores the comparison in the cache.
+async fn run_diff_exec(
+    input: EthBlockInput,
+    app_bin_path: PathBuf,
+    cache_storage: &CacheStorage,
+) -> anyhow::Result<()> {
+    let block_number = input.block_header.number;
+    tracing::info!("Executing block {block_number} with the STF and revm");
+    let report = diff_exec(&CpuWitnessGenerator::new(app_bin_path), input).await?;
+    for divergence in &report.divergences {
+        tracing::error!("STF and revm diverge in block {block_number}: {divergence}");
+    }
+    let path = cache_storage
+        .save_diff_exec_report(&report)
+        .with_context(|| format!("failed to save diff-exec report for block {block_number}"))?;
+    tracing::info!(
+        "Diff-exec report for block {block_number} saved to {}",
+        path.display()
+    );
+    anyhow::ensure!(report.is_clean(), "differential execution of {report}");
+    tracing::info!("Differential execution of {report}");
+    Ok(())
+}
+
+/// Spawns the task driving `backend` and returns the receiver of its pipeline updates.
+fn spawn_proving_task<B>(
+    join_set: &mut JoinSet<anyhow::Result<()>>,
+    backend: B,
+    block_receiver: Receiver<PreparedBlock>,
+    on_failure: OnFailure,
+    stale_policy: Option<Arc<StalePolicy>>,
+) -> Receiver<CalculationUpdate>
+where
+    B: ProvingBackend + 'static,
+{
+    let task_name = backend.name();
+    let (mut task, command_receiver) =
+        tasks::proving::ProvingTask::new(backend, block_receiver, on_failure);
+    if let Some(stale_policy) = stale_policy {
+        task = task.with_stale_policy(stale_policy);
+    }
+    join_set.spawn(observability::bind_task(task_name, task.run()));
+    command_receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use tokio::{sync::mpsc::channel, task::JoinSet};
+
+    use super::{Runner, spawn_proving_task};
+    use crate::{
+        cache::CacheStorage,
+        clients::ethproofs::{EthProofsApi, outbox::Outbox},
+        config::{Cli, Command, EthProverConfig},
+        prover::{backend::PreparedBlock, mock_prover::MockProver, types::EthBlockInput},
+        tasks::{eth_proofs_upload::EthProofsUploadTask, preparation::PreparationTask},
+        types::{EthProofsSubmission, Mode, OnFailure, StageLimits, WitnessValidation},
+    };
+
+    /// Records the updates submitted to EthProofs.
+    #[derive(Debug, Clone, Default)]
+    struct StubEthProofs {
+        updates: Arc<Mutex<Vec<(u64, &'static str)>>>,
+    }
+
+    impl StubEthProofs {
+        fn record(&self, block_number: u64, status: &'static str) -> anyhow::Result<()> {
+            self.updates.lock().unwrap().push((block_number, status));
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl EthProofsApi for StubEthProofs {
+        async fn queue_proof(&self, block_number: u64) -> anyhow::Result<()> {
+            self.record(block_number, "queued")
+        }
+
+        async fn proving_proof(&self, block_number: u64) -> anyhow::Result<()> {
+            self.record(block_number, "proving")
+        }
+
+        async fn send_proof(
+            &self,
+            block_number: u64,
+            _proof_bytes: &[u8],
+            _proving_time_secs: f64,
+            _cycles: u64,
+        ) -> anyhow::Result<()> {
+            self.record(block_number, "proved")
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_proofs_are_not_submitted_to_production() {
+        let config = EthProverConfig {
+            mode: Mode::MockProve,
+            ethproofs_submission: EthProofsSubmission::Prod,
+            ..Default::default()
+        };
+        let cli = Cli {
+            config: None,
+            command: Command::Run,
+        };
+        let err = Runner::new().run(cli, config).await.unwrap_err();
+        assert!(err.to_string().contains("production EthProofs"), "{err}");
+    }
+
+    const FIXTURE_BLOCK: u64 = 24_073_997;
+
+    fn fixture_input() -> EthBlockInput {
+        // Fixture blocks are stored in the same layout as the block cache.
+        let fixtures = CacheStorage::new(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_fixtures"),
+        )
+        .expect("open fixtures");
+        let (block, witness) = fixtures
+            .load_block(FIXTURE_BLOCK)
+            .expect("load fixture block")
+            .expect("fixture block exists");
+        EthBlockInput::new(block, witness)
+    }
+
+    #[tokio::test]
+    async fn pipeline_reports_status_transitions_to_ethproofs() {
+        let outbox_dir = tempfile::tempdir().expect("create tempdir");
+        let (block_sender, block_receiver) = channel(10);
+        let (preparation, prepared_receiver) = PreparationTask::new(
+            block_receiver,
+            StageLimits::SEQUENTIAL,
+            OnFailure::Continue,
+            WitnessValidation::Off,
+        );
+        let preparation = preparation.with_prepare(|input| {
+            if input.block_header.number != FIXTURE_BLOCK {
+                return PreparedBlock::failed(input, anyhow::anyhow!("stub preparation failure"));
+            }
+            PreparedBlock::prepare(input)
+        });
+        let mut join_set = JoinSet::new();
+        join_set.spawn(preparation.run());
+        let app_bin_path =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../artifacts/app.bin");
+        let updates = spawn_proving_task(
+            &mut join_set,
+            MockProver::new(app_bin_path, Duration::ZERO),
+            prepared_receiver,
+            OnFailure::Continue,
+            None,
+        );
+        let ethproofs = StubEthProofs::default();
+        let outbox = Outbox::open(outbox_dir.path()).expect("open outbox");
+        join_set.spawn(EthProofsUploadTask::new(ethproofs.clone(), updates, outbox).run());
+
+        let input = fixture_input();
+        // Fails to be prepared.
+        let unprepared = EthBlockInput::with_header(
+            alloy::consensus::Header {
+                number: 1,
+                ..Default::default()
+            },
+            Default::default(),
+        );
+        // The forward run doesn't match the header, so the block fails to be proven.
+        let mut diverging = input.clone();
+        diverging.block_header.gas_used += 1;
+        for block in [input, unprepared, diverging] {
+            block_sender.send(block).await.expect("send block");
+        }
+        drop(block_sender);
+        while let Some(result) = join_set.join_next().await {
+            result.expect("task").expect("task ok");
+        }
+
+        // Blocks that fail to be prepared are never queued, and failed proofs are never submitted.
+        assert_eq!(
+            *ethproofs.updates.lock().unwrap(),
+            [
+                (FIXTURE_BLOCK, "queued"),
+                (FIXTURE_BLOCK, "proving"),
+                (FIXTURE_BLOCK, "proved"),
+                (FIXTURE_BLOCK, "queued"),
+                (FIXTURE_BLOCK, "proving"),
+            ]
+        );
+    }
+}
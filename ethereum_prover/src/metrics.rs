@@ -2,7 +2,7 @@ use std::net::Ipv4Addr;
 use std::time::Duration;
 
 use anyhow::Context as _;
-use vise::{Buckets, Counter, Gauge, Histogram, Metrics, MetricsCollection, Unit};
+use vise::{Buckets, Counter, Gauge, Histogram, LabeledFamily, Metrics, MetricsCollection, Unit};
 use vise_exporter::MetricsExporter;
 
 #[derive(Debug, Metrics)]
@@ -14,16 +14,56 @@ pub struct ProverMetrics {
     #[metrics(buckets = Buckets::LATENCIES, unit = Unit::Seconds)]
     pub witness_duration: Histogram<Duration>,
     pub inflight_witness_tasks: Gauge<u64>,
+    /// Number of RISC-V cycles it took to execute a block in CPU-witness mode.
+    #[metrics(buckets = Buckets::exponential(1_000_000.0..=1_000_000_000_000.0, 2.0))]
+    pub witness_cycles: Histogram<u64>,
+    /// Number of RISC-V cycles per unit of gas used by a block in CPU-witness mode.
+    #[metrics(buckets = Buckets::exponential(1.0..=65_536.0, 2.0))]
+    pub witness_cycles_per_gas: Histogram<f64>,
     pub proof_success_total: Counter<u64>,
     pub proof_failure_total: Counter<u64>,
     #[metrics(buckets = Buckets::LATENCIES, unit = Unit::Seconds)]
     pub proof_duration: Histogram<Duration>,
     pub inflight_proof_tasks: Gauge<u64>,
+    /// Number of blocks successfully processed by each GPU device group.
+    #[metrics(labels = ["group"])]
+    pub device_group_success_total: LabeledFamily<String, Counter<u64>>,
+    /// Number of blocks that failed on each GPU device group.
+    #[metrics(labels = ["group"])]
+    pub device_group_failure_total: LabeledFamily<String, Counter<u64>>,
+    #[metrics(labels = ["group"], buckets = Buckets::LATENCIES, unit = Unit::Seconds)]
+    pub device_group_duration: LabeledFamily<String, Histogram<Duration>>,
+    /// Whether a GPU device group is busy with a block (`1`) or waiting for one (`0`).
+    #[metrics(labels = ["group"])]
+    pub device_group_busy: LabeledFamily<String, Gauge<u64>>,
+    /// Number of generated proofs that failed self-verification and were withheld.
+    pub proof_verification_failure_total: Counter<u64>,
     pub last_processed_block: Gauge<u64>,
+    /// Number of divergences from the canonical receipts found by the transaction debugger.
+    pub debug_divergences_total: Counter<u64>,
+    /// Number of matching blocks skipped by the continuous block stream.
+    pub skipped_blocks_total: Counter<u64>,
+    /// Number of blocks dropped because they could no longer be proven within the latency target,
+    /// by the pipeline stage that dropped them (`prepare` or `prove`).
+    #[metrics(labels = ["stage"])]
+    pub stale_blocks_total: LabeledFamily<&'static str, Counter<u64>>,
+    /// Number of blocks that failed to be prepared for proving (witness validation or oracle build).
+    pub preparation_failure_total: Counter<u64>,
+    #[metrics(buckets = Buckets::LATENCIES, unit = Unit::Seconds)]
+    pub preparation_duration: Histogram<Duration>,
+    pub inflight_preparations: Gauge<u64>,
+    /// Number of proven blocks that were reorged out of the canonical chain.
+    pub reorged_proofs_total: Counter<u64>,
+    /// Number of failed requests to RPC endpoints, each of which triggers a failover.
+    pub rpc_endpoint_failure_total: Counter<u64>,
     pub ethproofs_request_success_total: Counter<u64>,
     pub ethproofs_request_failure_total: Counter<u64>,
     #[metrics(buckets = Buckets::LATENCIES, unit = Unit::Seconds)]
     pub ethproofs_request_duration: Histogram<Duration>,
+    /// Number of EthProofs updates waiting in the outbox to be retried.
+    pub ethproofs_outbox_backlog: Gauge<u64>,
+    /// Number of EthProofs updates dropped because EthProofs rejected them with a non-retryable status.
+    pub ethproofs_rejected_updates_total: Counter<u64>,
 }
 
 #[vise::register]
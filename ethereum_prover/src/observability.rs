@@ -46,6 +46,34 @@ pub(crate) fn capture_anyhow(error: &Error) {
     });
 }
 
+pub(crate) fn capture_warning(message: &str) {
+    Hub::with_active(|hub| {
+        hub.capture_message(message, sentry::Level::Warning);
+    });
+}
+
+/// Attaches a JSON document to all events subsequently captured on the current hub.
+/// Inside [`bind_block`], this limits the attachment to the events of the current block.
+pub(crate) fn attach_json(filename: &str, value: &impl serde::Serialize) {
+    Hub::with_active(|hub| {
+        let buffer = match serde_json::to_vec_pretty(value) {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                tracing::warn!("Failed to serialize Sentry attachment {filename}: {err}");
+                return;
+            }
+        };
+        hub.configure_scope(|scope| {
+            scope.add_attachment(sentry::protocol::Attachment {
+                buffer,
+                filename: filename.to_owned(),
+                content_type: Some("application/json".to_owned()),
+                ty: None,
+            });
+        });
+    });
+}
+
 fn bind_hub<F>(hub: Arc<Hub>, future: F) -> impl Future<Output = F::Output>
 where
     F: Future,
@@ -0,0 +1,211 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alloy::primitives::{B256, keccak256};
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+
+use crate::prover::gpu_prover::ProofResult;
+
+/// Version of the prover that produced the stored proofs.
+const PROVER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Information about a stored proof, persisted next to the proof itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProofMetadata {
+    pub block_number: u64,
+    pub cycles: u64,
+    pub proving_time_secs: f64,
+    /// Keccak256 hash of the `app.bin` the proof was generated for.
+    pub app_bin_hash: B256,
+    pub prover_version: String,
+    /// Unix timestamp (in seconds) of the moment the proof was stored.
+    pub created_at: u64,
+    /// Set if the proven block was reorged out of the canonical chain.
+    #[serde(default)]
+    pub orphaned: bool,
+}
+
+/// On-disk storage for generated proofs.
+///
+/// Each proof is stored in its own directory: `<root>/<block_number>/proof.bin` holds the
+/// raw proof bytes and `<root>/<block_number>/metadata.json` holds [`ProofMetadata`].
+/// Metadata is written last, so a proof is only considered stored once metadata exists.
+#[derive(Debug, Clone)]
+pub struct ProofStore {
+    root: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+struct ProofPaths {
+    dir: PathBuf,
+    proof_bin: PathBuf,
+    metadata_json: PathBuf,
+}
+
+impl ProofStore {
+    pub fn new(root: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let this = Self { root: root.into() };
+        std::fs::create_dir_all(&this.root)?;
+        Ok(this)
+    }
+
+    pub fn save(
+        &self,
+        block_number: u64,
+        proof_result: &ProofResult,
+        app_bin_hash: B256,
+    ) -> anyhow::Result<ProofMetadata> {
+        let paths = self.proof_paths(block_number);
+        std::fs::create_dir_all(&paths.dir)?;
+        // Remove stale metadata first, so that a partially overwritten proof is never
+        // considered complete.
+        if paths.metadata_json.exists() {
+            std::fs::remove_file(&paths.metadata_json)?;
+        }
+
+        let metadata = ProofMetadata {
+            block_number,
+            cycles: proof_result.cycles,
+            proving_time_secs: proof_result.proving_time_secs,
+            app_bin_hash,
+            prover_version: PROVER_VERSION.to_string(),
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .context("system time is before the Unix epoch")?
+                .as_secs(),
+            orphaned: false,
+        };
+        std::fs::write(&paths.proof_bin, &proof_result.proof_bytes)?;
+        let data = serde_json::to_string_pretty(&metadata)?;
+        std::fs::write(&paths.metadata_json, data)?;
+        Ok(metadata)
+    }
+
+    pub fn load(&self, block_number: u64) -> anyhow::Result<Option<(ProofResult, ProofMetadata)>> {
+        let Some(metadata) = self.load_metadata(block_number)? else {
+            return Ok(None);
+        };
+        let paths = self.proof_paths(block_number);
+        let proof_bytes = std::fs::read(&paths.proof_bin)
+            .with_context(|| format!("failed to read stored proof for block {block_number}"))?;
+        let proof_result = ProofResult {
+            proof_bytes,
+            cycles: metadata.cycles,
+            proving_time_secs: metadata.proving_time_secs,
+        };
+        Ok(Some((proof_result, metadata)))
+    }
+
+    pub fn load_metadata(&self, block_number: u64) -> anyhow::Result<Option<ProofMetadata>> {
+        let paths = self.proof_paths(block_number);
+        if !paths.metadata_json.exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read_to_string(paths.metadata_json)?;
+        let metadata = serde_json::from_str(&data)?;
+        Ok(Some(metadata))
+    }
+
+    /// Flags a stored proof as belonging to a block that was reorged out.
+    /// Returns `false` if there's no stored proof for the block.
+    pub fn mark_orphaned(&self, block_number: u64) -> anyhow::Result<bool> {
+        let Some(mut metadata) = self.load_metadata(block_number)? else {
+            return Ok(false);
+        };
+        metadata.orphaned = true;
+        let data = serde_json::to_string_pretty(&metadata)?;
+        std::fs::write(self.proof_paths(block_number).metadata_json, data)?;
+        Ok(true)
+    }
+
+    /// Returns block numbers of all stored proofs in ascending order.
+    pub fn list(&self) -> anyhow::Result<Vec<u64>> {
+        let mut block_numbers = Vec::new();
+        for entry in std::fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let Some(block_number) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            if self.proof_paths(block_number).metadata_json.exists() {
+                block_numbers.push(block_number);
+            }
+        }
+        block_numbers.sort_unstable();
+        Ok(block_numbers)
+    }
+
+    pub fn delete(&self, block_number: u64) -> anyhow::Result<()> {
+        let paths = self.proof_paths(block_number);
+        if paths.dir.exists() {
+            std::fs::remove_dir_all(paths.dir)?;
+        }
+        Ok(())
+    }
+
+    fn proof_paths(&self, block_number: u64) -> ProofPaths {
+        let dir = self.root.join(block_number.to_string());
+        ProofPaths {
+            dir: dir.clone(),
+            proof_bin: dir.join("proof.bin"),
+            metadata_json: dir.join("metadata.json"),
+        }
+    }
+}
+
+/// Computes the hash identifying the RISC-V binary used for proving.
+pub fn app_bin_hash(app_bin_path: &Path) -> anyhow::Result<B256> {
+    let app_bin = std::fs::read(app_bin_path)
+        .with_context(|| format!("failed to read app binary at {}", app_bin_path.display()))?;
+    Ok(keccak256(app_bin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProofStore;
+    use crate::prover::gpu_prover::ProofResult;
+    use alloy::primitives::B256;
+    use tempfile::tempdir;
+
+    #[test]
+    fn proof_store_roundtrips_proofs() {
+        let dir = tempdir().expect("create tempdir");
+        let store = ProofStore::new(dir.path()).expect("create store");
+        let app_bin_hash = B256::repeat_byte(0xab);
+
+        for block_number in [20_u64, 10] {
+            let proof_result = ProofResult {
+                proof_bytes: vec![block_number as u8; 16],
+                cycles: block_number * 1000,
+                proving_time_secs: 1.5,
+            };
+            store
+                .save(block_number, &proof_result, app_bin_hash)
+                .expect("save proof");
+        }
+        assert_eq!(store.list().expect("list proofs"), vec![10, 20]);
+
+        let (proof_result, metadata) = store.load(20).expect("load proof").expect("proof exists");
+        assert_eq!(proof_result.proof_bytes, vec![20_u8; 16]);
+        assert_eq!(proof_result.cycles, 20_000);
+        assert_eq!(metadata.block_number, 20);
+        assert_eq!(metadata.app_bin_hash, app_bin_hash);
+
+        assert!(!metadata.orphaned);
+        assert!(store.mark_orphaned(20).expect("mark orphaned"));
+        let metadata = store
+            .load_metadata(20)
+            .expect("load metadata")
+            .expect("proof exists");
+        assert!(metadata.orphaned);
+        assert!(!store.mark_orphaned(30).expect("mark orphaned"));
+
+        store.delete(20).expect("delete proof");
+        assert!(store.load(20).expect("load proof").is_none());
+        assert_eq!(store.list().expect("list proofs"), vec![10]);
+    }
+}
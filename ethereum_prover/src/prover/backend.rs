@@ -0,0 +1,146 @@
+use async_trait::async_trait;
+use oracle_provider::ZkEENonDeterminismSource;
+
+use crate::prover::{
+    cpu_witness::WitnessResult, gpu_prover::ProofResult, oracle::build_oracle, types::EthBlockInput,
+};
+
+/// Kind of artifact produced by a [`ProvingBackend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// Backend only generates a witness, no proof is produced.
+    Witness,
+    /// Backend produces a proof that can be submitted to EthProofs.
+    Proof,
+}
+
+impl BackendKind {
+    /// Human-readable name of the produced artifact, used in logs and error messages.
+    pub fn artifact(&self) -> &'static str {
+        match self {
+            BackendKind::Witness => "witness",
+            BackendKind::Proof => "proof",
+        }
+    }
+}
+
+/// Result of processing a single block by a [`ProvingBackend`].
+#[derive(Debug)]
+pub enum BackendOutput {
+    Witness(WitnessResult),
+    Proof(ProofResult),
+}
+
+/// Block handed to a [`ProvingBackend`], together with the oracle built from it by the
+/// preparation stage, so that proving doesn't wait for the oracle.
+pub struct PreparedBlock {
+    pub input: EthBlockInput,
+    oracle: Option<ZkEENonDeterminismSource>,
+    /// Set if the block failed preparation; such blocks are only passed on to be reported.
+    preparation_error: Option<anyhow::Error>,
+    /// Set if the block was dropped as stale; such blocks are only passed on to be reported.
+    dropped: bool,
+}
+
+impl std::fmt::Debug for PreparedBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PreparedBlock")
+            .field("block_number", &self.block_number())
+            .field("has_oracle", &self.oracle.is_some())
+            .field("preparation_error", &self.preparation_error)
+            .field("dropped", &self.dropped)
+            .finish()
+    }
+}
+
+impl PreparedBlock {
+    /// Block without a prepared oracle; it's built when the backend asks for it.
+    pub fn new(input: EthBlockInput) -> Self {
+        Self {
+            input,
+            oracle: None,
+            preparation_error: None,
+            dropped: false,
+        }
+    }
+
+    /// Block that failed preparation and must not be processed.
+    pub fn failed(input: EthBlockInput, err: anyhow::Error) -> Self {
+        Self {
+            input,
+            oracle: None,
+            preparation_error: Some(err),
+            dropped: false,
+        }
+    }
+
+    /// Block that was dropped as stale and must not be processed.
+    pub fn dropped(input: EthBlockInput) -> Self {
+        Self {
+            input,
+            oracle: None,
+            preparation_error: None,
+            dropped: true,
+        }
+    }
+
+    /// Builds the oracle for the block. This is CPU-heavy, so it's better done on a blocking thread.
+    /// If the oracle can't be built, the block is marked as failed.
+    pub fn prepare(input: EthBlockInput) -> Self {
+        let block_number = input.block_header.number;
+        match build_oracle(input.clone()) {
+            Ok(oracle) => Self {
+                input,
+                oracle: Some(oracle),
+                preparation_error: None,
+                dropped: false,
+            },
+            Err(err) => Self::failed(
+                input,
+                err.context(format!(
+                    "failed to build the oracle for block {block_number}"
+                )),
+            ),
+        }
+    }
+
+    pub fn block_number(&self) -> u64 {
+        self.input.block_header.number
+    }
+
+    pub fn preparation_error(&self) -> Option<&anyhow::Error> {
+        self.preparation_error.as_ref()
+    }
+
+    pub fn is_dropped(&self) -> bool {
+        self.dropped
+    }
+
+    /// Returns the input and its oracle, building the oracle if it wasn't prepared.
+    pub fn into_parts(self) -> anyhow::Result<(EthBlockInput, ZkEENonDeterminismSource)> {
+        if let Some(err) = self.preparation_error {
+            return Err(err);
+        }
+        let oracle = match self.oracle {
+            Some(oracle) => oracle,
+            None => build_oracle(self.input.clone())?,
+        };
+        Ok((self.input, oracle))
+    }
+}
+
+/// A way to process blocks in the pipeline.
+///
+/// Backends only know how to turn a block input into an artifact; retries, metrics,
+/// Sentry bindings and pipeline updates are handled by the task driving the backend.
+#[async_trait]
+pub trait ProvingBackend: Send {
+    /// Short name of the backend, used as a task name and as a Sentry tag.
+    fn name(&self) -> &'static str;
+
+    /// Kind of artifact produced by the backend.
+    fn kind(&self) -> BackendKind;
+
+    /// Processes a single block.
+    async fn process(&mut self, block: PreparedBlock) -> anyhow::Result<BackendOutput>;
+}
@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use execution_utils::unrolled::{UnrolledCpuProver, UnrolledProverLevel};
+use oracle_provider::ZkEENonDeterminismSource;
+
+use crate::observability;
+use crate::prover::backend::{BackendKind, BackendOutput, PreparedBlock, ProvingBackend};
+use crate::prover::gpu_prover::{ProofResult, serialize_proof, strip_bin_suffix};
+
+/// Proves blocks with the airbender CPU prover.
+///
+/// Proofs are produced at the same (`RecursionUnified`) level and with the same encoding as the
+/// GPU [`Prover`](crate::prover::gpu_prover::Prover), so they can be verified and submitted in
+/// the same way. Proving is orders of magnitude slower than on GPU, so this is only intended for
+/// small blocks, e.g. to exercise the full proof path in CI or on machines without a GPU.
+pub struct CpuProver {
+    app_bin_path: PathBuf,
+    worker_threads: Option<usize>,
+    inner: Arc<UnrolledCpuProver>,
+}
+
+impl std::fmt::Debug for CpuProver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CpuProver")
+            .field("app_bin_path", &self.app_bin_path)
+            .finish()
+    }
+}
+
+impl CpuProver {
+    /// Creates the prover, precomputing the setup for the application and recursion circuits.
+    /// This takes a while, so it's better done on a blocking thread.
+    pub fn new(app_bin_path: &Path, worker_threads: Option<usize>) -> anyhow::Result<Self> {
+        Ok(Self {
+            app_bin_path: app_bin_path.to_path_buf(),
+            worker_threads,
+            inner: Arc::new(create_unrolled_cpu_prover(app_bin_path, worker_threads)?),
+        })
+    }
+
+    pub async fn prove(
+        &mut self,
+        block_number: u64,
+        oracle: ZkEENonDeterminismSource,
+    ) -> anyhow::Result<ProofResult> {
+        let start = Instant::now();
+        let inner = self.inner.clone();
+        let (proof, cycles) = match observability::spawn_blocking_on_current_hub(move || {
+            inner.prove(block_number, oracle)
+        })
+        .await
+        {
+            Ok(result) => result,
+            Err(err) => {
+                let panic_msg = crate::utils::extract_panic_message(err);
+                tracing::error!("CPU prover panicked for block {block_number}: {panic_msg}");
+
+                // Like the GPU prover, a prover that panicked may be left in a broken state,
+                // so it's replaced with a new instance.
+                tracing::info!("Re-creating the CPU prover instance after the panic");
+                self.inner = Arc::new(
+                    create_unrolled_cpu_prover(&self.app_bin_path, self.worker_threads)
+                        .with_context(|| {
+                            format!(
+                                "failed to re-instantiate CPU prover after panic while processing block {block_number}"
+                            )
+                        })?,
+                );
+                return Err(anyhow::anyhow!(
+                    "CPU prover task panicked while processing block {block_number}: {panic_msg}"
+                ));
+            }
+        };
+
+        let proving_time_secs = start.elapsed().as_secs_f64();
+        let proof_bytes = serialize_proof(&proof)
+            .with_context(|| format!("failed to encode proof bytes for block {block_number}"))?;
+        Ok(ProofResult {
+            proof_bytes,
+            cycles,
+            proving_time_secs,
+        })
+    }
+}
+
+/// Creates the airbender CPU prover: `execution_utils::unrolled::UnrolledCpuProver` from the same
+/// zksync-airbender `dev` branch as the GPU prover (see `Cargo.toml`). Its `new` and `prove`
+/// mirror those of `execution_utils::unrolled_gpu::UnrolledProver`, with `prove` returning the
+/// proof together with the cycle count.
+fn create_unrolled_cpu_prover(
+    app_bin_path: &Path,
+    worker_threads: Option<usize>,
+) -> anyhow::Result<UnrolledCpuProver> {
+    let base_path = strip_bin_suffix(app_bin_path)?;
+    Ok(UnrolledCpuProver::new(
+        &base_path,
+        worker_threads,
+        UnrolledProverLevel::RecursionUnified,
+    ))
+}
+
+#[async_trait]
+impl ProvingBackend for CpuProver {
+    fn name(&self) -> &'static str {
+        "cpu_prove"
+    }
+
+    fn kind(&self) -> BackendKind {
+        BackendKind::Proof
+    }
+
+    async fn process(&mut self, block: PreparedBlock) -> anyhow::Result<BackendOutput> {
+        let block_number = block.block_number();
+        let (_, oracle) = block.into_parts().with_context(|| {
+            format!("failed to build the proving oracle for block {block_number}")
+        })?;
+
+        tracing::info!("Proving block {} on CPU", block_number);
+        let proof_result = self
+            .prove(block_number, oracle)
+            .await
+            .with_context(|| format!("failed to prove block {block_number}"))?;
+        Ok(BackendOutput::Proof(proof_result))
+    }
+}
@@ -1,10 +1,13 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
 
+use alloy::consensus::TxType;
+use alloy::primitives::{Address, B256, Bytes, Log};
 use alloy::providers::DynProvider;
 use alloy::providers::Provider;
-use alloy::rpc::types::Transaction;
+use alloy::rpc::types::{Transaction, TransactionReceipt};
 use anyhow::Context as _;
+use async_trait::async_trait;
 use basic_bootloader::bootloader::BasicBootloader;
 use basic_bootloader::bootloader::config::BasicBootloaderForwardETHLikeConfig;
 use forward_system::run::InvalidTransaction;
@@ -13,36 +16,162 @@ use forward_system::run::result_keeper::ForwardRunningResultKeeper;
 use forward_system::run::result_keeper::TxProcessingOutputOwned;
 use forward_system::run::test_impl::NoopTxCallback;
 use forward_system::system::system_types::ethereum::EthereumStorageSystemTypesWithPostOps;
+use futures::{StreamExt as _, TryStreamExt as _, stream};
 use oracle_provider::ReadWitnessSource;
 use oracle_provider::ZkEENonDeterminismSource;
 use zk_ee::system::tracer::NopTracer;
 
+use crate::clients::rpc::{RpcPool, retry_rpc_call};
+use crate::metrics::METRICS;
+use crate::prover::backend::{BackendKind, BackendOutput, PreparedBlock, ProvingBackend};
+use crate::prover::debugger::{DebugReport, StfTxResult};
+use crate::prover::oracle::build_oracle;
+use crate::prover::outcome::{BlockExecutionOutcome, TxOutcome};
+use crate::prover::profiler::CycleProfiler;
+use crate::prover::tracer::{AccountPrestate, BlockTraces, ExecutionTracer};
+use crate::prover::types::EthBlockInput;
+use crate::prover::witness::PreState;
+use crate::types::RpcRole;
 use crate::{CacheStorage, observability};
 
+/// Number of concurrent `eth_getTransactionReceipt` requests when `eth_getBlockReceipts`
+/// is not available.
+const MAX_CONCURRENT_RECEIPT_REQUESTS: usize = 16;
+/// Default maximum number of RISC-V cycles a block may take to execute.
+pub const DEFAULT_CYCLE_LIMIT: u64 = 1 << 36;
+
+#[derive(Debug)]
+pub struct WitnessResult {
+    pub witness: Vec<u32>,
+    /// Number of RISC-V cycles it took to execute the block.
+    pub cycles: u64,
+    pub gas_used: u64,
+    pub tx_count: usize,
+}
+
+impl WitnessResult {
+    /// Number of cycles per unit of gas, or `None` for blocks without gas used.
+    pub fn cycles_per_gas(&self) -> Option<f64> {
+        (self.gas_used > 0).then(|| self.cycles as f64 / self.gas_used as f64)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CpuWitnessGenerator {
     app_bin_path: PathBuf,
+    cycle_limit: u64,
+    debug_context: Option<DebugContext>,
+    profiling_context: Option<ProfilingContext>,
+}
+
+/// Data required to debug a block after a forward-run failure.
+#[derive(Debug, Clone)]
+struct DebugContext {
+    rpc: Option<RpcPool>,
+    cache: CacheStorage,
+    /// Whether to trace the execution of the transactions while debugging.
+    trace_transactions: bool,
+}
+
+/// Block data the execution tracer reports besides the execution itself.
+#[derive(Debug)]
+pub struct TraceContext {
+    coinbase: Address,
+    /// Source of the balances, nonces and codes of the accessed accounts, if the witness has it.
+    pre_state: Option<PreState>,
+}
+
+/// Data required to profile the execution of blocks.
+#[derive(Debug, Clone)]
+struct ProfilingContext {
+    profiler: CycleProfiler,
+    cache: CacheStorage,
 }
 
 impl CpuWitnessGenerator {
     pub fn new(app_bin_path: PathBuf) -> Self {
-        Self { app_bin_path }
+        Self {
+            app_bin_path,
+            cycle_limit: DEFAULT_CYCLE_LIMIT,
+            debug_context: None,
+            profiling_context: None,
+        }
+    }
+
+    /// Sets the maximum number of RISC-V cycles a block may take; blocks exceeding it fail.
+    pub fn with_cycle_limit(mut self, cycle_limit: u64) -> Self {
+        self.cycle_limit = cycle_limit;
+        self
     }
 
+    /// Enables the transaction debugger, which is run whenever the forward run fails.
+    /// Receipts are fetched from the `witness` endpoints of `rpc` (if provided) and stored in
+    /// `cache`, so that cached blocks can be debugged without RPC. With `trace_transactions`,
+    /// execution traces of the debugged blocks are stored in `cache` as well.
+    pub(crate) fn with_debugger(
+        mut self,
+        rpc: Option<RpcPool>,
+        cache: CacheStorage,
+        trace_transactions: bool,
+    ) -> Self {
+        self.debug_context = Some(DebugContext {
+            rpc,
+            cache,
+            trace_transactions,
+        });
+        self
+    }
+
+    /// Enables the cycle profiler, which executes every block once more in the RISC-V simulator
+    /// before its witness is generated. Profiles are stored in `cache`.
+    pub(crate) fn with_profiler(mut self, profiler: CycleProfiler, cache: CacheStorage) -> Self {
+        self.profiling_context = Some(ProfilingContext { profiler, cache });
+        self
+    }
+
+    /// Executes the block with the STF and collects the results of its transactions.
     pub async fn forward_run(
         &self,
         block_number: u64,
+        transactions: &[Transaction],
         oracle: ZkEENonDeterminismSource,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<BlockExecutionOutcome> {
+        self.partial_forward_run(block_number, transactions, oracle)
+            .await?
+            .into_result()
+    }
+
+    /// Same as [`Self::forward_run`], but keeps the results of the executed transactions if
+    /// the run fails, e.g. to compare them with another execution of the block.
+    pub async fn partial_forward_run(
+        &self,
+        block_number: u64,
+        transactions: &[Transaction],
+        oracle: ZkEENonDeterminismSource,
+    ) -> anyhow::Result<ForwardRun> {
+        let transactions: Vec<(B256, TxType)> = transactions
+            .iter()
+            .map(|tx| (*tx.inner.tx_hash(), tx.inner.tx_type()))
+            .collect();
         match observability::spawn_blocking_on_current_hub(move || {
             let mut result_keeper = ForwardRunningResultKeeper::new(NoopTxCallback);
             let mut nop_tracer = NopTracer::default();
-            BasicBootloader::<EthereumStorageSystemTypesWithPostOps<ZkEENonDeterminismSource>>::run::<
-                BasicBootloaderForwardETHLikeConfig,
-            >(oracle, &mut result_keeper, &mut nop_tracer)
-            .map_err(|err| anyhow::anyhow!("failed to run the STF in forward-run mode: {err:?}"))?;
+            let run_error = BasicBootloader::<
+                EthereumStorageSystemTypesWithPostOps<ZkEENonDeterminismSource>,
+            >::run::<BasicBootloaderForwardETHLikeConfig>(
+                oracle,
+                &mut result_keeper,
+                &mut nop_tracer,
+            )
+            .err()
+            .map(|err| anyhow::anyhow!("failed to run the STF in forward-run mode: {err:?}"));
 
-            Ok(())
+            let (outcome, outcome_error) =
+                execution_outcome(block_number, &transactions, &result_keeper);
+            Ok(ForwardRun {
+                outcome,
+                error: run_error.or(outcome_error),
+            })
         })
         .await
         {
@@ -56,25 +185,56 @@ impl CpuWitnessGenerator {
         }
     }
 
+    /// Re-runs the block with the debugger attached and returns the divergences it found,
+    /// along with the execution traces of the transactions if `trace` is set.
     pub async fn debug(
         &self,
         block_number: u64,
         oracle: ZkEENonDeterminismSource,
         debugger: DebuggerTxCallback,
-    ) -> anyhow::Result<DebuggerTxCallback> {
+        trace: Option<TraceContext>,
+    ) -> anyhow::Result<(DebugReport, Option<BlockTraces>)> {
+        let tx_hashes: Vec<B256> = debugger.txs.iter().map(|tx| *tx.inner.tx_hash()).collect();
         match observability::spawn_blocking_on_current_hub(move || {
+            type Bootloader =
+                BasicBootloader<EthereumStorageSystemTypesWithPostOps<ZkEENonDeterminismSource>>;
+
             let mut result_keeper = ForwardRunningResultKeeper::new(debugger);
-            let mut nop_tracer = NopTracer::default();
-            // We ignore the error, as we are debugging and getting the results.
-            let _ = BasicBootloader::<
-                EthereumStorageSystemTypesWithPostOps<ZkEENonDeterminismSource>,
-            >::run::<BasicBootloaderForwardETHLikeConfig>(
-                oracle,
-                &mut result_keeper,
-                &mut nop_tracer,
-            );
+            // We ignore the errors, as we are debugging and getting the results.
+            let traces = if let Some(trace) = trace {
+                let mut tracer = ExecutionTracer::default();
+                let _ = Bootloader::run::<BasicBootloaderForwardETHLikeConfig>(
+                    oracle,
+                    &mut result_keeper,
+                    &mut tracer,
+                );
+                Some(
+                    tracer.into_block_traces(&tx_hashes, trace.coinbase, |address| {
+                        trace
+                            .pre_state
+                            .as_ref()
+                            .and_then(|pre_state| pre_block_account(pre_state, address))
+                    }),
+                )
+            } else {
+                let mut nop_tracer = NopTracer::default();
+                let _ = Bootloader::run::<BasicBootloaderForwardETHLikeConfig>(
+                    oracle,
+                    &mut result_keeper,
+                    &mut nop_tracer,
+                );
+                None
+            };
 
-            Ok(result_keeper.tx_result_callback)
+            let logs = stf_logs(&result_keeper, result_keeper.tx_result_callback.tx_count);
+            let mut debugger = result_keeper.tx_result_callback;
+            match logs {
+                Ok(logs) => debugger.compare_logs(&logs),
+                Err(err) => tracing::warn!(
+                    "Failed to collect logs emitted by the STF for block {block_number}: {err:#}"
+                ),
+            }
+            Ok((debugger.report, traces))
         })
         .await
         {
@@ -88,27 +248,42 @@ impl CpuWitnessGenerator {
         }
     }
 
+    /// Executes the block with the RISC-V runner and returns the generated witness along with
+    /// the number of cycles the execution took.
     pub async fn generate_witness(
         &self,
         block_number: u64,
         oracle: ZkEENonDeterminismSource,
-    ) -> anyhow::Result<Vec<u32>> {
+    ) -> anyhow::Result<(Vec<u32>, u64)> {
         let app_bin_path = self.app_bin_path.clone();
+        let cycle_limit = self.cycle_limit;
         match observability::spawn_blocking_on_current_hub(move || {
             let copy_source = ReadWitnessSource::new(oracle);
             let items = copy_source.get_read_items();
 
-            let output = zksync_os_runner::run(app_bin_path, None, 1 << 36, copy_source);
+            let cycle_budget = usize::try_from(cycle_limit)
+                .context("cycle limit does not fit into the address space")?;
+            let (output, cycles) = zksync_os_runner::run_and_get_effective_cycles(
+                app_bin_path,
+                None,
+                cycle_budget,
+                copy_source,
+            );
+            let Some(cycles) = cycles else {
+                anyhow::bail!(
+                    "block {block_number} did not finish within the limit of {cycle_limit} cycles"
+                );
+            };
             if output == [0u32; 8] {
                 anyhow::bail!("zksync_os_runner failed to execute block {block_number}");
             }
 
             let witness = items.borrow().clone();
-            Ok(witness)
+            Ok((witness, cycles))
         })
         .await
         {
-            Ok(Ok(witness)) => Ok(witness),
+            Ok(Ok(result)) => Ok(result),
             Ok(Err(err)) => Err(err).with_context(|| {
                 format!(
                     "failed to generate witness for block {block_number} using zksync_os_runner"
@@ -122,35 +297,411 @@ impl CpuWitnessGenerator {
             }
         }
     }
+
+    async fn process_block(&self, block: PreparedBlock) -> anyhow::Result<WitnessResult> {
+        let block_number = block.block_number();
+        tracing::info!("Performing forward run for block {}", block_number);
+        let (input, oracle) = block.into_parts().with_context(|| {
+            format!("failed to build the forward-run oracle for block {block_number}")
+        })?;
+        let forward_run = self
+            .forward_run(block_number, &input.transactions, oracle)
+            .await
+            .and_then(|outcome| {
+                outcome.check_against(&input.block_header)?;
+                tracing::debug!(
+                    "Forward run of block {block_number} matches the canonical header: {} transactions, {} gas used",
+                    outcome.transactions.len(),
+                    outcome.gas_used
+                );
+                Ok(outcome)
+            })
+            .with_context(|| format!("failed to perform forward run for block {block_number}"));
+        let outcome = match forward_run {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                if let Some(debug_context) = &self.debug_context {
+                    self.debug_block(input, debug_context)
+                        .await
+                        .with_context(|| {
+                            format!(
+                                "failed to debug block {block_number} after forward-run failure"
+                            )
+                        })?;
+                }
+                return Err(err);
+            }
+        };
+
+        if let Some(profiling_context) = &self.profiling_context {
+            self.profile_block(input.clone(), profiling_context).await;
+        }
+
+        tracing::info!("Generating witness for block {}", block_number);
+        let oracle = build_oracle(input).with_context(|| {
+            format!("failed to build the witness oracle for block {block_number}")
+        })?;
+        let (witness, cycles) = self
+            .generate_witness(block_number, oracle)
+            .await
+            .with_context(|| format!("failed to generate witness data for block {block_number}"))?;
+        Ok(WitnessResult {
+            witness,
+            cycles,
+            gas_used: outcome.gas_used,
+            tx_count: outcome.transactions.len(),
+        })
+    }
+
+    /// Profiles the execution of the block and stores the profile. Failures are only reported,
+    /// since the profile is not needed to generate the witness.
+    async fn profile_block(&self, input: EthBlockInput, profiling_context: &ProfilingContext) {
+        let block_number = input.block_header.number;
+        tracing::info!("Profiling cycles of block {block_number}");
+        let tx_hashes = input
+            .transactions
+            .iter()
+            .map(|tx| *tx.inner.tx_hash())
+            .collect();
+        let result = async {
+            let oracle = build_oracle(input).context("failed to build the profiling oracle")?;
+            let profile = profiling_context
+                .profiler
+                .profile(block_number, tx_hashes, oracle, self.cycle_limit)
+                .await?;
+            let dir = profiling_context.cache.save_cycle_profile(&profile)?;
+            anyhow::Ok((profile, dir))
+        }
+        .await
+        .with_context(|| format!("failed to profile block {block_number}"));
+
+        match result {
+            Ok((profile, dir)) => {
+                let heaviest = profile
+                    .heaviest_transactions(3)
+                    .iter()
+                    .map(|tx| format!("#{}: {} cycles", tx.tx_index, tx.cycles))
+                    .collect::<Vec<_>>();
+                tracing::info!(
+                    "Profiled block {block_number}: {} cycles, heaviest transactions: [{}], precompiles: {:?}. Stored in {}",
+                    profile.total_cycles,
+                    heaviest.join(", "),
+                    profile.precompiles,
+                    dir.display()
+                );
+            }
+            Err(err) => {
+                observability::capture_anyhow(&err);
+                tracing::error!("{err:#}");
+            }
+        }
+    }
+
+    async fn debug_block(
+        &self,
+        input: EthBlockInput,
+        debug_context: &DebugContext,
+    ) -> anyhow::Result<()> {
+        let block_number = input.block_header.number;
+        tracing::warn!("Forward run failed for block {block_number}, attempting to debug it");
+        let Some(receipts) = self
+            .block_receipts(&input, debug_context)
+            .await
+            .with_context(|| format!("failed to get receipts of block {block_number}"))?
+        else {
+            tracing::warn!(
+                "Receipts of block {block_number} are not cached and no RPC URL is provided for debugging"
+            );
+            tracing::warn!("In order to debug the issue, provide an RPC URL in the config");
+            return Ok(());
+        };
+
+        let trace = debug_context.trace_transactions.then(|| TraceContext {
+            coinbase: input.block_header.beneficiary,
+            pre_state: PreState::new(&input)
+                .inspect_err(|err| {
+                    tracing::warn!(
+                        "Account pre-state of block {block_number} won't be traced: {err:#}"
+                    );
+                })
+                .ok(),
+        });
+        let oracle = build_oracle(input.clone()).with_context(|| {
+            format!("failed to build the debug oracle for block {block_number}")
+        })?;
+        let debugger = DebuggerTxCallback::new(block_number, input.transactions, receipts);
+        let (report, traces) = self
+            .debug(block_number, oracle, debugger, trace)
+            .await
+            .with_context(|| format!("debugging failed for block {block_number}"))?;
+        tracing::info!(
+            "Debugging completed for block {block_number}: {} divergences in {} checked transactions",
+            report.divergences.len(),
+            report.checked_transactions
+        );
+        for divergence in &report.divergences {
+            tracing::error!("Divergence found while debugging block {block_number}: {divergence}");
+        }
+        METRICS
+            .debug_divergences_total
+            .inc_by(report.divergences.len() as u64);
+        let path = debug_context
+            .cache
+            .save_debug_report(&report)
+            .with_context(|| format!("failed to save debug report for block {block_number}"))?;
+        tracing::info!(
+            "Debug report for block {block_number} saved to {}",
+            path.display()
+        );
+        if let Some(traces) = traces {
+            let dir = debug_context
+                .cache
+                .save_debug_traces(block_number, &traces)
+                .with_context(|| {
+                    format!("failed to save execution traces for block {block_number}")
+                })?;
+            tracing::info!(
+                "Execution traces for block {block_number} saved to {}",
+                dir.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the receipts of all transactions of the block, from the cache or fetched via RPC.
+    /// Returns `None` if they're not cached and there is no RPC to fetch them from.
+    async fn block_receipts(
+        &self,
+        input: &EthBlockInput,
+        debug_context: &DebugContext,
+    ) -> anyhow::Result<Option<Vec<TransactionReceipt>>> {
+        let block_number = input.block_header.number;
+        if let Some(receipts) = debug_context.cache.load_receipts(block_number)? {
+            if receipts.len() == input.transactions.len() {
+                return Ok(Some(receipts));
+            }
+            tracing::warn!(
+                "Cached receipts of block {block_number} don't match its transactions, fetching them again"
+            );
+        }
+        let Some(rpc) = &debug_context.rpc else {
+            return Ok(None);
+        };
+        let block_hash = input.block_header.hash_slow();
+        let receipts = retry_rpc_call(
+            rpc,
+            RpcRole::Witness,
+            &format!("fetch receipts of block {block_number}"),
+            |endpoint| async move {
+                fetch_block_receipts(endpoint.provider(), block_hash, &input.transactions).await
+            },
+        )
+        .await?;
+        debug_context
+            .cache
+            .save_receipts(block_number, &receipts)
+            .context("failed to cache receipts")?;
+        Ok(Some(receipts))
+    }
+}
+
+/// Fetches the receipts with `eth_getBlockReceipts`, falling back to fetching them one by one
+/// for nodes that don't support it.
+async fn fetch_block_receipts(
+    provider: &DynProvider,
+    block_hash: B256,
+    transactions: &[Transaction],
+) -> anyhow::Result<Vec<TransactionReceipt>> {
+    match provider.get_block_receipts(block_hash.into()).await {
+        Ok(Some(receipts)) if receipts.len() == transactions.len() => return Ok(receipts),
+        Ok(Some(receipts)) => tracing::warn!(
+            "eth_getBlockReceipts returned {} receipts for {} transactions of block {block_hash}",
+            receipts.len(),
+            transactions.len()
+        ),
+        Ok(None) => tracing::warn!("eth_getBlockReceipts didn't find block {block_hash}"),
+        Err(err) => tracing::warn!("eth_getBlockReceipts failed for block {block_hash}: {err}"),
+    }
+    tracing::info!("Fetching receipts of block {block_hash} one by one");
+    stream::iter(transactions)
+        .map(|tx| async move {
+            let tx_hash = *tx.inner.tx_hash();
+            provider
+                .get_transaction_receipt(tx_hash)
+                .await
+                .with_context(|| format!("failed to fetch receipt of transaction {tx_hash}"))?
+                .with_context(|| format!("receipt of transaction {tx_hash} not found"))
+        })
+        .buffered(MAX_CONCURRENT_RECEIPT_REQUESTS)
+        .try_collect()
+        .await
+}
+
+/// Results of a forward run, see [`CpuWitnessGenerator::partial_forward_run`].
+#[derive(Debug)]
+pub struct ForwardRun {
+    /// Results of the transactions executed before the run failed, or of all transactions.
+    pub outcome: BlockExecutionOutcome,
+    pub error: Option<anyhow::Error>,
+}
+
+impl ForwardRun {
+    pub fn into_result(self) -> anyhow::Result<BlockExecutionOutcome> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(self.outcome),
+        }
+    }
+}
+
+/// Converts the results collected by the STF into a [`BlockExecutionOutcome`]. If the STF didn't
+/// execute all transactions successfully, the outcome covers the transactions before the first
+/// failed one, and the failure is returned alongside.
+fn execution_outcome(
+    block_number: u64,
+    transactions: &[(B256, TxType)],
+    result_keeper: &ForwardRunningResultKeeper<NoopTxCallback>,
+) -> (BlockExecutionOutcome, Option<anyhow::Error>) {
+    let logs = match stf_logs(result_keeper, transactions.len()) {
+        Ok(logs) => logs,
+        Err(err) => {
+            return (
+                BlockExecutionOutcome::new(block_number, Vec::new()),
+                Some(err),
+            );
+        }
+    };
+
+    let mut outcomes = Vec::with_capacity(transactions.len());
+    let mut error = None;
+    for ((&(tx_hash, tx_type), result), logs) in
+        transactions.iter().zip(&result_keeper.tx_results).zip(logs)
+    {
+        match result {
+            Ok(output) => outcomes.push(TxOutcome {
+                tx_hash,
+                tx_type,
+                status: output.status,
+                gas_used: output.gas_used,
+                logs,
+            }),
+            // A block with an invalid transaction is invalid as a whole, so a canonical block
+            // may not contain one.
+            Err(err) => {
+                error = Some(anyhow::anyhow!(
+                    "transaction {tx_hash} is invalid according to the STF: {err:?}"
+                ));
+                break;
+            }
+        }
+    }
+    if error.is_none() && result_keeper.tx_results.len() != transactions.len() {
+        error = Some(anyhow::anyhow!(
+            "STF executed {} transactions, but block {block_number} contains {}",
+            result_keeper.tx_results.len(),
+            transactions.len()
+        ));
+    }
+    (BlockExecutionOutcome::new(block_number, outcomes), error)
+}
+
+/// Groups the events emitted by the STF into the logs of each transaction.
+fn stf_logs<T: TxResultCallback>(
+    result_keeper: &ForwardRunningResultKeeper<T>,
+    tx_count: usize,
+) -> anyhow::Result<Vec<Vec<Log>>> {
+    let mut logs = vec![Vec::new(); tx_count];
+    for event in &result_keeper.events {
+        let tx_logs = logs.get_mut(event.tx_number as usize).with_context(|| {
+            format!(
+                "STF emitted event for unknown transaction #{}",
+                event.tx_number
+            )
+        })?;
+        tx_logs.push(Log::new_unchecked(
+            Address::from(event.address.to_be_bytes::<20>()),
+            event
+                .topics
+                .iter()
+                .map(|topic| B256::from(topic.as_u8_array()))
+                .collect(),
+            Bytes::copy_from_slice(event.data.as_slice()),
+        ));
+    }
+    Ok(logs)
+}
+
+/// Account as of before the block in the format of geth's `prestateTracer`, without storage.
+fn pre_block_account(pre_state: &PreState, address: Address) -> Option<AccountPrestate> {
+    let (account, code) = pre_state.account(address)?;
+    Some(AccountPrestate {
+        balance: Some(account.balance),
+        nonce: (account.nonce != 0).then_some(account.nonce),
+        code: code.cloned(),
+        ..Default::default()
+    })
+}
+
+#[async_trait]
+impl ProvingBackend for CpuWitnessGenerator {
+    fn name(&self) -> &'static str {
+        "cpu_witness"
+    }
+
+    fn kind(&self) -> BackendKind {
+        BackendKind::Witness
+    }
+
+    async fn process(&mut self, block: PreparedBlock) -> anyhow::Result<BackendOutput> {
+        let witness_result = self.process_block(block).await?;
+        Ok(BackendOutput::Witness(witness_result))
+    }
 }
 
+/// Compares the results of the transactions executed by the STF with their receipts.
 #[derive(Clone)]
 pub struct DebuggerTxCallback {
-    block_number: u64,
     txs: VecDeque<Transaction>,
-    provider: DynProvider,
-    problems: Vec<String>,
-    cache: CacheStorage,
+    tx_count: usize,
+    receipts: HashMap<B256, TransactionReceipt>,
+    cumulative_gas_used: u64,
+    /// Indices and hashes of the transactions compared with their receipts, for comparing the logs.
+    checked: Vec<(usize, B256)>,
+    report: DebugReport,
 }
 
 impl DebuggerTxCallback {
     pub fn new(
         block_number: u64,
         txs: Vec<Transaction>,
-        provider: DynProvider,
-        cache: CacheStorage,
+        receipts: Vec<TransactionReceipt>,
     ) -> Self {
         Self {
-            block_number,
+            tx_count: txs.len(),
             txs: VecDeque::from(txs),
-            provider,
-            problems: vec![],
-            cache,
+            receipts: receipts
+                .into_iter()
+                .map(|receipt| (receipt.transaction_hash, receipt))
+                .collect(),
+            cumulative_gas_used: 0,
+            checked: Vec::new(),
+            report: DebugReport::new(block_number),
         }
     }
 
-    pub fn get_problems(&self) -> &[String] {
-        &self.problems
+    pub fn report(&self) -> &DebugReport {
+        &self.report
+    }
+
+    /// Compares the logs emitted by the STF, grouped by transaction, with the receipts.
+    fn compare_logs(&mut self, stf_logs: &[Vec<Log>]) {
+        for (tx_index, tx_hash) in &self.checked {
+            if let (Some(logs), Some(receipt)) =
+                (stf_logs.get(*tx_index), self.receipts.get(tx_hash))
+            {
+                self.report.compare_logs(*tx_index, logs, receipt);
+            }
+        }
     }
 }
 
@@ -159,73 +710,37 @@ impl TxResultCallback for DebuggerTxCallback {
         &mut self,
         tx_execution_result: Result<TxProcessingOutputOwned, InvalidTransaction>,
     ) {
+        let tx_index = self.tx_count - self.txs.len();
         let Some(executed_tx) = self.txs.pop_front() else {
             tracing::error!("Transaction stream is empty, but tx_executed was called");
             return;
         };
+        let tx_hash = *executed_tx.inner.tx_hash();
 
-        let Ok(tx_execution_result) = tx_execution_result else {
-            let Err(err) = tx_execution_result else {
+        let tx_execution_result = match tx_execution_result {
+            Ok(result) => result,
+            Err(err) => {
+                tracing::error!("Transaction {tx_hash:?} was considered invalid: {err:?}");
+                self.report
+                    .record_invalid_tx(tx_index, tx_hash, format!("{err:?}"));
                 return;
-            };
-            tracing::error!(
-                "Transaction {:?} was considered invalid: {:?}",
-                executed_tx.inner.tx_hash(),
-                err
-            );
-            return;
+            }
         };
+        self.cumulative_gas_used += tx_execution_result.gas_used;
 
-        let rt_handle = tokio::runtime::Handle::current();
-        let tx_hash = executed_tx.inner.tx_hash();
         tracing::debug!("Debugging transaction {tx_hash:?}");
-
-        let receipt = if let Ok(Some(receipt)) = self.cache.load_receipt(self.block_number, tx_hash)
-        {
-            receipt
-        } else {
-            let receipt_result =
-                rt_handle.block_on(async { self.provider.get_transaction_receipt(*tx_hash).await });
-            let receipt = match receipt_result {
-                Ok(Some(receipt)) => receipt,
-                Ok(None) => {
-                    tracing::error!("Transaction receipt not found for {:?}", tx_hash);
-                    return;
-                }
-                Err(err) => {
-                    tracing::error!(
-                        "Failed to get transaction receipt for {:?}: {}",
-                        tx_hash,
-                        err
-                    );
-                    return;
-                }
-            };
-
-            if let Err(err) = self.cache.save_receipt(self.block_number, receipt.clone()) {
-                tracing::error!("Failed to save cache entry: {err}");
-            }
-
-            receipt
+        let Some(receipt) = self.receipts.get(&tx_hash) else {
+            tracing::error!("Transaction receipt not found for {tx_hash:?}");
+            return;
         };
-
-        tracing::debug!("Fetched receipt for transaction {tx_hash:?}");
-
-        if tx_execution_result.status != receipt.status() {
-            tracing::error!(
-                "Transaction {:?} execution status mismatch: STF status = {:?}, Ethereum status = {:?}",
-                tx_hash,
-                tx_execution_result.status,
-                receipt.status()
-            );
-        }
-        if tx_execution_result.gas_used != receipt.gas_used {
-            tracing::error!(
-                "Transaction {:?} gas used mismatch: STF gas used = {}, Ethereum gas used = {}",
-                tx_hash,
-                tx_execution_result.gas_used,
-                receipt.gas_used
-            );
-        }
+        let stf_result = StfTxResult {
+            tx_index,
+            tx_hash,
+            status: tx_execution_result.status,
+            gas_used: tx_execution_result.gas_used,
+            cumulative_gas_used: self.cumulative_gas_used,
+        };
+        self.report.compare_receipt(&stf_result, receipt);
+        self.checked.push((tx_index, tx_hash));
     }
 }
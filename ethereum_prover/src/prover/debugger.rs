@@ -0,0 +1,252 @@
+//! Divergences between the STF and the canonical chain, found by the transaction debugger.
+
+use std::fmt;
+
+use alloy::{
+    primitives::{B256, Log},
+    rpc::types::TransactionReceipt,
+};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+/// What differs between the STF execution of a transaction and its canonical receipt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DivergenceKind {
+    Status,
+    Gas,
+    CumulativeGas,
+    LogCount,
+    Log,
+    /// The STF rejected a transaction that is included in the canonical block.
+    InvalidTx,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Divergence {
+    pub tx_index: usize,
+    pub tx_hash: B256,
+    pub kind: DivergenceKind,
+    /// Index of the differing log, for [`DivergenceKind::Log`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_index: Option<usize>,
+    pub stf: Value,
+    pub chain: Value,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "transaction #{} ({}): {:?}",
+            self.tx_index, self.tx_hash, self.kind
+        )?;
+        if let Some(log_index) = self.log_index {
+            write!(f, " #{log_index}")?;
+        }
+        write!(f, " mismatch: STF = {}, chain = {}", self.stf, self.chain)
+    }
+}
+
+/// Result of debugging a block, stored as `debug_report.json` in the block cache directory.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DebugReport {
+    pub block_number: u64,
+    /// Number of transactions whose results were compared with their receipts.
+    pub checked_transactions: usize,
+    pub divergences: Vec<Divergence>,
+}
+
+/// Result of a single transaction executed by the STF, as seen by the debugger.
+#[derive(Debug, Clone)]
+pub struct StfTxResult {
+    pub tx_index: usize,
+    pub tx_hash: B256,
+    pub status: bool,
+    pub gas_used: u64,
+    pub cumulative_gas_used: u64,
+}
+
+impl DebugReport {
+    pub fn new(block_number: u64) -> Self {
+        Self {
+            block_number,
+            ..Default::default()
+        }
+    }
+
+    pub fn record_invalid_tx(&mut self, tx_index: usize, tx_hash: B256, reason: String) {
+        self.divergences.push(Divergence {
+            tx_index,
+            tx_hash,
+            kind: DivergenceKind::InvalidTx,
+            log_index: None,
+            stf: Value::String(reason),
+            chain: Value::String("included".to_owned()),
+        });
+    }
+
+    /// Compares the status and gas of the transaction with its receipt.
+    pub fn compare_receipt(&mut self, stf: &StfTxResult, receipt: &TransactionReceipt) {
+        self.checked_transactions += 1;
+        let mut push = |kind, stf_value: Value, chain_value: Value| {
+            if stf_value != chain_value {
+                self.divergences.push(Divergence {
+                    tx_index: stf.tx_index,
+                    tx_hash: stf.tx_hash,
+                    kind,
+                    log_index: None,
+                    stf: stf_value,
+                    chain: chain_value,
+                });
+            }
+        };
+        push(
+            DivergenceKind::Status,
+            json!(stf.status),
+            json!(receipt.status()),
+        );
+        push(
+            DivergenceKind::Gas,
+            json!(stf.gas_used),
+            json!(receipt.gas_used),
+        );
+        push(
+            DivergenceKind::CumulativeGas,
+            json!(stf.cumulative_gas_used),
+            json!(receipt.inner.cumulative_gas_used()),
+        );
+    }
+
+    /// Compares the logs emitted by the transaction in the STF with the logs of its receipt.
+    pub fn compare_logs(
+        &mut self,
+        tx_index: usize,
+        stf_logs: &[Log],
+        receipt: &TransactionReceipt,
+    ) {
+        let tx_hash = receipt.transaction_hash;
+        let chain_logs = receipt.inner.logs();
+        if stf_logs.len() != chain_logs.len() {
+            self.divergences.push(Divergence {
+                tx_index,
+                tx_hash,
+                kind: DivergenceKind::LogCount,
+                log_index: None,
+                stf: json!(stf_logs.len()),
+                chain: json!(chain_logs.len()),
+            });
+        }
+        for (log_index, (stf_log, chain_log)) in stf_logs.iter().zip(chain_logs).enumerate() {
+            if *stf_log != chain_log.inner {
+                self.divergences.push(Divergence {
+                    tx_index,
+                    tx_hash,
+                    kind: DivergenceKind::Log,
+                    log_index: Some(log_index),
+                    stf: json!(stf_log),
+                    chain: json!(chain_log.inner),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        consensus::{Receipt, ReceiptEnvelope},
+        primitives::{Address, B256, Bytes, Log},
+        rpc::types::TransactionReceipt,
+    };
+
+    use super::{DebugReport, DivergenceKind, StfTxResult};
+
+    fn receipt(status: bool, gas_used: u64, cumulative: u64, logs: &[Log]) -> TransactionReceipt {
+        let receipt = Receipt {
+            status: status.into(),
+            cumulative_gas_used: cumulative,
+            logs: logs
+                .iter()
+                .map(|log| alloy::rpc::types::Log {
+                    inner: log.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+        };
+        TransactionReceipt {
+            inner: ReceiptEnvelope::Eip1559(receipt.with_bloom()),
+            transaction_hash: B256::repeat_byte(1),
+            transaction_index: Some(1),
+            block_hash: None,
+            block_number: None,
+            gas_used,
+            effective_gas_price: 0,
+            blob_gas_used: None,
+            blob_gas_price: None,
+            from: Address::ZERO,
+            to: None,
+            contract_address: None,
+        }
+    }
+
+    fn log(data: &'static [u8]) -> Log {
+        Log::new_unchecked(
+            Address::repeat_byte(2),
+            vec![B256::repeat_byte(3)],
+            Bytes::from_static(data),
+        )
+    }
+
+    #[test]
+    fn matching_transaction_has_no_divergences() {
+        let mut report = DebugReport::new(10);
+        let stf = StfTxResult {
+            tx_index: 1,
+            tx_hash: B256::repeat_byte(1),
+            status: true,
+            gas_used: 21_000,
+            cumulative_gas_used: 42_000,
+        };
+        let logs = [log(b"transfer")];
+        let receipt = receipt(true, 21_000, 42_000, &logs);
+
+        report.compare_receipt(&stf, &receipt);
+        report.compare_logs(1, &logs, &receipt);
+
+        assert_eq!(report.checked_transactions, 1);
+        assert!(report.divergences.is_empty(), "{:?}", report.divergences);
+    }
+
+    #[test]
+    fn receipt_divergences_are_recorded() {
+        let mut report = DebugReport::new(10);
+        let stf = StfTxResult {
+            tx_index: 1,
+            tx_hash: B256::repeat_byte(1),
+            status: false,
+            gas_used: 30_000,
+            cumulative_gas_used: 51_000,
+        };
+        let receipt = receipt(true, 21_000, 42_000, &[log(b"a"), log(b"b")]);
+
+        report.compare_receipt(&stf, &receipt);
+        report.compare_logs(1, &[log(b"a"), log(b"c"), log(b"d")], &receipt);
+
+        let kinds: Vec<_> = report.divergences.iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                DivergenceKind::Status,
+                DivergenceKind::Gas,
+                DivergenceKind::CumulativeGas,
+                DivergenceKind::LogCount,
+                DivergenceKind::Log,
+            ]
+        );
+        assert_eq!(report.divergences[4].log_index, Some(1));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["divergences"][1]["kind"], "gas");
+        assert_eq!(json["divergences"][1]["stf"], 30_000);
+    }
+}
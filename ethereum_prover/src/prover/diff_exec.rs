@@ -0,0 +1,712 @@
+//! Differential execution of blocks with revm, over the same execution witness as the STF.
+//!
+//! If revm executes the block in line with the canonical header, the witness is complete and
+//! a divergence of the STF is a bug in the STF; otherwise the witness is the likely culprit.
+
+use std::{collections::HashMap, fmt};
+
+use alloy::{
+    consensus::{Header, Transaction as _},
+    eips::{
+        eip2935::HISTORY_STORAGE_ADDRESS,
+        eip4788::{BEACON_ROOTS_ADDRESS, SYSTEM_ADDRESS},
+        eip4895::{Withdrawal, Withdrawals},
+        eip7002::WITHDRAWAL_REQUEST_PREDEPLOY_ADDRESS,
+        eip7251::CONSOLIDATION_REQUEST_PREDEPLOY_ADDRESS,
+        eip7840::BlobParams,
+    },
+    primitives::{Address, B256, Bytes, U256, keccak256},
+    rlp::Decodable as _,
+    rpc::types::Transaction,
+    trie::{EMPTY_ROOT_HASH, KECCAK_EMPTY, TrieAccount},
+};
+use anyhow::Context as _;
+use revm::{
+    Context, DatabaseRef, ExecuteCommitEvm as _, MainBuilder as _, MainContext as _,
+    SystemCallCommitEvm as _,
+    context::{BlockEnv, CfgEnv, TxEnv},
+    context_interface::{block::BlobExcessGasAndPrice, either::Either},
+    database::{AccountState, CacheDB},
+    database_interface::DBErrorMarker,
+    primitives::hardfork::SpecId,
+    state::{AccountInfo, Bytecode},
+};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::observability;
+use crate::prover::{
+    cpu_witness::CpuWitnessGenerator,
+    debugger::DivergenceKind,
+    oracle::{build_oracle, witness_headers},
+    outcome::{BlockExecutionOutcome, TxOutcome},
+    sparse_trie::{SparseTrie, WitnessNodes},
+    types::EthBlockInput,
+};
+
+const MAINNET_CHAIN_ID: u64 = 1;
+
+/// Mainnet hard forks supported by the differential execution, from the latest one.
+const MAINNET_FORKS: [(u64, SpecId, BlobParams); 5] = [
+    (1_767_747_671, SpecId::OSAKA, BlobParams::bpo2()),
+    (1_765_290_071, SpecId::OSAKA, BlobParams::bpo1()),
+    (1_764_798_551, SpecId::OSAKA, BlobParams::osaka()),
+    (1_746_612_311, SpecId::PRAGUE, BlobParams::prague()),
+    (1_710_338_135, SpecId::CANCUN, BlobParams::cancun()),
+];
+
+/// Result of executing a block with revm.
+#[derive(Debug)]
+pub struct RevmRun {
+    /// Results of the transactions executed before the run failed, or of all transactions.
+    pub outcome: BlockExecutionOutcome,
+    /// Post-state root, if the whole block was executed.
+    pub state_root: anyhow::Result<B256>,
+}
+
+/// Difference between the STF and revm in executing a transaction.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecDivergence {
+    pub tx_index: usize,
+    pub tx_hash: B256,
+    pub kind: DivergenceKind,
+    /// Index of the differing log, for [`DivergenceKind::Log`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_index: Option<usize>,
+    pub stf: Value,
+    pub revm: Value,
+}
+
+impl fmt::Display for ExecDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "transaction #{} ({}): {:?}",
+            self.tx_index, self.tx_hash, self.kind
+        )?;
+        if let Some(log_index) = self.log_index {
+            write!(f, " #{log_index}")?;
+        }
+        write!(f, " mismatch: STF = {}, revm = {}", self.stf, self.revm)
+    }
+}
+
+/// Result of the differential execution of a block, stored as `diff_exec_report.json` in the
+/// block cache directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffExecReport {
+    pub block_number: u64,
+    /// Why the STF run failed, if it did.
+    pub stf_error: Option<String>,
+    /// Why the revm run failed, if it did, e.g. because the witness is incomplete.
+    pub revm_error: Option<String>,
+    pub stf_transactions: usize,
+    pub revm_transactions: usize,
+    pub divergences: Vec<ExecDivergence>,
+    pub expected_state_root: B256,
+    /// Post-state root computed from the revm execution over the witness.
+    pub revm_state_root: Option<B256>,
+}
+
+impl DiffExecReport {
+    pub fn new(
+        stf: &BlockExecutionOutcome,
+        stf_error: Option<&anyhow::Error>,
+        revm: &RevmRun,
+        header: &Header,
+    ) -> Self {
+        Self {
+            block_number: header.number,
+            stf_error: stf_error.map(|err| format!("{err:#}")),
+            revm_error: revm.state_root.as_ref().err().map(|err| format!("{err:#}")),
+            stf_transactions: stf.transactions.len(),
+            revm_transactions: revm.outcome.transactions.len(),
+            divergences: compare_outcomes(stf, &revm.outcome),
+            expected_state_root: header.state_root,
+            revm_state_root: revm.state_root.as_ref().ok().copied(),
+        }
+    }
+
+    /// Whether both executions succeeded, agree on every transaction and end in the
+    /// canonical state.
+    pub fn is_clean(&self) -> bool {
+        self.stf_error.is_none()
+            && self.revm_error.is_none()
+            && self.divergences.is_empty()
+            && self.revm_state_root == Some(self.expected_state_root)
+    }
+}
+
+impl fmt::Display for DiffExecReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "block {}: STF executed {} transactions, revm executed {}, {} divergences",
+            self.block_number,
+            self.stf_transactions,
+            self.revm_transactions,
+            self.divergences.len()
+        )?;
+        match self.revm_state_root {
+            Some(root) if root == self.expected_state_root => {
+                write!(f, ", revm state root matches the header")?
+            }
+            Some(root) => write!(
+                f,
+                ", revm state root {root} differs from the header ({})",
+                self.expected_state_root
+            )?,
+            None => {}
+        }
+        if let Some(err) = &self.stf_error {
+            write!(f, "; STF failed: {err}")?;
+        }
+        if let Some(err) = &self.revm_error {
+            write!(f, "; revm failed: {err}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Executes the block with both the STF and revm, and compares the results.
+pub async fn diff_exec(
+    stf: &CpuWitnessGenerator,
+    input: EthBlockInput,
+) -> anyhow::Result<DiffExecReport> {
+    let block_number = input.block_header.number;
+    let header = input.block_header.clone();
+    let oracle = build_oracle(input.clone()).with_context(|| {
+        format!("failed to build the forward-run oracle for block {block_number}")
+    })?;
+    let forward_run = stf
+        .partial_forward_run(block_number, &input.transactions, oracle)
+        .await?;
+    let revm = match observability::spawn_blocking_on_current_hub(move || execute_with_revm(&input))
+        .await
+    {
+        Ok(result) => result?,
+        Err(err) => {
+            let panic_msg = crate::utils::extract_panic_message(err);
+            anyhow::bail!("revm task panicked while processing block {block_number}: {panic_msg}");
+        }
+    };
+    Ok(DiffExecReport::new(
+        &forward_run.outcome,
+        forward_run.error.as_ref(),
+        &revm,
+        &header,
+    ))
+}
+
+/// Compares the transactions executed by both the STF and revm.
+pub fn compare_outcomes(
+    stf: &BlockExecutionOutcome,
+    revm: &BlockExecutionOutcome,
+) -> Vec<ExecDivergence> {
+    let mut divergences = Vec::new();
+    for (tx_index, (stf_tx, revm_tx)) in stf.transactions.iter().zip(&revm.transactions).enumerate()
+    {
+        let mut push = |kind, log_index, stf_value: Value, revm_value: Value| {
+            if stf_value != revm_value {
+                divergences.push(ExecDivergence {
+                    tx_index,
+                    tx_hash: stf_tx.tx_hash,
+                    kind,
+                    log_index,
+                    stf: stf_value,
+                    revm: revm_value,
+                });
+            }
+        };
+        push(
+            DivergenceKind::Status,
+            None,
+            json!(stf_tx.status),
+            json!(revm_tx.status),
+        );
+        push(
+            DivergenceKind::Gas,
+            None,
+            json!(stf_tx.gas_used),
+            json!(revm_tx.gas_used),
+        );
+        push(
+            DivergenceKind::LogCount,
+            None,
+            json!(stf_tx.logs.len()),
+            json!(revm_tx.logs.len()),
+        );
+        for (log_index, (stf_log, revm_log)) in stf_tx.logs.iter().zip(&revm_tx.logs).enumerate() {
+            push(
+                DivergenceKind::Log,
+                Some(log_index),
+                json!(stf_log),
+                json!(revm_log),
+            );
+        }
+    }
+    divergences
+}
+
+/// Executes the block with revm over the execution witness of the block.
+///
+/// Besides the transactions, this applies the system calls of the block and the withdrawals,
+/// so that the post-state root can be compared with the header.
+pub fn execute_with_revm(input: &EthBlockInput) -> anyhow::Result<RevmRun> {
+    let header = &input.block_header;
+    let block_number = header.number;
+    let (spec, blob_params) = MAINNET_FORKS
+        .into_iter()
+        .find(|(activation, ..)| header.timestamp >= *activation)
+        .map(|(_, spec, blob_params)| (spec, blob_params))
+        .with_context(|| {
+            format!(
+                "block {block_number} precedes Cancun, which is not supported by revm execution"
+            )
+        })?;
+
+    let ancestors = witness_headers(&input.execution_witness)?;
+    let parent = ancestors
+        .last()
+        .filter(|parent| parent.number + 1 == block_number)
+        .with_context(|| format!("parent of block {block_number} is not in the witness"))?;
+    let db = WitnessDb {
+        nodes: input
+            .execution_witness
+            .state
+            .iter()
+            .map(|node| (keccak256(node), node.clone()))
+            .collect(),
+        codes: input
+            .execution_witness
+            .codes
+            .iter()
+            .map(|code| (keccak256(code), code.clone()))
+            .collect(),
+        state_root: parent.state_root,
+        block_hashes: ancestors
+            .iter()
+            .map(|header| (header.number, header.hash_slow()))
+            .collect(),
+    };
+
+    let mut cache = CacheDB::new(&db);
+    let mut transactions = Vec::with_capacity(input.transactions.len());
+    let state_root = execute_block(&mut cache, input, spec, blob_params, &mut transactions)
+        .and_then(|()| post_state_root(&db, &cache));
+    Ok(RevmRun {
+        outcome: BlockExecutionOutcome::new(block_number, transactions),
+        state_root,
+    })
+}
+
+fn execute_block(
+    db: &mut CacheDB<&WitnessDb>,
+    input: &EthBlockInput,
+    spec: SpecId,
+    blob_params: BlobParams,
+    outcomes: &mut Vec<TxOutcome>,
+) -> anyhow::Result<()> {
+    let header = &input.block_header;
+    let mut cfg = CfgEnv::new_with_spec(spec);
+    cfg.chain_id = MAINNET_CHAIN_ID;
+    let mut evm = Context::mainnet()
+        .with_db(&mut *db)
+        .with_cfg(cfg)
+        .with_block(block_env(header, blob_params))
+        .build_mainnet();
+
+    let mut pre_block_calls = Vec::new();
+    if let Some(beacon_root) = header.parent_beacon_block_root {
+        pre_block_calls.push((BEACON_ROOTS_ADDRESS, Bytes::from(beacon_root)));
+    }
+    if spec >= SpecId::PRAGUE {
+        pre_block_calls.push((HISTORY_STORAGE_ADDRESS, Bytes::from(header.parent_hash)));
+    }
+    for (address, data) in pre_block_calls {
+        evm.transact_system_call_with_caller_commit(SYSTEM_ADDRESS, address, data)
+            .map_err(|err| anyhow::anyhow!("system call to {address} failed: {err}"))?;
+    }
+
+    for tx in &input.transactions {
+        let tx_hash = *tx.inner.tx_hash();
+        let result = evm.transact_commit(tx_env(tx)).map_err(|err| {
+            anyhow::anyhow!("transaction {tx_hash} is invalid according to revm: {err}")
+        })?;
+        outcomes.push(TxOutcome {
+            tx_hash,
+            tx_type: tx.inner.tx_type(),
+            status: result.is_success(),
+            gas_used: result.gas_used(),
+            logs: result.into_logs(),
+        });
+    }
+
+    // The requests produced by these calls are not compared, but the calls change the state.
+    if spec >= SpecId::PRAGUE {
+        for address in [
+            WITHDRAWAL_REQUEST_PREDEPLOY_ADDRESS,
+            CONSOLIDATION_REQUEST_PREDEPLOY_ADDRESS,
+        ] {
+            evm.transact_system_call_with_caller_commit(SYSTEM_ADDRESS, address, Bytes::new())
+                .map_err(|err| anyhow::anyhow!("system call to {address} failed: {err}"))?;
+        }
+    }
+    drop(evm);
+
+    // Withdrawals are applied last, like in the execution clients.
+    if !input.withdrawals_rlp.is_empty() {
+        let withdrawals = Withdrawals::decode(&mut input.withdrawals_rlp.as_slice())
+            .context("failed to decode withdrawals")?;
+        apply_withdrawals(db, &withdrawals)?;
+    }
+    Ok(())
+}
+
+/// Credits the withdrawals, marking the recipients as touched so that they get into the post-state.
+fn apply_withdrawals(
+    db: &mut CacheDB<&WitnessDb>,
+    withdrawals: &[Withdrawal],
+) -> anyhow::Result<()> {
+    for withdrawal in withdrawals {
+        let account = db.load_account(withdrawal.address)?;
+        account.info.balance += withdrawal.amount_wei();
+        account.account_state = match account.account_state {
+            // The recipient didn't exist before, so it's created with empty storage.
+            AccountState::NotExisting => AccountState::StorageCleared,
+            AccountState::None => AccountState::Touched,
+            state => state,
+        };
+    }
+    Ok(())
+}
+
+fn block_env(header: &Header, blob_params: BlobParams) -> BlockEnv {
+    BlockEnv {
+        number: U256::from(header.number),
+        beneficiary: header.beneficiary,
+        timestamp: U256::from(header.timestamp),
+        gas_limit: header.gas_limit,
+        basefee: header.base_fee_per_gas.unwrap_or_default(),
+        difficulty: header.difficulty,
+        prevrandao: Some(header.mix_hash),
+        blob_excess_gas_and_price: header.excess_blob_gas.map(|excess_blob_gas| {
+            BlobExcessGasAndPrice::new(excess_blob_gas, blob_params.update_fraction as u64)
+        }),
+    }
+}
+
+fn tx_env(tx: &Transaction) -> TxEnv {
+    let envelope = tx.inner.inner();
+    TxEnv {
+        tx_type: tx.inner.tx_type() as u8,
+        caller: tx.inner.signer(),
+        gas_limit: envelope.gas_limit(),
+        // For legacy transactions, this is the gas price.
+        gas_price: envelope.max_fee_per_gas(),
+        kind: envelope.kind(),
+        value: envelope.value(),
+        data: envelope.input().clone(),
+        nonce: envelope.nonce(),
+        chain_id: envelope.chain_id(),
+        access_list: envelope.access_list().cloned().unwrap_or_default(),
+        gas_priority_fee: envelope.max_priority_fee_per_gas(),
+        blob_hashes: envelope
+            .blob_versioned_hashes()
+            .map(<[B256]>::to_vec)
+            .unwrap_or_default(),
+        max_fee_per_blob_gas: envelope.max_fee_per_blob_gas().unwrap_or_default(),
+        authorization_list: envelope
+            .authorization_list()
+            .map(|list| list.iter().cloned().map(Either::Left).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Applies the changes made by the block to the parent state and returns the new state root.
+fn post_state_root(db: &WitnessDb, cache: &CacheDB<&WitnessDb>) -> anyhow::Result<B256> {
+    let mut state = SparseTrie::new(&db.nodes, db.state_root);
+    for (address, account) in &cache.cache.accounts {
+        // Accounts that were only read keep their state; in particular, empty accounts are only
+        // removed once they are touched (EIP-161).
+        if matches!(account.account_state, AccountState::None) {
+            continue;
+        }
+        let hashed_address = keccak256(address);
+        // Covers self-destructed accounts, and empty accounts touched by the block.
+        if matches!(account.account_state, AccountState::NotExisting) || account.info.is_empty() {
+            state
+                .remove(hashed_address)
+                .with_context(|| format!("failed to remove account {address}"))?;
+            continue;
+        }
+
+        let storage_root = match account.account_state {
+            AccountState::NotExisting | AccountState::StorageCleared => EMPTY_ROOT_HASH,
+            AccountState::Touched | AccountState::None => db
+                .account(*address)?
+                .map_or(EMPTY_ROOT_HASH, |account| account.storage_root),
+        };
+        let mut storage = SparseTrie::new(&db.nodes, storage_root);
+        for (slot, value) in &account.storage {
+            let hashed_slot = keccak256(slot.to_be_bytes::<32>());
+            if value.is_zero() {
+                storage.remove(hashed_slot)
+            } else {
+                storage.insert(hashed_slot, alloy::rlp::encode(value))
+            }
+            .with_context(|| format!("failed to update slot {slot} of account {address}"))?;
+        }
+
+        let trie_account = TrieAccount {
+            nonce: account.info.nonce,
+            balance: account.info.balance,
+            storage_root: storage.root(),
+            code_hash: account.info.code_hash,
+        };
+        state
+            .insert(hashed_address, alloy::rlp::encode(trie_account))
+            .with_context(|| format!("failed to update account {address}"))?;
+    }
+    Ok(state.root())
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0:#}")]
+pub struct WitnessDbError(anyhow::Error);
+
+impl From<anyhow::Error> for WitnessDbError {
+    fn from(err: anyhow::Error) -> Self {
+        Self(err)
+    }
+}
+
+impl DBErrorMarker for WitnessDbError {}
+
+/// Pre-state of the block, read from the execution witness.
+#[derive(Debug)]
+struct WitnessDb {
+    nodes: WitnessNodes,
+    codes: HashMap<B256, Bytes>,
+    state_root: B256,
+    block_hashes: HashMap<u64, B256>,
+}
+
+impl WitnessDb {
+    fn account(&self, address: Address) -> anyhow::Result<Option<TrieAccount>> {
+        SparseTrie::new(&self.nodes, self.state_root)
+            .get(keccak256(address))
+            .with_context(|| format!("failed to look up account {address}"))?
+            .map(|encoded| {
+                TrieAccount::decode(&mut encoded.as_slice())
+                    .with_context(|| format!("failed to decode account {address}"))
+            })
+            .transpose()
+    }
+
+    fn code(&self, code_hash: B256) -> anyhow::Result<Bytecode> {
+        if code_hash == KECCAK_EMPTY {
+            return Ok(Bytecode::default());
+        }
+        let code = self
+            .codes
+            .get(&code_hash)
+            .with_context(|| format!("code {code_hash} is not in the witness"))?;
+        Ok(Bytecode::new_raw(code.clone()))
+    }
+}
+
+impl DatabaseRef for WitnessDb {
+    type Error = WitnessDbError;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        let Some(account) = self.account(address)? else {
+            return Ok(None);
+        };
+        let code = self.code(account.code_hash)?;
+        Ok(Some(AccountInfo::new(
+            account.balance,
+            account.nonce,
+            account.code_hash,
+            code,
+        )))
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        Ok(self.code(code_hash)?)
+    }
+
+    fn storage_ref(&self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        let Some(account) = self.account(address)? else {
+            return Ok(U256::ZERO);
+        };
+        let value = SparseTrie::new(&self.nodes, account.storage_root)
+            .get(keccak256(index.to_be_bytes::<32>()))
+            .with_context(|| format!("failed to look up slot {index} of account {address}"))?;
+        let Some(encoded) = value else {
+            return Ok(U256::ZERO);
+        };
+        Ok(U256::decode(&mut encoded.as_slice())
+            .with_context(|| format!("failed to decode slot {index} of account {address}"))?)
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        Ok(self
+            .block_hashes
+            .get(&number)
+            .copied()
+            .with_context(|| format!("header of block {number} is not in the witness"))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use alloy::{
+        consensus::TxType,
+        eips::eip4895::Withdrawal,
+        primitives::{Address, B256, Bytes, Log, U256, keccak256},
+        trie::TrieAccount,
+    };
+    use revm::database::{AccountState, CacheDB, DbAccount};
+
+    use super::{DivergenceKind, WitnessDb, apply_withdrawals, compare_outcomes, post_state_root};
+    use crate::prover::outcome::{BlockExecutionOutcome, TxOutcome};
+    use crate::prover::sparse_trie::build_trie;
+
+    fn tx(status: bool, gas_used: u64, logs: &[&'static [u8]]) -> TxOutcome {
+        TxOutcome {
+            tx_hash: B256::repeat_byte(gas_used as u8),
+            tx_type: TxType::Eip1559,
+            status,
+            gas_used,
+            logs: logs
+                .iter()
+                .map(|data| Log::new_unchecked(Address::ZERO, Vec::new(), Bytes::from_static(data)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn outcomes_are_compared_per_transaction() {
+        let stf = BlockExecutionOutcome::new(
+            5,
+            vec![tx(true, 21_000, &[]), tx(true, 50_000, &[b"a", b"b"])],
+        );
+        let revm = BlockExecutionOutcome::new(
+            5,
+            vec![
+                tx(true, 21_000, &[]),
+                tx(false, 50_000, &[b"a", b"c", b"d"]),
+                tx(true, 30_000, &[]),
+            ],
+        );
+        assert!(compare_outcomes(&stf, &stf).is_empty());
+
+        let divergences = compare_outcomes(&stf, &revm);
+        let kinds: Vec<_> = divergences.iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                DivergenceKind::Status,
+                DivergenceKind::LogCount,
+                DivergenceKind::Log
+            ]
+        );
+        assert!(divergences.iter().all(|d| d.tx_index == 1));
+        assert_eq!(divergences[2].log_index, Some(1));
+        assert_eq!(
+            divergences[0].to_string(),
+            format!(
+                "transaction #1 ({}): Status mismatch: STF = true, revm = false",
+                divergences[0].tx_hash
+            )
+        );
+    }
+
+    #[test]
+    fn only_touched_empty_accounts_are_removed() {
+        let read = Address::repeat_byte(1);
+        let touched = Address::repeat_byte(2);
+        let empty_account = alloy::rlp::encode(TrieAccount::default());
+        let entries: BTreeMap<_, _> = [read, touched]
+            .iter()
+            .map(|address| (keccak256(address), empty_account.clone()))
+            .collect();
+        let (state_root, nodes) = build_trie(&entries);
+        let db = WitnessDb {
+            nodes,
+            codes: HashMap::new(),
+            state_root,
+            block_hashes: HashMap::new(),
+        };
+
+        let mut cache = CacheDB::new(&db);
+        for (address, account_state) in
+            [(read, AccountState::None), (touched, AccountState::Touched)]
+        {
+            let account = DbAccount {
+                account_state,
+                ..Default::default()
+            };
+            cache.cache.accounts.insert(address, account);
+        }
+
+        let expected = build_trie(&BTreeMap::from([(keccak256(read), empty_account)])).0;
+        assert_eq!(post_state_root(&db, &cache).unwrap(), expected);
+    }
+
+    #[test]
+    fn withdrawal_recipients_are_in_the_post_state() {
+        let existing = Address::repeat_byte(1);
+        let fresh = Address::repeat_byte(2);
+        let account = TrieAccount {
+            balance: U256::from(1),
+            ..Default::default()
+        };
+        let (state_root, nodes) = build_trie(&BTreeMap::from([(
+            keccak256(existing),
+            alloy::rlp::encode(account),
+        )]));
+        let db = WitnessDb {
+            nodes,
+            codes: HashMap::new(),
+            state_root,
+            block_hashes: HashMap::new(),
+        };
+
+        let mut cache = CacheDB::new(&db);
+        let withdrawals: Vec<_> = [existing, fresh]
+            .into_iter()
+            .enumerate()
+            .map(|(index, address)| Withdrawal {
+                index: index as u64,
+                validator_index: 0,
+                address,
+                amount: 1,
+            })
+            .collect();
+        apply_withdrawals(&mut cache, &withdrawals).unwrap();
+
+        let gwei = U256::from(1_000_000_000u64);
+        let expected = build_trie(&BTreeMap::from([
+            (
+                keccak256(existing),
+                alloy::rlp::encode(TrieAccount {
+                    balance: gwei + U256::from(1),
+                    ..Default::default()
+                }),
+            ),
+            (
+                keccak256(fresh),
+                alloy::rlp::encode(TrieAccount {
+                    balance: gwei,
+                    ..Default::default()
+                }),
+            ),
+        ]))
+        .0;
+        assert_eq!(post_state_root(&db, &cache).unwrap(), expected);
+    }
+}
@@ -1,10 +1,12 @@
 use anyhow::Context as _;
+use async_trait::async_trait;
 use oracle_provider::ZkEENonDeterminismSource;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 use crate::observability;
+use crate::prover::backend::{BackendKind, BackendOutput, PreparedBlock, ProvingBackend};
 
 #[derive(Debug)]
 pub struct ProofResult {
@@ -16,6 +18,8 @@ pub struct ProofResult {
 pub struct Prover {
     app_bin_path: PathBuf,
     worker_threads: Option<usize>,
+    /// CUDA devices the prover runs on; all visible devices if not set.
+    devices: Option<Vec<usize>>,
     inner: Arc<Mutex<Option<execution_utils::unrolled_gpu::UnrolledProver>>>,
 }
 
@@ -27,15 +31,35 @@ impl std::fmt::Debug for Prover {
 
 impl Prover {
     pub fn new(app_bin_path: &Path, worker_threads: Option<usize>) -> anyhow::Result<Self> {
-        let inner = create_unrolled_prover(app_bin_path, worker_threads).with_context(|| {
-            format!(
-                "failed to create unrolled prover with app binary at {:?}",
-                app_bin_path
-            )
-        })?;
+        Self::create(app_bin_path, worker_threads, None)
+    }
+
+    /// Creates a prover that only uses the given CUDA devices, so that several provers can run
+    /// side by side on one machine.
+    pub fn for_devices(
+        app_bin_path: &Path,
+        worker_threads: Option<usize>,
+        devices: Vec<usize>,
+    ) -> anyhow::Result<Self> {
+        Self::create(app_bin_path, worker_threads, Some(devices))
+    }
+
+    fn create(
+        app_bin_path: &Path,
+        worker_threads: Option<usize>,
+        devices: Option<Vec<usize>>,
+    ) -> anyhow::Result<Self> {
+        let inner = create_unrolled_prover(app_bin_path, worker_threads, devices.as_deref())
+            .with_context(|| {
+                format!(
+                    "failed to create unrolled prover with app binary at {:?} on devices {:?}",
+                    app_bin_path, devices
+                )
+            })?;
         Ok(Self {
             app_bin_path: app_bin_path.to_path_buf(),
             worker_threads,
+            devices,
             inner: Arc::new(Mutex::new(Some(inner))),
         })
     }
@@ -92,6 +116,7 @@ impl Prover {
                     let replacement = create_unrolled_prover(
                         self.app_bin_path.as_path(),
                         self.worker_threads,
+                        self.devices.as_deref(),
                     )
                     .with_context(|| {
                         format!(
@@ -108,7 +133,7 @@ impl Prover {
         };
 
         let proving_time_secs = start.elapsed().as_secs_f64();
-        let proof_bytes = bincode::serde::encode_to_vec(&proof, bincode::config::standard())
+        let proof_bytes = serialize_proof(&proof)
             .with_context(|| format!("failed to encode proof bytes for block {block_number}"))?;
         Ok(ProofResult {
             proof_bytes,
@@ -118,7 +143,41 @@ impl Prover {
     }
 }
 
-fn strip_bin_suffix(path: &Path) -> anyhow::Result<String> {
+#[async_trait]
+impl ProvingBackend for Prover {
+    fn name(&self) -> &'static str {
+        "gpu_prove"
+    }
+
+    fn kind(&self) -> BackendKind {
+        BackendKind::Proof
+    }
+
+    async fn process(&mut self, block: PreparedBlock) -> anyhow::Result<BackendOutput> {
+        let block_number = block.block_number();
+        let (_, oracle) = block.into_parts().with_context(|| {
+            format!("failed to build the proving oracle for block {block_number}")
+        })?;
+
+        tracing::info!("Proving block {} on GPU", block_number);
+        let proof_result = self
+            .prove(block_number, oracle)
+            .await
+            .with_context(|| format!("failed to prove block {block_number}"))?;
+        Ok(BackendOutput::Proof(proof_result))
+    }
+}
+
+/// Encodes a proof the way it's stored, submitted and verified, regardless of the prover.
+pub(crate) fn serialize_proof(proof: &impl serde::Serialize) -> anyhow::Result<Vec<u8>> {
+    Ok(bincode::serde::encode_to_vec(
+        proof,
+        bincode::config::standard(),
+    )?)
+}
+
+/// Returns the path prefix the prover artifacts (`app.bin`, `app.text`) share.
+pub(crate) fn strip_bin_suffix(path: &Path) -> anyhow::Result<String> {
     let path_str = path
         .to_str()
         .ok_or_else(|| anyhow::anyhow!("app path is not valid UTF-8"))?;
@@ -132,6 +191,7 @@ fn strip_bin_suffix(path: &Path) -> anyhow::Result<String> {
 fn create_unrolled_prover(
     app_bin_path: &Path,
     worker_threads: Option<usize>,
+    devices: Option<&[usize]>,
 ) -> anyhow::Result<execution_utils::unrolled_gpu::UnrolledProver> {
     let base_path = strip_bin_suffix(app_bin_path)?;
     let mut configuration =
@@ -140,6 +200,12 @@ fn create_unrolled_prover(
         configuration.max_thread_pool_threads = Some(threads);
         configuration.replay_worker_threads_count = threads;
     }
+    if let Some(devices) = devices {
+        // `device_ids` of the airbender `ExecutionProverConfiguration` (zksync-airbender, `dev`
+        // branch): an airbender revision without it fails to build instead of silently proving on
+        // every device. `gpu_device_groups_use_their_own_devices` checks the pinning on real GPUs.
+        configuration.device_ids = Some(devices.to_vec());
+    }
 
     let unrolled_prover = execution_utils::unrolled_gpu::UnrolledProver::new(
         &base_path,
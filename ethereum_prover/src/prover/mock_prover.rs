@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use alloy::primitives::keccak256;
+use anyhow::Context as _;
+use async_trait::async_trait;
+
+use crate::prover::backend::{BackendKind, BackendOutput, PreparedBlock, ProvingBackend};
+use crate::prover::cpu_witness::CpuWitnessGenerator;
+use crate::prover::gpu_prover::ProofResult;
+use crate::prover::types::EthBlockInput;
+
+/// Size of the synthetic proof, in bytes.
+const MOCK_PROOF_LEN: usize = 1024;
+/// Number of cycles reported for every synthetic proof.
+pub const MOCK_PROOF_CYCLES: u64 = 1_000_000;
+
+/// Prover that runs the STF in forward-run mode, but returns a synthetic proof instead of
+/// actually proving the block.
+///
+/// Intended for end-to-end pipeline tests on machines without a GPU: the output depends
+/// only on the block number, so it is stable across runs.
+#[derive(Debug, Clone)]
+pub struct MockProver {
+    witness_generator: CpuWitnessGenerator,
+    latency: Duration,
+}
+
+impl MockProver {
+    /// Creates a mock prover that additionally waits for `latency` before returning
+    /// each proof, to emulate the proving time.
+    pub fn new(app_bin_path: PathBuf, latency: Duration) -> Self {
+        Self {
+            witness_generator: CpuWitnessGenerator::new(app_bin_path),
+            latency,
+        }
+    }
+
+    /// Runs the block in forward-run mode, checking the result against the canonical header,
+    /// and returns a synthetic proof for it.
+    pub async fn prove(&self, input: EthBlockInput) -> anyhow::Result<ProofResult> {
+        self.prove_block(PreparedBlock::new(input)).await
+    }
+
+    async fn prove_block(&self, block: PreparedBlock) -> anyhow::Result<ProofResult> {
+        let start = Instant::now();
+        let block_number = block.block_number();
+        let (input, oracle) = block.into_parts().with_context(|| {
+            format!("failed to build the proving oracle for block {block_number}")
+        })?;
+        self.witness_generator
+            .forward_run(block_number, &input.transactions, oracle)
+            .await
+            .and_then(|outcome| outcome.check_against(&input.block_header))
+            .with_context(|| format!("failed to perform forward run for block {block_number}"))?;
+        tokio::time::sleep(self.latency).await;
+
+        Ok(ProofResult {
+            proof_bytes: mock_proof_bytes(block_number),
+            cycles: MOCK_PROOF_CYCLES,
+            proving_time_secs: start.elapsed().as_secs_f64(),
+        })
+    }
+}
+
+#[async_trait]
+impl ProvingBackend for MockProver {
+    fn name(&self) -> &'static str {
+        "mock_prove"
+    }
+
+    fn kind(&self) -> BackendKind {
+        BackendKind::Proof
+    }
+
+    async fn process(&mut self, block: PreparedBlock) -> anyhow::Result<BackendOutput> {
+        tracing::info!("Generating mock proof for block {}", block.block_number());
+        let proof_result = self.prove_block(block).await?;
+        Ok(BackendOutput::Proof(proof_result))
+    }
+}
+
+/// Expands the block number into [`MOCK_PROOF_LEN`] bytes using a keccak hash chain.
+fn mock_proof_bytes(block_number: u64) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(MOCK_PROOF_LEN);
+    let mut digest = keccak256(block_number.to_be_bytes());
+    while bytes.len() < MOCK_PROOF_LEN {
+        bytes.extend_from_slice(digest.as_slice());
+        digest = keccak256(digest);
+    }
+    bytes.truncate(MOCK_PROOF_LEN);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MOCK_PROOF_LEN, mock_proof_bytes};
+
+    #[test]
+    fn mock_proof_bytes_are_deterministic() {
+        let first = mock_proof_bytes(24073997);
+        assert_eq!(first.len(), MOCK_PROOF_LEN);
+        assert_eq!(first, mock_proof_bytes(24073997));
+        assert_ne!(first, mock_proof_bytes(24073998));
+    }
+}
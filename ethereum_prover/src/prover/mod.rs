@@ -3,7 +3,17 @@
 //! and the interfaces ZKsync OS provides, making it easier to use ZKsync OS
 //! functionality in the context of the Ethereum prover.
 
+pub mod backend;
+pub mod cpu_prover;
 pub mod cpu_witness;
+pub mod debugger;
+pub mod diff_exec;
 pub mod gpu_prover;
+pub mod mock_prover;
 pub mod oracle;
+pub mod outcome;
+pub mod profiler;
+pub mod sparse_trie;
+pub mod tracer;
 pub mod types;
+pub mod witness;
@@ -1,5 +1,6 @@
 use alloy::consensus::Header;
 use alloy::rlp::{Decodable, Encodable};
+use alloy::rpc::types::debug::ExecutionWitness;
 use anyhow::{anyhow, bail};
 use basic_system::system_implementation::ethereum_storage_model::caches::account_properties::EthereumAccountProperties;
 use basic_system::system_implementation::ethereum_storage_model::{
@@ -22,9 +23,10 @@ use zk_ee::utils::Bytes32;
 
 use crate::prover::types::EthBlockInput;
 
-pub fn build_oracle(input: EthBlockInput) -> anyhow::Result<ZkEENonDeterminismSource> {
-    let mut headers: Vec<Header> = input
-        .execution_witness
+/// Decodes the ancestor headers of the execution witness, which are sorted from the oldest one
+/// to the parent of the block.
+pub fn witness_headers(witness: &ExecutionWitness) -> anyhow::Result<Vec<Header>> {
+    let headers: Vec<Header> = witness
         .headers
         .iter()
         .map(|el| {
@@ -39,7 +41,11 @@ pub fn build_oracle(input: EthBlockInput) -> anyhow::Result<ZkEENonDeterminismSo
     if !headers.is_sorted_by(|a, b| a.number < b.number) {
         bail!("execution witness headers are not sorted");
     }
+    Ok(headers)
+}
 
+pub fn build_oracle(input: EthBlockInput) -> anyhow::Result<ZkEENonDeterminismSource> {
+    let mut headers = witness_headers(&input.execution_witness)?;
     headers.reverse();
 
     let mut headers_encodings: Vec<_> = input
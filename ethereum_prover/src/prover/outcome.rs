@@ -0,0 +1,163 @@
+//! Results of executing a block with the STF, and their comparison with the canonical header.
+
+use alloy::{
+    consensus::{Header, Receipt, ReceiptEnvelope, TxType, proofs::calculate_receipt_root},
+    primitives::{B256, Bloom, Log},
+};
+
+/// Result of a single transaction as reported by the STF.
+#[derive(Debug, Clone)]
+pub struct TxOutcome {
+    pub tx_hash: B256,
+    pub tx_type: TxType,
+    pub status: bool,
+    pub gas_used: u64,
+    pub logs: Vec<Log>,
+}
+
+/// Result of executing a block with the STF.
+///
+/// The post-state root is not part of the outcome: the STF checks it against the target header
+/// itself and fails the run on a mismatch.
+#[derive(Debug, Clone)]
+pub struct BlockExecutionOutcome {
+    pub block_number: u64,
+    pub transactions: Vec<TxOutcome>,
+    pub gas_used: u64,
+    pub receipts_root: B256,
+    pub logs_bloom: Bloom,
+}
+
+impl BlockExecutionOutcome {
+    /// Derives the block-level fields from the results of the transactions.
+    pub fn new(block_number: u64, transactions: Vec<TxOutcome>) -> Self {
+        let mut outcome = Self {
+            block_number,
+            transactions,
+            gas_used: 0,
+            receipts_root: B256::ZERO,
+            logs_bloom: Bloom::ZERO,
+        };
+        outcome.gas_used = outcome.transactions.iter().map(|tx| tx.gas_used).sum();
+        outcome.receipts_root = calculate_receipt_root(&outcome.receipts());
+        outcome.logs_bloom = outcome
+            .transactions
+            .iter()
+            .flat_map(|tx| &tx.logs)
+            .collect();
+        outcome
+    }
+
+    /// Receipts of the transactions, in the form they are committed to by the receipts root.
+    pub fn receipts(&self) -> Vec<ReceiptEnvelope> {
+        let mut cumulative_gas_used = 0;
+        self.transactions
+            .iter()
+            .map(|tx| {
+                cumulative_gas_used += tx.gas_used;
+                let receipt = Receipt {
+                    status: tx.status.into(),
+                    cumulative_gas_used,
+                    logs: tx.logs.clone(),
+                };
+                ReceiptEnvelope::from_typed(tx.tx_type, receipt.with_bloom())
+            })
+            .collect()
+    }
+
+    /// Compares the outcome with the canonical header of the block, so that blocks the STF
+    /// executes differently from Ethereum are caught even if the run itself succeeds.
+    pub fn check_against(&self, header: &Header) -> anyhow::Result<()> {
+        let block_number = self.block_number;
+        let mut mismatches = Vec::new();
+        if self.gas_used != header.gas_used {
+            mismatches.push(format!(
+                "gas used: STF = {}, header = {}",
+                self.gas_used, header.gas_used
+            ));
+        }
+        if self.receipts_root != header.receipts_root {
+            mismatches.push(format!(
+                "receipts root: STF = {}, header = {}",
+                self.receipts_root, header.receipts_root
+            ));
+        }
+        if self.logs_bloom != header.logs_bloom {
+            mismatches.push("logs bloom".to_owned());
+        }
+        anyhow::ensure!(
+            mismatches.is_empty(),
+            "execution of block {block_number} diverges from the canonical header: {}",
+            mismatches.join("; ")
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        consensus::{Header, TxType},
+        primitives::{Address, B256, Bytes, Log},
+        trie::EMPTY_ROOT_HASH,
+    };
+
+    use super::{BlockExecutionOutcome, TxOutcome};
+
+    fn tx(tx_type: TxType, status: bool, gas_used: u64, logs: Vec<Log>) -> TxOutcome {
+        TxOutcome {
+            tx_hash: B256::ZERO,
+            tx_type,
+            status,
+            gas_used,
+            logs,
+        }
+    }
+
+    #[test]
+    fn empty_block_has_empty_receipts_root() {
+        let outcome = BlockExecutionOutcome::new(1, Vec::new());
+        assert_eq!(outcome.receipts_root, EMPTY_ROOT_HASH);
+        assert_eq!(outcome.gas_used, 0);
+        outcome
+            .check_against(&Header {
+                receipts_root: EMPTY_ROOT_HASH,
+                ..Default::default()
+            })
+            .expect("empty block matches");
+    }
+
+    #[test]
+    fn divergences_from_header_are_reported() {
+        let log = Log::new_unchecked(
+            Address::repeat_byte(1),
+            vec![B256::repeat_byte(2)],
+            Bytes::from_static(b"data"),
+        );
+        let outcome = BlockExecutionOutcome::new(
+            7,
+            vec![
+                tx(TxType::Legacy, true, 21_000, Vec::new()),
+                tx(TxType::Eip1559, false, 50_000, vec![log]),
+            ],
+        );
+        assert_eq!(outcome.gas_used, 71_000);
+        assert_eq!(outcome.receipts()[1].cumulative_gas_used(), 71_000);
+        let header = Header {
+            gas_used: outcome.gas_used,
+            receipts_root: outcome.receipts_root,
+            logs_bloom: outcome.logs_bloom,
+            ..Default::default()
+        };
+        outcome.check_against(&header).expect("outcome matches");
+
+        let mut failed = outcome.transactions.clone();
+        failed[0].status = false;
+        let err = BlockExecutionOutcome::new(7, failed)
+            .check_against(&header)
+            .unwrap_err();
+        let err = err.to_string();
+        assert!(err.contains("receipts root"), "{err}");
+        assert!(!err.contains("gas used"), "{err}");
+    }
+}
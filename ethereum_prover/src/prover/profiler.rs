@@ -0,0 +1,501 @@
+//! Cycle profiler for the STF running in the RISC-V simulator.
+//!
+//! The simulator is stepped cycle by cycle, and every `sample_interval` cycles the call stack of
+//! the program is unwound through the frame pointers and symbolized with the ELF of `app.bin`.
+//! Samples are aggregated into folded stacks (the input format of `flamegraph.pl` and `inferno`),
+//! and attributed to precompiles and delegations by the names of the functions on the stack.
+//! Transaction boundaries are detected on every cycle, so cycles per transaction are exact.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use alloy::primitives::B256;
+use anyhow::Context as _;
+use object::{Object as _, ObjectSymbol as _, SymbolKind};
+use oracle_provider::ZkEENonDeterminismSource;
+use risc_v_simulator::abstractions::memory::{MemorySource as _, VectorMemoryImpl};
+use risc_v_simulator::cycle::IWithoutByteAccessIsaConfigWithDelegation;
+use risc_v_simulator::cycle::state::RiscV32State;
+use risc_v_simulator::mmu::NoMMU;
+use serde::Serialize;
+
+use crate::observability;
+
+/// Address `app.bin` is loaded at and starts executing from.
+const ENTRY_POINT: u32 = 0;
+/// Size of the simulated memory.
+const MEMORY_SIZE: usize = 1 << 30;
+/// Index of the frame pointer (`s0`) register.
+const FP_REGISTER: usize = 8;
+/// Index of the return address (`ra`) register.
+const RA_REGISTER: usize = 1;
+/// Stacks deeper than this are truncated, which also protects against corrupted frame chains.
+const MAX_STACK_DEPTH: usize = 256;
+/// Frame reported for addresses without a symbol.
+const UNKNOWN_FRAME: &str = "[unknown]";
+
+/// The bootloader reports the result of every transaction to its result keeper, which is where
+/// `TxResultCallback` is invoked in forward-run mode. Entering this function marks the end of a
+/// transaction.
+const TX_BOUNDARY_FUNCTION: &str = "tx_processed";
+
+/// Precompiles, matched by the outermost function on the stack containing the pattern.
+const PRECOMPILES: &[(&str, &str)] = &[
+    ("ecrecover", "ecrecover"),
+    ("sha256", "sha256"),
+    ("ripemd160", "ripemd160"),
+    ("modexp", "modexp"),
+    ("ecadd", "bn254_add"),
+    ("ecmul", "bn254_mul"),
+    ("ecpairing", "bn254_pairing"),
+    ("blake2f", "blake2f"),
+    ("point_evaluation", "point_evaluation"),
+    ("bls12", "bls12_381"),
+    ("p256", "p256_verify"),
+];
+
+/// Delegated circuits, matched by the innermost function on the stack containing the pattern.
+const DELEGATIONS: &[(&str, &str)] = &[
+    ("blake2", "blake2s"),
+    ("bigint", "bigint"),
+    ("keccak", "keccak"),
+];
+
+/// Function symbols of the RISC-V program, sorted by address.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    functions: Vec<Function>,
+}
+
+#[derive(Debug)]
+struct Function {
+    start: u32,
+    end: u32,
+    name: String,
+}
+
+impl Symbols {
+    /// Loads the function symbols from the ELF `app.bin` was built from. `app.text` only holds
+    /// the instructions, so it can't be used to symbolize addresses.
+    pub fn load(elf_path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read(elf_path)
+            .with_context(|| format!("failed to read {}", elf_path.display()))?;
+        Self::from_elf(&data).with_context(|| format!("failed to parse {}", elf_path.display()))
+    }
+
+    pub fn from_elf(data: &[u8]) -> anyhow::Result<Self> {
+        let file = object::File::parse(data)?;
+        let mut functions = Vec::new();
+        for symbol in file.symbols() {
+            if symbol.kind() != SymbolKind::Text || symbol.size() == 0 {
+                continue;
+            }
+            let Ok(name) = symbol.name() else {
+                continue;
+            };
+            let start = u32::try_from(symbol.address())
+                .with_context(|| format!("symbol {name} is outside of the 32-bit address space"))?;
+            functions.push(Function {
+                start,
+                end: start.saturating_add(symbol.size() as u32),
+                name: format!("{:#}", rustc_demangle::demangle(name)),
+            });
+        }
+        anyhow::ensure!(!functions.is_empty(), "ELF has no function symbols");
+        Ok(Self::new(functions))
+    }
+
+    fn new(mut functions: Vec<Function>) -> Self {
+        functions.sort_by_key(|function| function.start);
+        Self { functions }
+    }
+
+    /// Returns the entry addresses of the functions whose names contain `pattern`.
+    pub fn entries(&self, pattern: &str) -> Vec<u32> {
+        self.functions
+            .iter()
+            .filter(|function| function.name.contains(pattern))
+            .map(|function| function.start)
+            .collect()
+    }
+
+    /// Returns the name of the function containing `address`.
+    pub fn lookup(&self, address: u32) -> Option<&str> {
+        let index = self
+            .functions
+            .partition_point(|function| function.start <= address)
+            .checked_sub(1)?;
+        let function = &self.functions[index];
+        (address < function.end).then_some(function.name.as_str())
+    }
+}
+
+/// Cycles spent on a single transaction.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TxCycles {
+    pub tx_index: usize,
+    pub tx_hash: Option<B256>,
+    pub cycles: u64,
+}
+
+/// Attribution of the cycles of a block. The total and the cycles per transaction are exact;
+/// the rest is estimated from samples, so it's precise up to the sampling interval.
+#[derive(Debug, Clone, Serialize)]
+pub struct CycleProfile {
+    pub block_number: u64,
+    pub total_cycles: u64,
+    pub sample_interval: u64,
+    /// Cycles from the end of the previous transaction (or the start of the block) to the end of
+    /// the transaction, so the first transaction includes the pre-block system calls.
+    pub transactions: Vec<TxCycles>,
+    /// Cycles after the last transaction: post-block system calls, withdrawals and the
+    /// computation of the state root.
+    pub finalization_cycles: u64,
+    pub precompiles: BTreeMap<String, u64>,
+    pub delegations: BTreeMap<String, u64>,
+    /// Cycles per call stack, keyed by the `;`-separated stack starting from the outermost frame.
+    #[serde(skip)]
+    pub folded_stacks: BTreeMap<String, u64>,
+}
+
+impl CycleProfile {
+    /// Renders the stacks in the folded format understood by `flamegraph.pl` and `inferno`.
+    pub fn folded(&self) -> String {
+        self.folded_stacks
+            .iter()
+            .map(|(stack, cycles)| format!("{stack} {cycles}\n"))
+            .collect()
+    }
+
+    /// Transactions sorted by the number of cycles, most expensive first.
+    pub fn heaviest_transactions(&self, count: usize) -> Vec<&TxCycles> {
+        let mut transactions: Vec<_> = self.transactions.iter().collect();
+        transactions.sort_by(|a, b| b.cycles.cmp(&a.cycles));
+        transactions.truncate(count);
+        transactions
+    }
+}
+
+/// Aggregates executed cycles and stack samples into a [`CycleProfile`].
+#[derive(Debug, Default)]
+struct ProfileBuilder {
+    total_cycles: u64,
+    folded_stacks: HashMap<String, u64>,
+    finished_transactions: Vec<u64>,
+    current_segment: u64,
+    precompiles: BTreeMap<String, u64>,
+    delegations: BTreeMap<String, u64>,
+}
+
+impl ProfileBuilder {
+    /// Counts an executed cycle; `enters_tx_boundary` is whether it's the first instruction of
+    /// the function marking the end of a transaction.
+    fn count_cycle(&mut self, enters_tx_boundary: bool) {
+        if enters_tx_boundary {
+            self.finished_transactions
+                .push(std::mem::take(&mut self.current_segment));
+        }
+        self.current_segment += 1;
+        self.total_cycles += 1;
+    }
+
+    /// Records a sample of `cycles` spent in `stack`, which starts from the outermost frame.
+    fn record(&mut self, stack: &[&str], cycles: u64) {
+        *self.folded_stacks.entry(stack.join(";")).or_default() += cycles;
+
+        if let Some(precompile) = stack.iter().find_map(|frame| classify(frame, PRECOMPILES)) {
+            *self.precompiles.entry(precompile.to_owned()).or_default() += cycles;
+        }
+        if let Some(delegation) = stack
+            .iter()
+            .rev()
+            .find_map(|frame| classify(frame, DELEGATIONS))
+        {
+            *self.delegations.entry(delegation.to_owned()).or_default() += cycles;
+        }
+    }
+
+    fn finish(self, block_number: u64, sample_interval: u64, tx_hashes: &[B256]) -> CycleProfile {
+        if self.finished_transactions.len() != tx_hashes.len() {
+            tracing::warn!(
+                "Found {} transaction boundaries in the profile of block {block_number}, but the block has {} transactions",
+                self.finished_transactions.len(),
+                tx_hashes.len()
+            );
+        }
+        let transactions = self
+            .finished_transactions
+            .into_iter()
+            .enumerate()
+            .map(|(tx_index, cycles)| TxCycles {
+                tx_index,
+                tx_hash: tx_hashes.get(tx_index).copied(),
+                cycles,
+            })
+            .collect();
+        CycleProfile {
+            block_number,
+            total_cycles: self.total_cycles,
+            sample_interval,
+            transactions,
+            finalization_cycles: self.current_segment,
+            precompiles: self.precompiles,
+            delegations: self.delegations,
+            folded_stacks: self.folded_stacks.into_iter().collect(),
+        }
+    }
+}
+
+fn classify(frame: &str, categories: &[(&str, &'static str)]) -> Option<&'static str> {
+    let frame = frame.to_ascii_lowercase();
+    categories
+        .iter()
+        .find(|(pattern, _)| frame.contains(pattern))
+        .map(|(_, name)| *name)
+}
+
+/// Unwinds the call stack through the frame pointers. Returns the addresses of the frames,
+/// starting from the innermost one (`pc` itself).
+///
+/// With frame pointers, the return address and the caller's frame pointer are saved right below
+/// the address the frame pointer points to.
+fn unwind(pc: u32, fp: u32, read_word: impl Fn(u32) -> Option<u32>) -> Vec<u32> {
+    let mut addresses = vec![pc];
+    let mut fp = fp;
+    while addresses.len() < MAX_STACK_DEPTH && fp >= 8 {
+        let (Some(ra), Some(caller_fp)) = (read_word(fp - 4), read_word(fp - 8)) else {
+            break;
+        };
+        // A return address below the first instruction means the frame chain is corrupted.
+        if ra < 4 {
+            break;
+        }
+        // The return address points after the call, step back into the calling instruction.
+        addresses.push(ra - 4);
+        // The stack grows down, so the frames of the callers are at higher addresses.
+        if caller_fp <= fp {
+            break;
+        }
+        fp = caller_fp;
+    }
+    addresses
+}
+
+/// Profiles the execution of blocks in the RISC-V simulator.
+#[derive(Debug, Clone)]
+pub struct CycleProfiler {
+    app_bin_path: PathBuf,
+    symbols: Arc<Symbols>,
+    sample_interval: u64,
+}
+
+impl CycleProfiler {
+    pub fn new(app_bin_path: PathBuf, symbols: Symbols, sample_interval: u64) -> Self {
+        Self {
+            app_bin_path,
+            symbols: Arc::new(symbols),
+            sample_interval: sample_interval.max(1),
+        }
+    }
+
+    /// Executes the block in the simulator and attributes its cycles.
+    pub async fn profile(
+        &self,
+        block_number: u64,
+        tx_hashes: Vec<B256>,
+        oracle: ZkEENonDeterminismSource,
+        cycle_limit: u64,
+    ) -> anyhow::Result<CycleProfile> {
+        let this = self.clone();
+        match observability::spawn_blocking_on_current_hub(move || {
+            this.profile_blocking(block_number, &tx_hashes, oracle, cycle_limit)
+        })
+        .await
+        {
+            Ok(result) => result,
+            Err(err) => {
+                let panic_msg = crate::utils::extract_panic_message(err);
+                Err(anyhow::anyhow!(
+                    "profiler task panicked while processing block {block_number}: {panic_msg}"
+                ))
+            }
+        }
+    }
+
+    fn profile_blocking(
+        &self,
+        block_number: u64,
+        tx_hashes: &[B256],
+        mut oracle: ZkEENonDeterminismSource,
+        cycle_limit: u64,
+    ) -> anyhow::Result<CycleProfile> {
+        let app_bin = std::fs::read(&self.app_bin_path)
+            .with_context(|| format!("failed to read {}", self.app_bin_path.display()))?;
+        let mut memory = VectorMemoryImpl::new_for_byte_size(MEMORY_SIZE);
+        memory.load_image(ENTRY_POINT, app_bin.into_iter());
+        let mut state =
+            RiscV32State::<IWithoutByteAccessIsaConfigWithDelegation>::initial(ENTRY_POINT);
+        let mut mmu = NoMMU { sapt: 0 };
+
+        let tx_boundaries = self.symbols.entries(TX_BOUNDARY_FUNCTION);
+        if tx_boundaries.is_empty() {
+            tracing::warn!(
+                "The ELF has no `{TX_BOUNDARY_FUNCTION}` function, so cycles can't be attributed to transactions"
+            );
+        }
+        let mut builder = ProfileBuilder::default();
+        let mut pending_cycles = 0;
+        let mut finished = false;
+        for _ in 0..cycle_limit {
+            builder.count_cycle(tx_boundaries.contains(&state.pc));
+            if pending_cycles == 0 {
+                // The innermost frame may not have saved its return address yet, so `ra` is
+                // used for the caller of a leaf function instead of the frame chain.
+                let mut addresses = unwind(state.pc, state.registers[FP_REGISTER], |address| {
+                    Some(memory.get_noexcept(address as u64))
+                });
+                if addresses.len() == 1 {
+                    addresses.push(state.registers[RA_REGISTER].wrapping_sub(4));
+                }
+                let stack: Vec<&str> = addresses
+                    .iter()
+                    .rev()
+                    .map(|&address| self.symbols.lookup(address).unwrap_or(UNKNOWN_FRAME))
+                    .collect();
+                builder.record(&stack, self.sample_interval);
+                pending_cycles = self.sample_interval;
+            }
+            pending_cycles -= 1;
+
+            let pc = state.pc;
+            state.cycle(&mut memory, &mut (), &mut mmu, &mut oracle);
+            // The program ends in a jump to itself.
+            if state.pc == pc {
+                finished = true;
+                break;
+            }
+        }
+        anyhow::ensure!(
+            finished,
+            "block {block_number} did not finish within the limit of {cycle_limit} cycles"
+        );
+        anyhow::ensure!(
+            state.registers[10..18] != [0; 8],
+            "simulator failed to execute block {block_number}"
+        );
+
+        Ok(builder.finish(block_number, self.sample_interval, tx_hashes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use alloy::primitives::B256;
+
+    use super::{Function, ProfileBuilder, Symbols, unwind};
+
+    fn symbols() -> Symbols {
+        Symbols::new(vec![
+            Function {
+                start: 0x200,
+                end: 0x300,
+                name: "bootloader::process_transaction".to_owned(),
+            },
+            Function {
+                start: 0x100,
+                end: 0x180,
+                name: "bootloader::run".to_owned(),
+            },
+        ])
+    }
+
+    #[test]
+    fn symbols_are_looked_up_by_address_range() {
+        let symbols = symbols();
+        assert_eq!(symbols.lookup(0x100), Some("bootloader::run"));
+        assert_eq!(symbols.lookup(0x17f), Some("bootloader::run"));
+        assert_eq!(symbols.lookup(0x180), None);
+        assert_eq!(
+            symbols.lookup(0x2ff),
+            Some("bootloader::process_transaction")
+        );
+        assert_eq!(symbols.lookup(0x50), None);
+    }
+
+    #[test]
+    fn stack_is_unwound_through_frame_pointers() {
+        // Frames at 0x1000 (innermost), 0x1100 and 0x1200 (outermost).
+        let memory = HashMap::from([
+            (0x1000 - 4, 0x254),
+            (0x1000 - 8, 0x1100),
+            (0x1100 - 4, 0x124),
+            (0x1100 - 8, 0x1200),
+            (0x1200 - 4, 0),
+            (0x1200 - 8, 0),
+        ]);
+        let addresses = unwind(0x400, 0x1000, |address| memory.get(&address).copied());
+        assert_eq!(addresses, [0x400, 0x250, 0x120]);
+    }
+
+    #[test]
+    fn unwinding_stops_at_corrupted_return_addresses() {
+        let memory = HashMap::from([(0x1000 - 4, 2), (0x1000 - 8, 0x1100)]);
+        let addresses = unwind(0x400, 0x1000, |address| memory.get(&address).copied());
+        assert_eq!(addresses, [0x400]);
+    }
+
+    #[test]
+    fn transaction_boundaries_are_found_by_entry_address() {
+        let symbols = Symbols::new(vec![
+            Function {
+                start: 0x100,
+                end: 0x180,
+                name: "bootloader::run".to_owned(),
+            },
+            Function {
+                start: 0x400,
+                end: 0x480,
+                name: "<ForwardRunningResultKeeper as ResultKeeperExt>::tx_processed".to_owned(),
+            },
+        ]);
+        assert_eq!(symbols.entries("tx_processed"), [0x400]);
+        assert!(symbols.entries("no_such_function").is_empty());
+    }
+
+    #[test]
+    fn cycles_are_attributed_to_transactions_and_precompiles() {
+        let mut builder = ProfileBuilder::default();
+        // Transactions end by entering the boundary function at cycles 60 and 105; the cycles
+        // spent in it count towards the next transaction.
+        for cycle in 0..206 {
+            builder.count_cycle(cycle == 60 || cycle == 105);
+        }
+
+        let run = "bootloader::run";
+        let tx = "bootloader::process_transaction";
+        let ecrecover = "system_hooks::precompiles::ecrecover";
+        let bigint = "crypto::bigint_delegation::mul";
+        builder.record(&[run], 10);
+        builder.record(&[run, tx, ecrecover, bigint], 30);
+        builder.record(&[run, tx, ecrecover], 20);
+        builder.record(&[run], 100);
+
+        let tx_hashes = [B256::repeat_byte(1), B256::repeat_byte(2)];
+        let profile = builder.finish(1, 5, &tx_hashes);
+        assert_eq!(profile.total_cycles, 206);
+        let cycles: Vec<_> = profile.transactions.iter().map(|tx| tx.cycles).collect();
+        assert_eq!(cycles, [60, 45]);
+        assert_eq!(profile.transactions[1].tx_hash, Some(tx_hashes[1]));
+        assert_eq!(profile.finalization_cycles, 101);
+        assert_eq!(profile.precompiles["ecrecover"], 50);
+        assert_eq!(profile.delegations["bigint"], 30);
+        assert_eq!(profile.heaviest_transactions(1)[0].tx_index, 0);
+
+        let folded = profile.folded();
+        assert!(folded.contains(&format!("{run};{tx};{ecrecover};{bigint} 30\n")));
+        assert!(folded.contains(&format!("{run} 110\n")));
+    }
+}
@@ -0,0 +1,424 @@
+//! Sparse Merkle Patricia trie backed by the nodes of an execution witness.
+//!
+//! Only the nodes on the paths of updated keys are decoded; the rest of the trie is kept as
+//! hashes. This is enough to compute the post-state root of a block executed outside of the STF,
+//! as the witness contains every node the execution touches. Lookups are also used to validate
+//! witnesses, so that both agree on how witness nodes are decoded.
+
+use std::collections::HashMap;
+
+use alloy::{
+    primitives::{B256, Bytes, keccak256},
+    rlp::{Decodable as _, EMPTY_STRING_CODE},
+    trie::{
+        EMPTY_ROOT_HASH, Nibbles, TrieMask,
+        nodes::{BranchNodeRef, ExtensionNodeRef, LeafNodeRef, RlpNode, TrieNode},
+    },
+};
+use anyhow::Context as _;
+
+/// Trie nodes of an execution witness, keyed by their hashes.
+pub type WitnessNodes = HashMap<B256, Bytes>;
+
+/// Trie node on the path to a key that is either not in the witness or can't be decoded,
+/// including its inlined children.
+#[derive(Debug, thiserror::Error)]
+#[error("trie node {hash} {reason}")]
+pub struct InvalidNode {
+    pub hash: B256,
+    reason: &'static str,
+}
+
+/// Contents of a witness node: the hashes of its children and the values of its leaves,
+/// with the inlined children expanded.
+#[derive(Debug, Default)]
+pub struct NodeContents {
+    pub children: Vec<B256>,
+    pub values: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Default)]
+enum Node {
+    #[default]
+    Empty,
+    /// Node that hasn't been decoded from the witness yet.
+    Hash(B256),
+    Leaf {
+        key: Nibbles,
+        value: Vec<u8>,
+    },
+    Extension {
+        key: Nibbles,
+        child: Box<Node>,
+    },
+    Branch {
+        children: Box<[Node; 16]>,
+    },
+}
+
+#[derive(Debug)]
+pub struct SparseTrie<'a> {
+    nodes: &'a WitnessNodes,
+    root: Node,
+}
+
+impl<'a> SparseTrie<'a> {
+    pub fn new(nodes: &'a WitnessNodes, root: B256) -> Self {
+        let root = if root == EMPTY_ROOT_HASH {
+            Node::Empty
+        } else {
+            Node::Hash(root)
+        };
+        Self { nodes, root }
+    }
+
+    /// Looks up the value stored under `key`. Fails if the path to the key is not in the witness.
+    pub fn get(&self, key: B256) -> Result<Option<Vec<u8>>, InvalidNode> {
+        self.get_at(&self.root, Nibbles::unpack(key))
+    }
+
+    pub fn insert(&mut self, key: B256, value: Vec<u8>) -> anyhow::Result<()> {
+        let mut root = std::mem::take(&mut self.root);
+        let result = self.insert_at(&mut root, Nibbles::unpack(key), value);
+        self.root = root;
+        result
+    }
+
+    pub fn remove(&mut self, key: B256) -> anyhow::Result<()> {
+        let mut root = std::mem::take(&mut self.root);
+        let result = self.remove_at(&mut root, Nibbles::unpack(key));
+        self.root = root;
+        result
+    }
+
+    pub fn root(&self) -> B256 {
+        if matches!(self.root, Node::Empty) {
+            return EMPTY_ROOT_HASH;
+        }
+        let rlp = rlp_node(&self.root);
+        // Nodes shorter than 32 bytes are inlined by their parents, but the root is always hashed.
+        rlp.as_hash().unwrap_or_else(|| keccak256(&rlp))
+    }
+
+    /// Replaces a hash node with the decoded node.
+    fn resolve(&self, node: &mut Node) -> Result<(), InvalidNode> {
+        if let Node::Hash(hash) = node {
+            *node = decode_node(self.nodes, *hash)?;
+        }
+        Ok(())
+    }
+
+    fn get_at(&self, node: &Node, path: Nibbles) -> Result<Option<Vec<u8>>, InvalidNode> {
+        match node {
+            Node::Empty => Ok(None),
+            Node::Hash(hash) => self.get_at(&decode_node(self.nodes, *hash)?, path),
+            Node::Leaf { key, value } => Ok((*key == path).then(|| value.clone())),
+            Node::Extension { key, child } => {
+                if path.starts_with(key) {
+                    self.get_at(child, path.slice(key.len()..))
+                } else {
+                    Ok(None)
+                }
+            }
+            Node::Branch { children } => match path.first() {
+                Some(nibble) => self.get_at(&children[nibble as usize], path.slice(1..)),
+                None => Ok(None),
+            },
+        }
+    }
+
+    fn insert_at(&self, node: &mut Node, path: Nibbles, value: Vec<u8>) -> anyhow::Result<()> {
+        self.resolve(node)?;
+        *node = match std::mem::take(node) {
+            Node::Empty => Node::Leaf { key: path, value },
+            Node::Leaf {
+                key,
+                value: old_value,
+            } => {
+                if key == path {
+                    Node::Leaf { key, value }
+                } else {
+                    // Keys in the state and storage tries have the same length, so neither
+                    // of the keys is a prefix of the other one.
+                    let common = key.common_prefix_length(&path);
+                    let mut children: Box<[Node; 16]> = Default::default();
+                    children[key.get_unchecked(common) as usize] = Node::Leaf {
+                        key: key.slice(common + 1..),
+                        value: old_value,
+                    };
+                    children[path.get_unchecked(common) as usize] = Node::Leaf {
+                        key: path.slice(common + 1..),
+                        value,
+                    };
+                    with_prefix(path.slice(..common), Node::Branch { children })
+                }
+            }
+            Node::Extension { key, mut child } => {
+                let common = key.common_prefix_length(&path);
+                if common == key.len() {
+                    self.insert_at(&mut child, path.slice(common..), value)?;
+                    Node::Extension { key, child }
+                } else {
+                    let mut children: Box<[Node; 16]> = Default::default();
+                    children[key.get_unchecked(common) as usize] =
+                        with_prefix(key.slice(common + 1..), *child);
+                    children[path.get_unchecked(common) as usize] = Node::Leaf {
+                        key: path.slice(common + 1..),
+                        value,
+                    };
+                    with_prefix(key.slice(..common), Node::Branch { children })
+                }
+            }
+            Node::Branch { mut children } => {
+                let nibble = path.first().context("key is shorter than the trie path")?;
+                self.insert_at(&mut children[nibble as usize], path.slice(1..), value)?;
+                Node::Branch { children }
+            }
+            Node::Hash(_) => unreachable!("node is resolved above"),
+        };
+        Ok(())
+    }
+
+    fn remove_at(&self, node: &mut Node, path: Nibbles) -> anyhow::Result<()> {
+        self.resolve(node)?;
+        *node = match std::mem::take(node) {
+            Node::Empty => Node::Empty,
+            Node::Leaf { key, value } => {
+                if key == path {
+                    Node::Empty
+                } else {
+                    Node::Leaf { key, value }
+                }
+            }
+            Node::Extension { key, mut child } => {
+                if path.starts_with(&key) {
+                    self.remove_at(&mut child, path.slice(key.len()..))?;
+                }
+                with_prefix(key, *child)
+            }
+            Node::Branch { mut children } => {
+                let nibble = path.first().context("key is shorter than the trie path")?;
+                self.remove_at(&mut children[nibble as usize], path.slice(1..))?;
+                let remaining: Vec<usize> = (0..16)
+                    .filter(|&index| !matches!(children[index], Node::Empty))
+                    .collect();
+                match remaining[..] {
+                    // A branch with a single child is merged into it, so the child has to be
+                    // decoded; the witness contains such siblings for this reason.
+                    [index] => {
+                        let mut child = std::mem::take(&mut children[index]);
+                        self.resolve(&mut child)?;
+                        with_prefix(Nibbles::from_nibbles([index as u8]), child)
+                    }
+                    _ => Node::Branch { children },
+                }
+            }
+            Node::Hash(_) => unreachable!("node is resolved above"),
+        };
+        Ok(())
+    }
+}
+
+/// Decodes the witness node with the given hash.
+pub fn node_contents(nodes: &WitnessNodes, hash: B256) -> Result<NodeContents, InvalidNode> {
+    let mut contents = NodeContents::default();
+    collect_contents(&decode_node(nodes, hash)?, &mut contents);
+    Ok(contents)
+}
+
+fn collect_contents(node: &Node, contents: &mut NodeContents) {
+    match node {
+        Node::Empty => {}
+        Node::Hash(hash) => contents.children.push(*hash),
+        Node::Leaf { value, .. } => contents.values.push(value.clone()),
+        Node::Extension { child, .. } => collect_contents(child, contents),
+        Node::Branch { children } => {
+            for child in children.iter() {
+                collect_contents(child, contents);
+            }
+        }
+    }
+}
+
+fn decode_node(nodes: &WitnessNodes, hash: B256) -> Result<Node, InvalidNode> {
+    let invalid = |reason| InvalidNode { hash, reason };
+    let encoded = nodes
+        .get(&hash)
+        .ok_or_else(|| invalid("is not in the witness"))?;
+    TrieNode::decode(&mut encoded.as_ref())
+        .and_then(from_trie_node)
+        .map_err(|_| invalid("can't be decoded"))
+}
+
+/// Prepends `prefix` to the path of `node`, merging it into leaves and extensions.
+fn with_prefix(prefix: Nibbles, node: Node) -> Node {
+    if prefix.is_empty() {
+        return node;
+    }
+    match node {
+        Node::Empty => Node::Empty,
+        Node::Leaf { key, value } => Node::Leaf {
+            key: prefix.join(&key),
+            value,
+        },
+        Node::Extension { key, child } => Node::Extension {
+            key: prefix.join(&key),
+            child,
+        },
+        node @ (Node::Branch { .. } | Node::Hash(_)) => Node::Extension {
+            key: prefix,
+            child: Box::new(node),
+        },
+    }
+}
+
+fn from_trie_node(node: TrieNode) -> alloy::rlp::Result<Node> {
+    Ok(match node {
+        TrieNode::EmptyRoot => Node::Empty,
+        TrieNode::Leaf(leaf) => Node::Leaf {
+            key: leaf.key,
+            value: leaf.value,
+        },
+        TrieNode::Extension(extension) => Node::Extension {
+            key: extension.key,
+            child: Box::new(from_rlp_node(&extension.child)?),
+        },
+        TrieNode::Branch(branch) => {
+            let mut children: Box<[Node; 16]> = Default::default();
+            // The stack only holds the children that are present.
+            let nibbles = (0..16).filter(|&nibble| branch.state_mask.is_bit_set(nibble));
+            for (nibble, child) in nibbles.zip(&branch.stack) {
+                children[nibble as usize] = from_rlp_node(child)?;
+            }
+            Node::Branch { children }
+        }
+    })
+}
+
+/// Children shorter than 32 bytes are inlined into their parents instead of being hashed.
+fn from_rlp_node(rlp: &RlpNode) -> alloy::rlp::Result<Node> {
+    match rlp.as_hash() {
+        Some(hash) => Ok(Node::Hash(hash)),
+        None => from_trie_node(TrieNode::decode(&mut rlp.as_slice())?),
+    }
+}
+
+fn rlp_node(node: &Node) -> RlpNode {
+    let mut buf = Vec::new();
+    match node {
+        Node::Empty => RlpNode::from_rlp(&[EMPTY_STRING_CODE]),
+        Node::Hash(hash) => RlpNode::word_rlp(hash),
+        Node::Leaf { key, value } => LeafNodeRef::new(key, value).rlp(&mut buf),
+        Node::Extension { key, child } => {
+            let child = rlp_node(child);
+            ExtensionNodeRef::new(key, &child).rlp(&mut buf)
+        }
+        Node::Branch { children } => {
+            let mut state_mask = TrieMask::default();
+            let mut stack = Vec::new();
+            for (nibble, child) in children.iter().enumerate() {
+                if !matches!(child, Node::Empty) {
+                    state_mask.set_bit(nibble as u8);
+                    stack.push(rlp_node(child));
+                }
+            }
+            BranchNodeRef::new(&stack, state_mask).rlp(&mut buf)
+        }
+    }
+}
+
+/// Builds the full trie with the given entries, returning its root and all of its nodes.
+#[cfg(test)]
+pub(crate) fn build_trie(
+    entries: &std::collections::BTreeMap<B256, Vec<u8>>,
+) -> (B256, WitnessNodes) {
+    use alloy::trie::{HashBuilder, proof::ProofRetainer};
+
+    let targets = entries.keys().map(Nibbles::unpack).collect();
+    let mut builder = HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets));
+    for (key, value) in entries {
+        builder.add_leaf(Nibbles::unpack(key), value);
+    }
+    let root = builder.root();
+    let nodes = builder
+        .take_proof_nodes()
+        .into_inner()
+        .into_values()
+        .map(|node| (keccak256(&node), node))
+        .collect();
+    (root, nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use alloy::{
+        primitives::{B256, Bytes, keccak256},
+        trie::EMPTY_ROOT_HASH,
+    };
+
+    use super::{SparseTrie, WitnessNodes, build_trie, node_contents};
+
+    fn key(byte: u8) -> B256 {
+        keccak256([byte])
+    }
+
+    #[test]
+    fn updates_match_the_rebuilt_trie() {
+        let mut entries: BTreeMap<_, _> = (0..20).map(|i| (key(i), vec![i; 40])).collect();
+        let (root, nodes) = build_trie(&entries);
+        let mut trie = SparseTrie::new(&nodes, root);
+        assert_eq!(trie.root(), root);
+        assert_eq!(trie.get(key(3)).unwrap(), Some(vec![3; 40]));
+        assert_eq!(trie.get(key(100)).unwrap(), None);
+
+        for (key, value) in [(key(3), vec![0xaa; 40]), (key(50), vec![1])] {
+            trie.insert(key, value.clone()).unwrap();
+            entries.insert(key, value);
+        }
+        for removed in [key(7), key(11), key(200)] {
+            trie.remove(removed).unwrap();
+            entries.remove(&removed);
+        }
+        assert_eq!(trie.root(), build_trie(&entries).0);
+        assert_eq!(trie.get(key(50)).unwrap(), Some(vec![1]));
+    }
+
+    #[test]
+    fn trie_can_be_emptied_and_refilled() {
+        let entries: BTreeMap<_, _> = (0..2).map(|i| (key(i), vec![i + 1])).collect();
+        let (root, nodes) = build_trie(&entries);
+        let mut trie = SparseTrie::new(&nodes, root);
+        trie.remove(key(0)).unwrap();
+        trie.remove(key(1)).unwrap();
+        assert_eq!(trie.root(), EMPTY_ROOT_HASH);
+
+        let mut empty = SparseTrie::new(&nodes, EMPTY_ROOT_HASH);
+        for (key, value) in &entries {
+            empty.insert(*key, value.clone()).unwrap();
+        }
+        assert_eq!(empty.root(), root);
+    }
+
+    #[test]
+    fn missing_nodes_are_reported() {
+        let entries: BTreeMap<_, _> = (0..20).map(|i| (key(i), vec![i; 40])).collect();
+        let (root, _) = build_trie(&entries);
+        let nodes = WitnessNodes::new();
+        let mut trie = SparseTrie::new(&nodes, root);
+        let err = trie.insert(key(1), vec![1]).unwrap_err();
+        assert!(err.to_string().contains("is not in the witness"), "{err}");
+    }
+
+    #[test]
+    fn undecodable_nodes_are_reported_with_their_hash() {
+        let garbage = Bytes::from_static(b"not a trie node");
+        let hash = keccak256(&garbage);
+        let nodes = WitnessNodes::from([(hash, garbage)]);
+        let err = SparseTrie::new(&nodes, hash).get(key(1)).unwrap_err();
+        assert_eq!(err.hash, hash);
+        assert!(err.to_string().contains("can't be decoded"), "{err}");
+        assert!(node_contents(&nodes, hash).is_err());
+    }
+}
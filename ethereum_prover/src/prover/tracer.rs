@@ -0,0 +1,655 @@
+//! Execution tracer for the STF, producing traces in the format of geth's built-in tracers.
+//!
+//! Call frames are emitted in the `callTracer` format and storage accesses in the `prestateTracer`
+//! format, so that they can be diffed against `debug_traceTransaction` of a node. The tracer is
+//! only used when debugging blocks, as it slows down the execution considerably.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use alloy::primitives::{Address, B256, Bytes, U256};
+use ruint::aliases::B160;
+use serde::Serialize;
+use zk_ee::{
+    system::{
+        CallModifier, CallResult, EthereumLikeTypes, ExecutionEnvironmentLaunchParams,
+        evm::{EvmFrameInterface, errors::EvmError},
+        tracer::{Tracer, evm_tracer::EvmTracer},
+    },
+    types_config::SystemIOTypesConfig,
+    utils::Bytes32,
+};
+
+/// Call frame in the format of geth's `callTracer`.
+///
+/// Gas is not reported: the STF accounts resources in its own units, which don't map to EVM gas
+/// for individual frames.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    #[serde(rename = "type")]
+    pub kind: CallKind,
+    pub from: Address,
+    pub to: Address,
+    pub value: U256,
+    pub input: Bytes,
+    pub output: Bytes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<CallFrame>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CallKind {
+    Call,
+    StaticCall,
+    DelegateCall,
+    CallCode,
+    Create,
+    Create2,
+    SelfDestruct,
+}
+
+/// Account in the format of geth's `prestateTracer`.
+///
+/// Balance, nonce and code are omitted if they aren't known, see
+/// [`ExecutionTracer::into_block_traces()`]; like geth, zero nonces and empty code are omitted too.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AccountPrestate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<Bytes>,
+    /// Values of the accessed slots before the transaction.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<B256, B256>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageWrite {
+    pub address: Address,
+    pub key: B256,
+    pub value: B256,
+}
+
+/// Everything recorded for a single transaction.
+#[derive(Debug, Clone, Default)]
+pub struct TxTrace {
+    pub call: Option<CallFrame>,
+    pub prestate: BTreeMap<Address, AccountPrestate>,
+    pub storage_writes: Vec<StorageWrite>,
+    pub opcode_counts: BTreeMap<String, u64>,
+}
+
+/// Trace result of a transaction, in the format of geth's `debug_traceBlock*`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxTraceResult<T> {
+    pub tx_hash: B256,
+    pub result: T,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxExecutionStats {
+    pub opcode_counts: BTreeMap<String, u64>,
+    pub storage_writes: Vec<StorageWrite>,
+}
+
+/// Traces of all transactions of a block, split by format.
+#[derive(Debug, Clone, Default)]
+pub struct BlockTraces {
+    pub calls: Vec<TxTraceResult<Option<CallFrame>>>,
+    pub prestate: Vec<TxTraceResult<BTreeMap<Address, AccountPrestate>>>,
+    pub stats: Vec<TxTraceResult<TxExecutionStats>>,
+}
+
+/// Records call frames, opcode counts and storage accesses of every transaction.
+#[derive(Debug, Default)]
+pub struct ExecutionTracer {
+    /// Traces of the transactions by their index in the block.
+    traces: BTreeMap<usize, TxTrace>,
+    /// Index and trace of the transaction being executed.
+    current: Option<(usize, TxTrace)>,
+    /// Number of transactions started so far.
+    tx_count: usize,
+    /// Slots written by the current transaction; reads of them don't observe the pre-state.
+    written_slots: BTreeSet<(Address, B256)>,
+    /// Frames that have been entered, but not completed yet.
+    frames: Vec<CallFrame>,
+    /// Kind of the next frame if it's a contract deployment, which isn't distinguishable
+    /// from the frame itself.
+    pending_create: Option<CallKind>,
+}
+
+impl ExecutionTracer {
+    fn begin_tx(&mut self) {
+        // A transaction that was aborted without finishing keeps its index, so that the traces
+        // of the following transactions aren't attributed to the wrong hashes.
+        let tx_count = self.tx_count;
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|(tx_index, _)| *tx_index < tx_count)
+        {
+            self.finish_tx();
+        }
+        self.current();
+        self.tx_count += 1;
+        self.written_slots.clear();
+        self.pending_create = None;
+    }
+
+    fn finish_tx(&mut self) {
+        // Frames left after a failed transaction are closed as is.
+        while !self.frames.is_empty() {
+            self.exit_frame(Bytes::new(), Some("frame not completed".to_owned()));
+        }
+        if let Some((tx_index, trace)) = self.current.take() {
+            self.traces.insert(tx_index, trace);
+        }
+    }
+
+    fn current(&mut self) -> &mut TxTrace {
+        // Accesses before a transaction begins are attributed to it.
+        let tx_count = self.tx_count;
+        &mut self
+            .current
+            .get_or_insert_with(|| (tx_count, TxTrace::default()))
+            .1
+    }
+
+    fn touch_account(&mut self, address: Address) -> &mut AccountPrestate {
+        self.current().prestate.entry(address).or_default()
+    }
+
+    fn enter_frame(
+        &mut self,
+        kind: CallKind,
+        from: Address,
+        to: Address,
+        value: U256,
+        input: Bytes,
+    ) {
+        let kind = match kind {
+            CallKind::Create => self.pending_create.take().unwrap_or(CallKind::Create),
+            kind => kind,
+        };
+        self.touch_account(from);
+        self.touch_account(to);
+        self.frames.push(CallFrame {
+            kind,
+            from,
+            to,
+            value,
+            input,
+            output: Bytes::new(),
+            error: None,
+            calls: Vec::new(),
+        });
+    }
+
+    fn exit_frame(&mut self, output: Bytes, error: Option<String>) {
+        let Some(mut frame) = self.frames.pop() else {
+            return;
+        };
+        frame.output = output;
+        frame.error = error;
+        match self.frames.last_mut() {
+            Some(parent) => parent.calls.push(frame),
+            None => self.current().call = Some(frame),
+        }
+    }
+
+    fn record_storage_read(&mut self, address: Address, key: B256, value: B256) {
+        let written = self.written_slots.contains(&(address, key));
+        let storage = &mut self.touch_account(address).storage;
+        // The first value read before the slot is written is its value before the transaction.
+        if !written {
+            storage.entry(key).or_insert(value);
+        }
+    }
+
+    fn record_storage_write(&mut self, address: Address, key: B256, value: B256) {
+        self.written_slots.insert((address, key));
+        self.touch_account(address);
+        self.current().storage_writes.push(StorageWrite {
+            address,
+            key,
+            value,
+        });
+    }
+
+    fn record_opcode(&mut self, opcode: u8) {
+        *self
+            .current()
+            .opcode_counts
+            .entry(opcode_name(opcode))
+            .or_default() += 1;
+    }
+
+    /// Pairs the recorded traces with the hashes of the executed transactions by their index.
+    ///
+    /// Balance, nonce and code of the accessed accounts are taken from `pre_block_account`, which
+    /// returns an account as of before the block. They are only reported for the first transaction
+    /// accessing the account, since the changes made by earlier transactions aren't tracked.
+    /// The coinbase is accessed by every transaction, as it receives the fees.
+    pub fn into_block_traces(
+        mut self,
+        tx_hashes: &[B256],
+        coinbase: Address,
+        mut pre_block_account: impl FnMut(Address) -> Option<AccountPrestate>,
+    ) -> BlockTraces {
+        self.finish_tx();
+        let mut accessed = BTreeSet::new();
+        let mut block_traces = BlockTraces::default();
+        for (tx_index, mut trace) in self.traces {
+            let Some(&tx_hash) = tx_hashes.get(tx_index) else {
+                tracing::warn!(
+                    "Dropping the trace of transaction #{tx_index}: the block has only {} transactions",
+                    tx_hashes.len()
+                );
+                continue;
+            };
+            trace.prestate.entry(coinbase).or_default();
+            for (&address, account) in &mut trace.prestate {
+                if !accessed.insert(address) {
+                    continue;
+                }
+                if let Some(pre_block) = pre_block_account(address) {
+                    account.balance = pre_block.balance;
+                    account.nonce = pre_block.nonce;
+                    account.code = pre_block.code;
+                }
+            }
+            block_traces.calls.push(TxTraceResult {
+                tx_hash,
+                result: trace.call,
+            });
+            block_traces.prestate.push(TxTraceResult {
+                tx_hash,
+                result: trace.prestate,
+            });
+            block_traces.stats.push(TxTraceResult {
+                tx_hash,
+                result: TxExecutionStats {
+                    opcode_counts: trace.opcode_counts,
+                    storage_writes: trace.storage_writes,
+                },
+            });
+        }
+        block_traces
+    }
+}
+
+fn address(address: &B160) -> Address {
+    Address::from(address.to_be_bytes::<20>())
+}
+
+fn word(value: &Bytes32) -> B256 {
+    B256::from(value.as_u8_array())
+}
+
+impl<S: EthereumLikeTypes> Tracer<S> for ExecutionTracer {
+    fn on_new_execution_frame(&mut self, initial_state: &ExecutionEnvironmentLaunchParams<S>) {
+        let request = &initial_state.external_call;
+        let kind = match request.modifier {
+            CallModifier::Constructor => CallKind::Create,
+            CallModifier::Static => CallKind::StaticCall,
+            CallModifier::Delegate | CallModifier::DelegateStatic => CallKind::DelegateCall,
+            CallModifier::EVMCallcode | CallModifier::EVMCallcodeStatic => CallKind::CallCode,
+            _ => CallKind::Call,
+        };
+        self.enter_frame(
+            kind,
+            address(&request.caller),
+            address(&request.callee),
+            U256::from_be_bytes(request.nominal_token_value.to_be_bytes::<32>()),
+            Bytes::copy_from_slice(request.input),
+        );
+    }
+
+    fn after_execution_frame_completed(&mut self, result: Option<(&S::Resources, &CallResult<S>)>) {
+        match result {
+            Some((_, CallResult::Successful { return_values })) => {
+                self.exit_frame(Bytes::copy_from_slice(return_values.returndata), None);
+            }
+            Some((_, CallResult::Failed { return_values })) => self.exit_frame(
+                Bytes::copy_from_slice(return_values.returndata),
+                Some("execution reverted".to_owned()),
+            ),
+            _ => self.exit_frame(Bytes::new(), Some("call preparation failed".to_owned())),
+        }
+    }
+
+    fn on_storage_read(
+        &mut self,
+        _ee_type: zk_ee::execution_environment_type::ExecutionEnvironmentType,
+        is_transient: bool,
+        address: <S::IOTypes as SystemIOTypesConfig>::Address,
+        key: <S::IOTypes as SystemIOTypesConfig>::StorageKey,
+        value: <S::IOTypes as SystemIOTypesConfig>::StorageValue,
+    ) {
+        if !is_transient {
+            self.record_storage_read(self::address(&address), word(&key), word(&value));
+        }
+    }
+
+    fn on_storage_write(
+        &mut self,
+        _ee_type: zk_ee::execution_environment_type::ExecutionEnvironmentType,
+        is_transient: bool,
+        address: <S::IOTypes as SystemIOTypesConfig>::Address,
+        key: <S::IOTypes as SystemIOTypesConfig>::StorageKey,
+        value: <S::IOTypes as SystemIOTypesConfig>::StorageValue,
+    ) {
+        if !is_transient {
+            self.record_storage_write(self::address(&address), word(&key), word(&value));
+        }
+    }
+
+    fn begin_tx(&mut self, _calldata: &[u8]) {
+        ExecutionTracer::begin_tx(self);
+    }
+
+    fn finish_tx(&mut self) {
+        ExecutionTracer::finish_tx(self);
+    }
+
+    fn evm_tracer(&mut self) -> &mut impl EvmTracer<S> {
+        self
+    }
+}
+
+impl<S: EthereumLikeTypes> EvmTracer<S> for ExecutionTracer {
+    fn before_evm_interpreter_execution_step(
+        &mut self,
+        opcode: u8,
+        _frame_state: &impl EvmFrameInterface<S>,
+    ) {
+        self.record_opcode(opcode);
+    }
+
+    fn after_evm_interpreter_execution_step(
+        &mut self,
+        _opcode: u8,
+        _frame_state: &impl EvmFrameInterface<S>,
+    ) {
+    }
+
+    fn on_opcode_error(&mut self, error: &EvmError, _frame_state: &impl EvmFrameInterface<S>) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.error = Some(format!("{error:?}"));
+        }
+    }
+
+    fn on_call_error(&mut self, error: &EvmError) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.error = Some(format!("{error:?}"));
+        }
+    }
+
+    fn on_selfdestruct(
+        &mut self,
+        beneficiary: B160,
+        token_value: ruint::aliases::U256,
+        _frame_state: &impl EvmFrameInterface<S>,
+    ) {
+        // geth reports self-destructs as a child frame of the destructed contract.
+        let from = self.frames.last().map(|frame| frame.to).unwrap_or_default();
+        self.enter_frame(
+            CallKind::SelfDestruct,
+            from,
+            address(&beneficiary),
+            U256::from_be_bytes(token_value.to_be_bytes::<32>()),
+            Bytes::new(),
+        );
+        self.exit_frame(Bytes::new(), None);
+    }
+
+    fn on_create_request(&mut self, is_create2: bool) {
+        self.pending_create = Some(if is_create2 {
+            CallKind::Create2
+        } else {
+            CallKind::Create
+        });
+    }
+}
+
+/// Name of the opcode as used by geth, e.g. in the `structLog` tracer.
+fn opcode_name(opcode: u8) -> String {
+    let name = match opcode {
+        0x00 => "STOP",
+        0x01 => "ADD",
+        0x02 => "MUL",
+        0x03 => "SUB",
+        0x04 => "DIV",
+        0x05 => "SDIV",
+        0x06 => "MOD",
+        0x07 => "SMOD",
+        0x08 => "ADDMOD",
+        0x09 => "MULMOD",
+        0x0a => "EXP",
+        0x0b => "SIGNEXTEND",
+        0x10 => "LT",
+        0x11 => "GT",
+        0x12 => "SLT",
+        0x13 => "SGT",
+        0x14 => "EQ",
+        0x15 => "ISZERO",
+        0x16 => "AND",
+        0x17 => "OR",
+        0x18 => "XOR",
+        0x19 => "NOT",
+        0x1a => "BYTE",
+        0x1b => "SHL",
+        0x1c => "SHR",
+        0x1d => "SAR",
+        0x20 => "KECCAK256",
+        0x30 => "ADDRESS",
+        0x31 => "BALANCE",
+        0x32 => "ORIGIN",
+        0x33 => "CALLER",
+        0x34 => "CALLVALUE",
+        0x35 => "CALLDATALOAD",
+        0x36 => "CALLDATASIZE",
+        0x37 => "CALLDATACOPY",
+        0x38 => "CODESIZE",
+        0x39 => "CODECOPY",
+        0x3a => "GASPRICE",
+        0x3b => "EXTCODESIZE",
+        0x3c => "EXTCODECOPY",
+        0x3d => "RETURNDATASIZE",
+        0x3e => "RETURNDATACOPY",
+        0x3f => "EXTCODEHASH",
+        0x40 => "BLOCKHASH",
+        0x41 => "COINBASE",
+        0x42 => "TIMESTAMP",
+        0x43 => "NUMBER",
+        0x44 => "PREVRANDAO",
+        0x45 => "GASLIMIT",
+        0x46 => "CHAINID",
+        0x47 => "SELFBALANCE",
+        0x48 => "BASEFEE",
+        0x49 => "BLOBHASH",
+        0x4a => "BLOBBASEFEE",
+        0x50 => "POP",
+        0x51 => "MLOAD",
+        0x52 => "MSTORE",
+        0x53 => "MSTORE8",
+        0x54 => "SLOAD",
+        0x55 => "SSTORE",
+        0x56 => "JUMP",
+        0x57 => "JUMPI",
+        0x58 => "PC",
+        0x59 => "MSIZE",
+        0x5a => "GAS",
+        0x5b => "JUMPDEST",
+        0x5c => "TLOAD",
+        0x5d => "TSTORE",
+        0x5e => "MCOPY",
+        0x5f => "PUSH0",
+        0x60..=0x7f => return format!("PUSH{}", opcode - 0x5f),
+        0x80..=0x8f => return format!("DUP{}", opcode - 0x7f),
+        0x90..=0x9f => return format!("SWAP{}", opcode - 0x8f),
+        0xa0..=0xa4 => return format!("LOG{}", opcode - 0xa0),
+        0xf0 => "CREATE",
+        0xf1 => "CALL",
+        0xf2 => "CALLCODE",
+        0xf3 => "RETURN",
+        0xf4 => "DELEGATECALL",
+        0xf5 => "CREATE2",
+        0xfa => "STATICCALL",
+        0xfd => "REVERT",
+        0xfe => "INVALID",
+        0xff => "SELFDESTRUCT",
+        _ => return format!("opcode {opcode:#04x}"),
+    };
+    name.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{Address, B256, Bytes, U256};
+
+    use super::{AccountPrestate, CallKind, ExecutionTracer, opcode_name};
+
+    #[test]
+    fn nested_frames_form_a_call_tree() {
+        let [eoa, contract, library] = [1, 2, 3].map(Address::repeat_byte);
+        let mut tracer = ExecutionTracer::default();
+        tracer.begin_tx();
+        tracer.enter_frame(
+            CallKind::Call,
+            eoa,
+            contract,
+            U256::from(5),
+            Bytes::from_static(b"in"),
+        );
+        tracer.enter_frame(
+            CallKind::DelegateCall,
+            contract,
+            library,
+            U256::ZERO,
+            Bytes::new(),
+        );
+        tracer.record_opcode(0x54);
+        tracer.record_opcode(0x54);
+        tracer.record_storage_read(contract, B256::ZERO, B256::repeat_byte(1));
+        tracer.record_storage_write(contract, B256::ZERO, B256::repeat_byte(2));
+        // Later reads observe the written value, which is not the pre-state.
+        tracer.record_storage_read(contract, B256::ZERO, B256::repeat_byte(2));
+        // Slots written before they are read don't have a known pre-state.
+        let written_first = B256::repeat_byte(7);
+        tracer.record_storage_write(contract, written_first, B256::repeat_byte(3));
+        tracer.record_storage_read(contract, written_first, B256::repeat_byte(3));
+        tracer.exit_frame(Bytes::new(), Some("execution reverted".to_owned()));
+        tracer.exit_frame(Bytes::from_static(b"out"), None);
+        tracer.finish_tx();
+
+        let tx_hash = B256::repeat_byte(0xaa);
+        let traces = tracer.into_block_traces(&[tx_hash], Address::ZERO, |_| None);
+
+        let call = traces.calls[0].result.as_ref().expect("root frame");
+        assert_eq!(call.to, contract);
+        assert_eq!(call.calls.len(), 1);
+        assert_eq!(call.calls[0].kind, CallKind::DelegateCall);
+        let json = serde_json::to_value(&traces.calls).unwrap();
+        assert_eq!(json[0]["txHash"], serde_json::json!(tx_hash));
+        assert_eq!(json[0]["result"]["type"], "CALL");
+        assert_eq!(json[0]["result"]["value"], "0x5");
+        assert_eq!(json[0]["result"]["calls"][0]["error"], "execution reverted");
+
+        let storage = &traces.prestate[0].result[&contract].storage;
+        assert_eq!(storage.len(), 1);
+        assert_eq!(storage[&B256::ZERO], B256::repeat_byte(1));
+        assert_eq!(traces.stats[0].result.opcode_counts["SLOAD"], 2);
+        assert_eq!(traces.stats[0].result.storage_writes.len(), 2);
+    }
+
+    #[test]
+    fn create_requests_set_the_frame_kind() {
+        let mut tracer = ExecutionTracer::default();
+        tracer.begin_tx();
+        tracer.pending_create = Some(CallKind::Create2);
+        tracer.enter_frame(
+            CallKind::Create,
+            Address::ZERO,
+            Address::repeat_byte(1),
+            U256::ZERO,
+            Bytes::new(),
+        );
+        tracer.exit_frame(Bytes::new(), None);
+        let (_, trace) = tracer.current.as_ref().expect("current transaction");
+        assert_eq!(
+            trace.call.as_ref().expect("create frame").kind,
+            CallKind::Create2
+        );
+        // Unfinished frames are closed when the transaction ends.
+        tracer.enter_frame(
+            CallKind::Call,
+            Address::ZERO,
+            Address::repeat_byte(2),
+            U256::ZERO,
+            Bytes::new(),
+        );
+        tracer.finish_tx();
+
+        let traces = tracer.into_block_traces(&[B256::ZERO], Address::ZERO, |_| None);
+        let call = traces.calls[0].result.as_ref().unwrap();
+        assert_eq!(call.kind, CallKind::Call);
+        assert!(call.error.is_some());
+        assert_eq!(opcode_name(0x7f), "PUSH32");
+        assert_eq!(opcode_name(0xa2), "LOG2");
+        assert_eq!(opcode_name(0x0c), "opcode 0x0c");
+    }
+
+    #[test]
+    fn traces_are_keyed_by_transaction_index() {
+        let [sender, token, coinbase] = [1, 2, 3].map(Address::repeat_byte);
+        let call = |tracer: &mut ExecutionTracer| {
+            tracer.enter_frame(CallKind::Call, sender, token, U256::ZERO, Bytes::new());
+        };
+        let mut tracer = ExecutionTracer::default();
+        tracer.begin_tx();
+        call(&mut tracer);
+        tracer.exit_frame(Bytes::new(), None);
+        tracer.finish_tx();
+        // The second transaction is aborted without finishing.
+        tracer.begin_tx();
+        call(&mut tracer);
+        tracer.begin_tx();
+        call(&mut tracer);
+        tracer.exit_frame(Bytes::new(), None);
+        tracer.finish_tx();
+
+        let tx_hashes = [1, 2, 3].map(B256::repeat_byte);
+        let traces = tracer.into_block_traces(&tx_hashes, coinbase, |address| {
+            Some(AccountPrestate {
+                balance: Some(U256::from(address.0[0])),
+                ..Default::default()
+            })
+        });
+        let hashes: Vec<_> = traces.calls.iter().map(|trace| trace.tx_hash).collect();
+        assert_eq!(hashes, tx_hashes);
+        assert!(traces.calls[1].result.as_ref().unwrap().error.is_some());
+        assert!(traces.calls[2].result.as_ref().unwrap().error.is_none());
+
+        // Account fields are only known for the first transaction accessing the account.
+        let first = &traces.prestate[0].result;
+        assert_eq!(first[&token].balance, Some(U256::from(2)));
+        assert_eq!(first[&coinbase].balance, Some(U256::from(3)));
+        let second = &traces.prestate[1].result;
+        assert_eq!(second[&token], AccountPrestate::default());
+        assert!(second.contains_key(&coinbase));
+        let json = serde_json::to_value(&traces.prestate).unwrap();
+        assert_eq!(
+            json[0]["result"][token.to_string()],
+            serde_json::json!({ "balance": "0x2" })
+        );
+        assert_eq!(json[1]["result"][token.to_string()], serde_json::json!({}));
+    }
+}
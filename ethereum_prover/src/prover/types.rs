@@ -38,3 +38,17 @@ impl EthBlockInput {
         }
     }
 }
+
+#[cfg(test)]
+impl EthBlockInput {
+    /// Block without transactions or withdrawals, for tests that don't execute it.
+    pub(crate) fn with_header(block_header: Header, execution_witness: ExecutionWitness) -> Self {
+        Self {
+            transactions: Vec::new(),
+            encoded_transactions: Vec::new(),
+            execution_witness,
+            block_header,
+            withdrawals_rlp: Vec::new(),
+        }
+    }
+}
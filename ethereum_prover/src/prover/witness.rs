@@ -0,0 +1,502 @@
+//! Consistency checks for execution witnesses, run before they are handed to the prover.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+};
+
+use alloy::{
+    consensus::Header,
+    primitives::{Address, B256, Bytes, keccak256},
+    rlp::Decodable as _,
+    rpc::types::debug::ExecutionWitness,
+    trie::{EMPTY_ROOT_HASH, KECCAK_EMPTY, TrieAccount},
+};
+use anyhow::Context as _;
+use serde::Serialize;
+
+use crate::prover::{
+    sparse_trie::{SparseTrie, WitnessNodes, node_contents},
+    types::EthBlockInput,
+};
+
+/// Checks that the ancestor headers of the witness form a chain that ends at `parent_hash`.
+///
+/// A witness fetched for a block number during a reorg may belong to another fork than the
+/// block itself; in that case its header chain doesn't link to the block's parent.
+pub fn check_header_chain(
+    witness: &ExecutionWitness,
+    block_number: u64,
+    parent_hash: B256,
+) -> anyhow::Result<()> {
+    let mut headers = witness
+        .headers
+        .iter()
+        .enumerate()
+        .map(|(idx, encoded)| {
+            Header::decode(&mut encoded.as_ref())
+                .with_context(|| format!("failed to decode witness header #{idx}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    headers.sort_by_key(|header| header.number);
+
+    let Some(latest) = headers.last() else {
+        anyhow::bail!("execution witness for block {block_number} contains no headers");
+    };
+    anyhow::ensure!(
+        latest.number + 1 == block_number,
+        "latest witness header is {}, expected the parent of block {block_number}",
+        latest.number
+    );
+    let latest_hash = latest.hash_slow();
+    anyhow::ensure!(
+        latest_hash == parent_hash,
+        "witness parent header {latest_hash} doesn't match parent hash {parent_hash} of block {block_number}"
+    );
+
+    for pair in headers.windows(2) {
+        let (ancestor, child) = (&pair[0], &pair[1]);
+        anyhow::ensure!(
+            ancestor.number + 1 == child.number && ancestor.hash_slow() == child.parent_hash,
+            "witness headers {} and {} don't form a chain",
+            ancestor.number,
+            child.number
+        );
+    }
+    Ok(())
+}
+
+/// Result of the pre-flight validation of an execution witness, see [`validate`].
+#[derive(Debug, Default, Serialize)]
+pub struct WitnessReport {
+    pub block_number: u64,
+    /// Why the ancestor headers don't link up to the parent of the block, if they don't.
+    pub header_chain_error: Option<String>,
+    /// Accounts that can't be resolved from the parent state root.
+    pub missing_nodes: Vec<MissingNode>,
+    /// Accounts whose bytecode is not in the witness.
+    pub missing_codes: Vec<MissingCode>,
+    /// Trie nodes that are not reachable from the parent state root.
+    pub unused_nodes: Vec<B256>,
+    /// Bytecodes that no account in the witness refers to.
+    pub unused_codes: Vec<B256>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MissingNode {
+    pub address: Address,
+    /// The first node on the path to the account that is not in the witness.
+    pub node_hash: B256,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MissingCode {
+    pub address: Address,
+    pub code_hash: B256,
+}
+
+impl WitnessReport {
+    /// Whether the witness contains everything needed to execute the block.
+    /// Unused nodes and codes don't affect execution.
+    pub fn is_valid(&self) -> bool {
+        self.header_chain_error.is_none()
+            && self.missing_nodes.is_empty()
+            && self.missing_codes.is_empty()
+    }
+}
+
+impl fmt::Display for WitnessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "witness of block {}: ", self.block_number)?;
+        if let Some(err) = &self.header_chain_error {
+            write!(f, "invalid header chain ({err}), ")?;
+        }
+        write!(
+            f,
+            "{} missing nodes, {} missing codes, {} unused nodes, {} unused codes",
+            self.missing_nodes.len(),
+            self.missing_codes.len(),
+            self.unused_nodes.len(),
+            self.unused_codes.len()
+        )?;
+        // The alternate form lists what's missing, e.g. for the logs.
+        if f.alternate() {
+            for MissingNode { address, node_hash } in &self.missing_nodes {
+                write!(
+                    f,
+                    "\n  missing node {node_hash} on the path to account {address}"
+                )?;
+            }
+            for MissingCode { address, code_hash } in &self.missing_codes {
+                write!(f, "\n  missing code {code_hash} of account {address}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that the execution witness of the block is complete before running the STF.
+///
+/// Verifies that the ancestor headers link up to the parent of the block, that every account
+/// in `keys` resolves through the state trie from the parent state root, and that the code of
+/// every such account is present. Nodes and codes that can't be reached from the state root
+/// are reported as unused.
+pub fn validate(input: &EthBlockInput) -> WitnessReport {
+    let witness = &input.execution_witness;
+    let block_number = input.block_header.number;
+    let mut report = WitnessReport {
+        block_number,
+        ..Default::default()
+    };
+    if let Err(err) = check_header_chain(witness, block_number, input.block_header.parent_hash) {
+        report.header_chain_error = Some(format!("{err:#}"));
+    }
+    // Without the parent header, there is no state root to resolve the accounts from.
+    let Some(parent) = parent_header(witness, block_number) else {
+        return report;
+    };
+
+    let nodes = witness_nodes(witness);
+    let codes: HashMap<B256, &Bytes> = witness
+        .codes
+        .iter()
+        .map(|code| (keccak256(code), code))
+        .collect();
+
+    for key in &witness.keys {
+        let Ok(address) = Address::try_from(key.as_ref()) else {
+            // Storage slot keys are only resolved as part of the reachability check.
+            continue;
+        };
+        match resolve_account(&nodes, parent.state_root, address) {
+            Ok(Some(account)) => {
+                if account.code_hash != KECCAK_EMPTY && !codes.contains_key(&account.code_hash) {
+                    report.missing_codes.push(MissingCode {
+                        address,
+                        code_hash: account.code_hash,
+                    });
+                }
+            }
+            Ok(None) => {}
+            Err(node_hash) => report
+                .missing_nodes
+                .push(MissingNode { address, node_hash }),
+        }
+    }
+
+    let reachable = reachable_nodes(&nodes, parent.state_root);
+    report.unused_nodes = nodes
+        .keys()
+        .filter(|hash| !reachable.nodes.contains(*hash))
+        .copied()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    report.unused_codes = codes
+        .keys()
+        .filter(|hash| !reachable.code_hashes.contains(*hash))
+        .copied()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    report
+}
+
+/// Accounts of the state before the block, read from the execution witness.
+#[derive(Debug)]
+pub struct PreState {
+    nodes: WitnessNodes,
+    codes: HashMap<B256, Bytes>,
+    state_root: B256,
+}
+
+impl PreState {
+    pub fn new(input: &EthBlockInput) -> anyhow::Result<Self> {
+        let witness = &input.execution_witness;
+        let block_number = input.block_header.number;
+        let parent = parent_header(witness, block_number)
+            .with_context(|| format!("parent of block {block_number} is not in the witness"))?;
+        Ok(Self {
+            nodes: witness_nodes(witness),
+            codes: witness
+                .codes
+                .iter()
+                .map(|code| (keccak256(code), code.clone()))
+                .collect(),
+            state_root: parent.state_root,
+        })
+    }
+
+    /// Returns the account along with its code, or `None` if the account doesn't exist or
+    /// can't be resolved from the witness. The code is `None` if it's not in the witness.
+    pub fn account(&self, address: Address) -> Option<(TrieAccount, Option<&Bytes>)> {
+        let account = resolve_account(&self.nodes, self.state_root, address).ok()??;
+        let code = if account.code_hash == KECCAK_EMPTY {
+            None
+        } else {
+            self.codes.get(&account.code_hash)
+        };
+        Some((account, code))
+    }
+}
+
+fn parent_header(witness: &ExecutionWitness, block_number: u64) -> Option<Header> {
+    witness
+        .headers
+        .iter()
+        .filter_map(|encoded| Header::decode(&mut encoded.as_ref()).ok())
+        .find(|header| header.number + 1 == block_number)
+}
+
+fn witness_nodes(witness: &ExecutionWitness) -> WitnessNodes {
+    witness
+        .state
+        .iter()
+        .map(|node| (keccak256(node), node.clone()))
+        .collect()
+}
+
+/// Looks up the account in the state trie. Returns `Ok(None)` if the witness proves that
+/// the account doesn't exist, and the hash of the first missing node if the path to the
+/// account is incomplete.
+fn resolve_account(
+    nodes: &WitnessNodes,
+    state_root: B256,
+    address: Address,
+) -> Result<Option<TrieAccount>, B256> {
+    let account = SparseTrie::new(nodes, state_root)
+        .get(keccak256(address))
+        .map_err(|err| err.hash)?;
+    // An undecodable account can't be used by the STF either.
+    Ok(account.and_then(|account| TrieAccount::decode(&mut account.as_slice()).ok()))
+}
+
+#[derive(Default)]
+struct ReachableNodes {
+    nodes: HashSet<B256>,
+    code_hashes: HashSet<B256>,
+}
+
+/// Collects the nodes reachable from the state root, including the storage tries of the
+/// reachable accounts, and the code hashes of these accounts.
+fn reachable_nodes(nodes: &WitnessNodes, state_root: B256) -> ReachableNodes {
+    let mut reachable = ReachableNodes::default();
+    // Nodes to visit, and whether they belong to the account trie.
+    let mut pending = vec![(state_root, true)];
+    while let Some((hash, is_account_trie)) = pending.pop() {
+        if !reachable.nodes.insert(hash) {
+            continue;
+        }
+        let Ok(contents) = node_contents(nodes, hash) else {
+            continue;
+        };
+        pending.extend(
+            contents
+                .children
+                .into_iter()
+                .map(|child| (child, is_account_trie)),
+        );
+        if !is_account_trie {
+            continue;
+        }
+        for value in contents.values {
+            if let Ok(account) = TrieAccount::decode(&mut value.as_slice()) {
+                if account.storage_root != EMPTY_ROOT_HASH {
+                    pending.push((account.storage_root, false));
+                }
+                reachable.code_hashes.insert(account.code_hash);
+            }
+        }
+    }
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        consensus::Header,
+        primitives::{Address, B256, Bytes, keccak256},
+        rlp::Encodable as _,
+        rpc::types::debug::ExecutionWitness,
+        trie::{
+            Nibbles, TrieAccount, TrieMask,
+            nodes::{BranchNode, LeafNode, RlpNode},
+        },
+    };
+
+    use super::{check_header_chain, validate};
+    use crate::prover::types::EthBlockInput;
+
+    fn encode(header: &Header) -> alloy::primitives::Bytes {
+        let mut buffer = Vec::new();
+        header.encode(&mut buffer);
+        buffer.into()
+    }
+
+    fn header_chain(len: u64) -> Vec<Header> {
+        let mut headers: Vec<Header> = Vec::new();
+        for number in 10..10 + len {
+            let parent_hash = headers.last().map(Header::hash_slow).unwrap_or_default();
+            headers.push(Header {
+                number,
+                parent_hash,
+                ..Default::default()
+            });
+        }
+        headers
+    }
+
+    #[test]
+    fn linked_header_chain_is_accepted() {
+        let headers = header_chain(3);
+        let parent_hash = headers[2].hash_slow();
+        // Headers may come in any order.
+        let witness = ExecutionWitness {
+            headers: headers.iter().rev().map(encode).collect(),
+            ..Default::default()
+        };
+
+        check_header_chain(&witness, 13, parent_hash).expect("chain is valid");
+    }
+
+    #[test]
+    fn header_chain_from_another_fork_is_rejected() {
+        let headers = header_chain(3);
+        let witness = ExecutionWitness {
+            headers: headers.iter().map(encode).collect(),
+            ..Default::default()
+        };
+        let err = check_header_chain(&witness, 13, B256::repeat_byte(1)).unwrap_err();
+        assert!(err.to_string().contains("doesn't match"), "{err}");
+
+        let mut broken = headers.clone();
+        broken[1].parent_hash = B256::repeat_byte(2);
+        broken[2].parent_hash = broken[1].hash_slow();
+        let witness = ExecutionWitness {
+            headers: broken.iter().map(encode).collect(),
+            ..Default::default()
+        };
+        let err = check_header_chain(&witness, 13, broken[2].hash_slow()).unwrap_err();
+        assert!(err.to_string().contains("don't form a chain"), "{err}");
+    }
+
+    fn rlp(value: &impl alloy::rlp::Encodable) -> Bytes {
+        let mut buffer = Vec::new();
+        value.encode(&mut buffer);
+        buffer.into()
+    }
+
+    /// Builds a state trie of a single branch node with a leaf per account. Returns the
+    /// branch node and the leaves; the accounts must differ in the first nibble of their path.
+    fn state_trie(accounts: &[(Address, TrieAccount)]) -> (Bytes, Vec<Bytes>) {
+        let mut leaves: Vec<(u8, Bytes)> = accounts
+            .iter()
+            .map(|(address, account)| {
+                let path = Nibbles::unpack(keccak256(address));
+                let leaf = LeafNode::new(path.slice(1..), rlp(account).to_vec());
+                (path.get_unchecked(0), rlp(&leaf))
+            })
+            .collect();
+        leaves.sort_by_key(|(nibble, _)| *nibble);
+        let mut state_mask = TrieMask::default();
+        for (nibble, _) in &leaves {
+            assert!(!state_mask.is_bit_set(*nibble), "accounts share a nibble");
+            state_mask.set_bit(*nibble);
+        }
+        let stack = leaves
+            .iter()
+            .map(|(_, leaf)| RlpNode::from_rlp(leaf))
+            .collect();
+        let branch = rlp(&BranchNode::new(stack, state_mask));
+        (branch, leaves.into_iter().map(|(_, leaf)| leaf).collect())
+    }
+
+    fn block_input(state_root: B256, witness: ExecutionWitness) -> EthBlockInput {
+        let parent = Header {
+            number: 9,
+            state_root,
+            ..Default::default()
+        };
+        let block_header = Header {
+            number: 10,
+            parent_hash: parent.hash_slow(),
+            ..Default::default()
+        };
+        EthBlockInput::with_header(
+            block_header,
+            ExecutionWitness {
+                headers: vec![encode(&parent)],
+                ..witness
+            },
+        )
+    }
+
+    #[test]
+    fn missing_and_unused_witness_data_is_reported() {
+        let code = Bytes::from_static(b"contract code");
+        let contract = Address::repeat_byte(1);
+        let first_nibble = |address: Address| keccak256(address)[0] >> 4;
+        let eoa = (2..=u8::MAX)
+            .map(Address::repeat_byte)
+            .find(|&address| first_nibble(address) != first_nibble(contract))
+            .unwrap();
+        let contract_account = TrieAccount {
+            code_hash: keccak256(&code),
+            ..Default::default()
+        };
+        let (branch, leaves) =
+            state_trie(&[(contract, contract_account), (eoa, TrieAccount::default())]);
+        let state_root = keccak256(&branch);
+        let keys = vec![
+            Bytes::copy_from_slice(contract.as_slice()),
+            Bytes::copy_from_slice(eoa.as_slice()),
+        ];
+
+        let complete = ExecutionWitness {
+            state: [vec![branch.clone()], leaves.clone()].concat(),
+            codes: vec![code.clone()],
+            keys: keys.clone(),
+            headers: Vec::new(),
+        };
+        let report = validate(&block_input(state_root, complete));
+        assert!(report.is_valid(), "{report}");
+        assert!(report.unused_nodes.is_empty() && report.unused_codes.is_empty());
+
+        let stray_node = Bytes::from_static(b"stray node");
+        let stray_code = Bytes::from_static(b"stray code");
+        let incomplete = ExecutionWitness {
+            state: vec![branch, stray_node.clone()],
+            codes: vec![stray_code.clone()],
+            keys,
+            headers: Vec::new(),
+        };
+        let report = validate(&block_input(state_root, incomplete));
+        assert!(!report.is_valid());
+        assert_eq!(report.missing_nodes.len(), 2);
+        assert!(report.missing_codes.is_empty());
+        assert_eq!(report.unused_nodes, [keccak256(&stray_node)]);
+        assert_eq!(report.unused_codes, [keccak256(&stray_code)]);
+    }
+
+    #[test]
+    fn missing_code_is_reported() {
+        let contract = Address::repeat_byte(1);
+        let account = TrieAccount {
+            code_hash: B256::repeat_byte(0xc0),
+            ..Default::default()
+        };
+        let (branch, leaves) = state_trie(&[(contract, account)]);
+        let witness = ExecutionWitness {
+            state: [vec![branch.clone()], leaves].concat(),
+            keys: vec![Bytes::copy_from_slice(contract.as_slice())],
+            ..Default::default()
+        };
+
+        let report = validate(&block_input(keccak256(&branch), witness));
+
+        assert!(report.missing_nodes.is_empty());
+        assert_eq!(report.missing_codes.len(), 1);
+        assert_eq!(report.missing_codes[0].address, contract);
+        assert!(!report.is_valid());
+    }
+}
@@ -1,52 +1,85 @@
 use anyhow::Context as _;
 use std::time::Duration;
 
-use alloy::providers::{DynProvider, Provider};
+use alloy::providers::Provider;
+use futures::StreamExt as _;
+use futures::stream::FuturesOrdered;
 use tokio::sync::mpsc::{Receiver, Sender, channel};
-use url::Url;
 
+use super::heads::HeadNotifier;
 use crate::metrics::METRICS;
-use crate::{CacheStorage, observability, prover::types::EthBlockInput, types::CachePolicy};
+use crate::{
+    CacheStorage,
+    clients::rpc::{RpcPool, retry_rpc_call},
+    observability,
+    prover::types::EthBlockInput,
+    tasks::checkpoint::CheckpointTracker,
+    types::{BlockSelection, CachePolicy, RpcRole, StageLimits, WitnessSource},
+};
 
-const POLL_INTERVAL_SECS: u64 = 2;
+/// Delay before retrying after an RPC failure.
+const RETRY_INTERVAL_SECS: u64 = 2;
 
+/// Parameters of the [`ContinuousBlockStream`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ContinuousStreamConfig {
+    /// Offset of the processed blocks, see `block_mod`.
+    pub prover_id: u64,
+    /// Only every `block_mod`-th block is processed.
+    pub block_mod: u64,
+    pub block_selection: BlockSelection,
+    /// Maximum lag (in blocks) behind the head in `catch_up` mode.
+    pub max_catch_up_lag: u64,
+    /// Number of blocks on top of a block before it's processed.
+    pub confirmations: u64,
+    pub fetch_limits: StageLimits,
+    pub witness_source: WitnessSource,
+    pub cache_policy: CachePolicy,
+}
+
+/// Streams blocks as they are produced, according to the block selection.
+///
+/// Up to `fetch_limits.concurrency` blocks are fetched at the same time, so that fetching a block
+/// doesn't wait for the previous one in `catch_up` mode; blocks are still sent in order.
 #[derive(Debug)]
 pub struct ContinuousBlockStream {
-    prover_id: u64,
-    block_mod: u64,
-    provider: DynProvider,
+    config: ContinuousStreamConfig,
+    rpc: RpcPool,
     cache: CacheStorage,
-    cache_policy: CachePolicy,
+    /// Persists the last processed block in `catch_up` mode.
+    checkpoint: Option<CheckpointTracker>,
     sender: Sender<EthBlockInput>,
 }
 
 impl ContinuousBlockStream {
     pub fn new(
-        rpc_url: Url,
-        prover_id: u64,
-        block_mod: u64,
+        rpc: RpcPool,
         cache: CacheStorage,
-        cache_policy: CachePolicy,
+        config: ContinuousStreamConfig,
     ) -> (Self, Receiver<EthBlockInput>) {
-        // Capacity of 1 ensures that we won't be too far behind in case proving takes more time than expected.
-        let (sender, receiver) = channel(1);
-
-        let provider = alloy::providers::ProviderBuilder::new().connect_http(rpc_url);
-        let provider = DynProvider::new(provider);
+        // A small queue ensures that we won't be too far behind in case proving takes more time than expected.
+        let (sender, receiver) = channel(config.fetch_limits.queue_size);
+        let checkpoint = (config.block_selection == BlockSelection::CatchUp)
+            .then(|| CheckpointTracker::new(cache.clone()));
 
         (
             Self {
-                provider,
-                sender,
-                prover_id,
-                block_mod,
+                config,
+                rpc,
                 cache,
-                cache_policy,
+                checkpoint,
+                sender,
             },
             receiver,
         )
     }
 
+    /// Returns the tracker that has to be told when the sent blocks are done, so that the last
+    /// processed block can be persisted; `None` unless in `catch_up` mode.
+    pub fn checkpoint_tracker(&self) -> Option<CheckpointTracker> {
+        self.checkpoint.clone()
+    }
+
     pub async fn run(self) -> anyhow::Result<()> {
         let result = self.run_inner().await;
         if let Err(ref err) = result {
@@ -56,62 +89,192 @@ impl ContinuousBlockStream {
     }
 
     async fn run_inner(self) -> anyhow::Result<()> {
-        tracing::info!("Running continuous block stream");
-        let mut last_selected = None;
+        tracing::info!(
+            "Running continuous block stream with {:?} block selection",
+            self.config.block_selection
+        );
+        let head_endpoint = self.rpc.select(RpcRole::Head);
+        let mut heads = HeadNotifier::new(head_endpoint.provider().clone(), head_endpoint.url());
+
+        let mut last_selected = match self.config.block_selection {
+            BlockSelection::Head => None,
+            BlockSelection::CatchUp => {
+                let checkpoint = self
+                    .cache
+                    .load_checkpoint()
+                    .context("failed to load the last processed block")?;
+                if let Some(checkpoint) = checkpoint {
+                    tracing::info!("Resuming after the last processed block {checkpoint}");
+                }
+                checkpoint
+            }
+        };
+        // Fetches in the order the blocks were selected, so that they are sent in order.
+        let mut fetches = FuturesOrdered::new();
         loop {
-            let head = match super::retry_rpc_call("fetch latest L1 head", || async {
-                self.provider
-                    .get_block_number()
-                    .await
-                    .map_err(anyhow::Error::from)
-            })
-            .await
-            {
-                Ok(head) => head,
-                Err(err) => {
-                    tracing::error!("Failed to fetch the latest L1 head after retries: {err}");
-                    tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
-                    continue;
+            tokio::select! {
+                Some((selected, result)) = fetches.next(), if !fetches.is_empty() => {
+                    let eth_block_input = match result {
+                        Ok(input) => input,
+                        Err(err) => {
+                            tracing::error!(
+                                "Failed to fetch input for block {selected} after retries: {err}"
+                            );
+                            continue;
+                        }
+                    };
+                    tracing::info!("Fetched block input for block {}", selected);
+                    METRICS.blocks_received_total.inc();
+                    METRICS.last_processed_block.set(selected);
+                    // The checkpoint is persisted once the block is done, so that a restart
+                    // processes it again if it's still in flight.
+                    if let Some(checkpoint) = &self.checkpoint {
+                        checkpoint.dispatched(selected);
+                    }
+                    self.sender.send(eth_block_input).await.with_context(|| {
+                        format!("failed to send block {selected} to the proving pipeline")
+                    })?;
                 }
+                selected = self.select_next_block(&mut heads, last_selected), if fetches.len() < self.config.fetch_limits.concurrency => {
+                    let selected = selected?;
+                    last_selected = Some(selected);
+                    fetches.push_back(self.fetch_block(selected));
+                }
+            }
+        }
+    }
+
+    /// Waits until there's a block to process after `last_selected` and returns it.
+    async fn select_next_block(
+        &self,
+        heads: &mut HeadNotifier,
+        last_selected: Option<u64>,
+    ) -> anyhow::Result<u64> {
+        // Head received via the subscription; it's only polled for if there's none.
+        let mut notified_head = None;
+        loop {
+            let head = match notified_head.take() {
+                Some(head) => head,
+                None => match retry_rpc_call(
+                    &self.rpc,
+                    RpcRole::Head,
+                    "fetch latest L1 head",
+                    |endpoint| async move {
+                        endpoint
+                            .provider()
+                            .get_block_number()
+                            .await
+                            .map_err(anyhow::Error::from)
+                    },
+                )
+                .await
+                {
+                    Ok(head) => head,
+                    Err(err) => {
+                        tracing::error!("Failed to fetch the latest L1 head after retries: {err}");
+                        tokio::time::sleep(Duration::from_secs(RETRY_INTERVAL_SECS)).await;
+                        continue;
+                    }
+                },
             };
 
-            let selected = select_block(head, self.prover_id, self.block_mod)
-                .context("failed to select the next block to process")?;
-            if last_selected.is_some_and(|prev| selected <= prev) {
-                tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+            // Only blocks with enough confirmations on top of them are eligible.
+            let head = head.saturating_sub(self.config.confirmations);
+            let Some(next) = next_block(
+                self.config.block_selection,
+                last_selected,
+                head,
+                self.config.prover_id,
+                self.config.block_mod,
+                self.config.max_catch_up_lag,
+            )
+            .context("failed to select the next block to process")?
+            else {
+                notified_head = heads.wait_for_new_head().await;
                 continue;
+            };
+            let NextBlock { selected, skipped } = next;
+            if skipped > 0 {
+                tracing::warn!(
+                    "Skipping {skipped} matching blocks before block {selected} (last processed block: {last_selected:?})"
+                );
+                METRICS.skipped_blocks_total.inc_by(skipped);
             }
-            last_selected = Some(selected);
-
             tracing::info!("Selected block {}", selected);
+            return Ok(selected);
+        }
+    }
 
-            let eth_block_input = match super::fetch_input_with_retries(
-                &self.provider,
+    /// Fetches the input of the selected block. In `catch_up` mode, every block has to be
+    /// processed, so the fetch is retried until it succeeds.
+    async fn fetch_block(&self, selected: u64) -> (u64, anyhow::Result<EthBlockInput>) {
+        loop {
+            let result = super::fetch_input_with_retries(
+                &self.rpc,
                 selected,
-                self.cache_policy,
+                self.config.witness_source,
+                self.config.cache_policy,
                 &self.cache,
             )
-            .await
-            {
-                Ok(input) => input,
-                Err(err) => {
+            .await;
+            match result {
+                Err(err) if self.config.block_selection == BlockSelection::CatchUp => {
                     tracing::error!(
-                        "Failed to fetch input for block {selected} after retries; skipping block: {err}"
+                        "Failed to fetch input for block {selected} after retries, retrying: {err}"
                     );
-                    tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
-                    continue;
+                    tokio::time::sleep(Duration::from_secs(RETRY_INTERVAL_SECS)).await;
                 }
-            };
-            tracing::info!("Fetched block input for block {}", selected);
-            METRICS.blocks_received_total.inc();
-            METRICS.last_processed_block.set(selected);
-            self.sender.send(eth_block_input).await.with_context(|| {
-                format!("failed to send block {selected} to the proving pipeline")
-            })?;
+                result => return (selected, result),
+            }
         }
     }
 }
 
+#[derive(Debug, PartialEq, Eq)]
+struct NextBlock {
+    selected: u64,
+    /// Number of matching blocks between the last processed block and the selected one.
+    skipped: u64,
+}
+
+/// Picks the next block to process given the last processed one and the current head.
+/// Returns `None` if there's nothing new to process yet.
+fn next_block(
+    block_selection: BlockSelection,
+    last_selected: Option<u64>,
+    head: u64,
+    prover_id: u64,
+    block_mod: u64,
+    max_catch_up_lag: u64,
+) -> anyhow::Result<Option<NextBlock>> {
+    let head_block = select_block(head, prover_id, block_mod)?;
+    let Some(last_selected) = last_selected else {
+        return Ok(Some(NextBlock {
+            selected: head_block,
+            skipped: 0,
+        }));
+    };
+    if head_block <= last_selected {
+        return Ok(None);
+    }
+
+    // The first matching block after the last processed one. It's derived via `select_block`
+    // to stay aligned even if `prover_id`/`block_mod` changed since the checkpoint was written.
+    let next_matching = select_block(last_selected + block_mod, prover_id, block_mod)?;
+    let missed = (head_block - next_matching) / block_mod;
+    let next = match block_selection {
+        BlockSelection::CatchUp if head_block - next_matching <= max_catch_up_lag => NextBlock {
+            selected: next_matching,
+            skipped: 0,
+        },
+        BlockSelection::Head | BlockSelection::CatchUp => NextBlock {
+            selected: head_block,
+            skipped: missed,
+        },
+    };
+    Ok(Some(next))
+}
+
 fn select_block(candidate_block: u64, prover_id: u64, block_mod: u64) -> anyhow::Result<u64> {
     anyhow::ensure!(block_mod > 0, "block_mod must be greater than 0");
     anyhow::ensure!(
@@ -132,7 +295,8 @@ fn select_block(candidate_block: u64, prover_id: u64, block_mod: u64) -> anyhow:
 
 #[cfg(test)]
 mod tests {
-    use super::select_block;
+    use super::{NextBlock, next_block, select_block};
+    use crate::types::BlockSelection;
 
     #[test]
     fn select_block_matches_expected() {
@@ -160,4 +324,70 @@ mod tests {
         let err = select_block(3, 5, 10).unwrap_err();
         assert!(err.to_string().contains("candidate block"));
     }
+
+    #[test]
+    fn head_selection_skips_missed_blocks() {
+        let next = next_block(BlockSelection::Head, Some(100), 135, 0, 10, 1000).unwrap();
+        assert_eq!(
+            next,
+            Some(NextBlock {
+                selected: 130,
+                skipped: 2
+            })
+        );
+        assert_eq!(
+            next_block(BlockSelection::Head, Some(130), 135, 0, 10, 1000).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn catch_up_selection_processes_every_matching_block() {
+        let next = next_block(BlockSelection::CatchUp, Some(100), 135, 0, 10, 1000).unwrap();
+        assert_eq!(
+            next,
+            Some(NextBlock {
+                selected: 110,
+                skipped: 0
+            })
+        );
+        // Without a checkpoint, start from the head.
+        let next = next_block(BlockSelection::CatchUp, None, 135, 0, 10, 1000).unwrap();
+        assert_eq!(
+            next,
+            Some(NextBlock {
+                selected: 130,
+                skipped: 0
+            })
+        );
+        // Checkpoint written with another `prover_id` is realigned.
+        let next = next_block(BlockSelection::CatchUp, Some(103), 135, 5, 10, 1000).unwrap();
+        assert_eq!(
+            next,
+            Some(NextBlock {
+                selected: 105,
+                skipped: 0
+            })
+        );
+    }
+
+    #[test]
+    fn catch_up_selection_falls_back_to_head_on_large_lag() {
+        let next = next_block(BlockSelection::CatchUp, Some(100), 200, 0, 10, 50).unwrap();
+        assert_eq!(
+            next,
+            Some(NextBlock {
+                selected: 200,
+                skipped: 9
+            })
+        );
+        let next = next_block(BlockSelection::CatchUp, Some(100), 160, 0, 10, 50).unwrap();
+        assert_eq!(
+            next,
+            Some(NextBlock {
+                selected: 110,
+                skipped: 0
+            })
+        );
+    }
 }
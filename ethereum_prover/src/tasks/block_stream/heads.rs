@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use alloy::{
+    providers::{DynProvider, Provider},
+    pubsub::SubscriptionStream,
+    rpc::types::Header,
+};
+use futures::StreamExt as _;
+use tokio::time::Instant;
+use url::Url;
+
+/// Interval between head polls when there's no `newHeads` subscription.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// If no head arrives through the subscription for this long, it's considered stale and recreated.
+const SUBSCRIPTION_STALE_TIMEOUT: Duration = Duration::from_secs(60);
+const BASE_RESUBSCRIBE_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RESUBSCRIBE_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Notifies the continuous block stream about new chain heads.
+///
+/// For `ws://`/`wss://` RPC URLs, heads are received via `eth_subscribe("newHeads")`. If the
+/// subscription can't be created or breaks, it falls back to polling and periodically tries to
+/// resubscribe with capped exponential backoff. For other URLs, it always polls.
+pub(super) struct HeadNotifier {
+    provider: DynProvider,
+    use_subscription: bool,
+    subscription: Option<SubscriptionStream<Header>>,
+    next_subscribe_attempt: Instant,
+    resubscribe_backoff: Duration,
+}
+
+impl std::fmt::Debug for HeadNotifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HeadNotifier")
+            .field("use_subscription", &self.use_subscription)
+            .field("subscribed", &self.subscription.is_some())
+            .finish()
+    }
+}
+
+impl HeadNotifier {
+    pub fn new(provider: DynProvider, rpc_url: &Url) -> Self {
+        Self {
+            provider,
+            use_subscription: supports_subscriptions(rpc_url),
+            subscription: None,
+            next_subscribe_attempt: Instant::now(),
+            resubscribe_backoff: BASE_RESUBSCRIBE_BACKOFF,
+        }
+    }
+
+    /// Waits until a new head may be available. Returns the number of the new head if it was
+    /// received via the subscription; otherwise, the caller has to poll for it.
+    pub async fn wait_for_new_head(&mut self) -> Option<u64> {
+        if self.use_subscription
+            && self.subscription.is_none()
+            && Instant::now() >= self.next_subscribe_attempt
+        {
+            self.subscribe().await;
+        }
+
+        let Some(subscription) = &mut self.subscription else {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            return None;
+        };
+        match tokio::time::timeout(SUBSCRIPTION_STALE_TIMEOUT, subscription.next()).await {
+            Ok(Some(header)) => {
+                tracing::debug!("Received new head {} via subscription", header.number);
+                return Some(header.number);
+            }
+            Ok(None) => {
+                tracing::warn!("newHeads subscription closed, falling back to polling");
+                self.subscription = None;
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "No new heads received for {}s, recreating the newHeads subscription",
+                    SUBSCRIPTION_STALE_TIMEOUT.as_secs()
+                );
+                self.subscription = None;
+            }
+        }
+        None
+    }
+
+    async fn subscribe(&mut self) {
+        match self.provider.subscribe_blocks().await {
+            Ok(subscription) => {
+                tracing::info!("Subscribed to newHeads");
+                self.subscription = Some(subscription.into_stream());
+                self.resubscribe_backoff = BASE_RESUBSCRIBE_BACKOFF;
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to subscribe to newHeads, polling for the next {}s: {err}",
+                    self.resubscribe_backoff.as_secs()
+                );
+                self.next_subscribe_attempt = Instant::now() + self.resubscribe_backoff;
+                self.resubscribe_backoff = self
+                    .resubscribe_backoff
+                    .saturating_mul(2)
+                    .min(MAX_RESUBSCRIBE_BACKOFF);
+            }
+        }
+    }
+}
+
+fn supports_subscriptions(rpc_url: &Url) -> bool {
+    matches!(rpc_url.scheme(), "ws" | "wss")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::supports_subscriptions;
+
+    #[test]
+    fn subscriptions_are_used_for_websocket_urls() {
+        assert!(supports_subscriptions(
+            &"wss://eth.example.com".parse().unwrap()
+        ));
+        assert!(supports_subscriptions(
+            &"ws://localhost:8546".parse().unwrap()
+        ));
+        assert!(!supports_subscriptions(
+            &"https://eth.example.com".parse().unwrap()
+        ));
+    }
+}
@@ -1,37 +1,68 @@
-use std::{future::Future, time::Duration};
-
-use crate::{CacheStorage, prover::types::EthBlockInput, types::CachePolicy};
+use crate::{
+    CacheStorage,
+    clients::rpc::{RpcEndpoint, RpcPool, retry_rpc_call},
+    prover::{types::EthBlockInput, witness},
+    types::{CachePolicy, RpcRole, WitnessSource},
+};
 use alloy::{
     eips::BlockNumberOrTag,
+    primitives::B256,
     providers::{DynProvider, Provider, ext::DebugApi as _},
+    rpc::types::debug::ExecutionWitness,
 };
 use anyhow::Context as _;
 
 mod continuous;
+mod heads;
+mod range;
 mod single_block;
+mod witness_builder;
 
-pub(crate) use continuous::ContinuousBlockStream;
+pub(crate) use continuous::{ContinuousBlockStream, ContinuousStreamConfig};
+pub(crate) use range::{RangeBlockStream, parse_blocks_file, range_blocks};
 pub(crate) use single_block::SingleBlockStream;
 
-const MAX_RPC_ATTEMPTS: usize = 3;
-const BASE_RPC_BACKOFF_MS: u64 = 200;
-
 async fn fetch_input(
-    provider: &DynProvider,
+    rpc: &RpcPool,
+    endpoint: &RpcEndpoint,
     block_number: u64,
+    witness_source: WitnessSource,
     cache_policy: CachePolicy,
     cache: &CacheStorage,
 ) -> anyhow::Result<EthBlockInput> {
+    let provider = endpoint.provider();
+    // Pin the block hash first, so that the block and the witness belong to the same fork
+    // even if a reorg happens in the middle of fetching.
+    let block_hash = fetch_canonical_hash(provider, block_number).await?;
     let block = provider
-        .get_block_by_number(BlockNumberOrTag::Number(block_number))
+        .get_block_by_hash(block_hash)
         .full()
         .await
         .with_context(|| format!("failed to fetch block {block_number} from RPC"))?
-        .ok_or_else(|| anyhow::anyhow!("block {block_number} not found"))?;
-    let witness = provider
-        .debug_execution_witness(BlockNumberOrTag::Number(block_number))
-        .await
-        .with_context(|| format!("failed to fetch execution witness for block {block_number}"))?;
+        .ok_or_else(|| anyhow::anyhow!("block {block_number} ({block_hash}) not found"))?;
+    let witness = match witness_source {
+        WitnessSource::Rpc => fetch_execution_witness(provider, block_number).await?,
+        WitnessSource::Local => witness_builder::build_execution_witness(provider, &block).await?,
+        WitnessSource::Auto => match fetch_execution_witness(provider, block_number).await {
+            Ok(witness) => witness,
+            Err(err) => {
+                tracing::warn!("{err:#}; building the witness locally");
+                witness_builder::build_execution_witness(provider, &block).await?
+            }
+        },
+    };
+    witness::check_header_chain(&witness, block_number, block.header.parent_hash)
+        .with_context(|| format!("execution witness doesn't match block {block_number}"))?;
+    // The witness can only be requested by number, so make sure that the block was still
+    // canonical when the witness was fetched.
+    let canonical_hash = fetch_canonical_hash(provider, block_number).await?;
+    anyhow::ensure!(
+        canonical_hash == block_hash,
+        "block {block_number} was reorged while fetching its input ({block_hash} -> {canonical_hash})"
+    );
+    if rpc.cross_check() {
+        cross_check_block_hash(rpc, endpoint, block_number, block_hash).await?;
+    }
     if !matches!(cache_policy, CachePolicy::Off) {
         cache
             .cache_block(block_number, &block, &witness)
@@ -41,103 +72,95 @@ async fn fetch_input(
     Ok(input)
 }
 
-async fn fetch_input_with_retries(
+async fn fetch_execution_witness(
     provider: &DynProvider,
     block_number: u64,
-    cache_policy: CachePolicy,
-    cache: &CacheStorage,
-) -> anyhow::Result<EthBlockInput> {
-    let operation = format!("fetch block input for block {block_number}");
-    retry_rpc_call(&operation, || async {
-        fetch_input(provider, block_number, cache_policy, cache).await
-    })
-    .await
+) -> anyhow::Result<ExecutionWitness> {
+    provider
+        .debug_execution_witness(BlockNumberOrTag::Number(block_number))
+        .await
+        .with_context(|| format!("failed to fetch execution witness for block {block_number}"))
 }
 
-async fn retry_rpc_call<T, F, Fut>(operation: &str, call: F) -> anyhow::Result<T>
-where
-    F: FnMut() -> Fut,
-    Fut: Future<Output = anyhow::Result<T>>,
-{
-    retry_rpc_call_with_config(operation, MAX_RPC_ATTEMPTS, BASE_RPC_BACKOFF_MS, call).await
+/// Returns the hash of the canonical block with the given number.
+pub(crate) async fn fetch_canonical_hash(
+    provider: &DynProvider,
+    block_number: u64,
+) -> anyhow::Result<B256> {
+    let block = provider
+        .get_block_by_number(BlockNumberOrTag::Number(block_number))
+        .await
+        .with_context(|| format!("failed to fetch header of block {block_number} from RPC"))?
+        .ok_or_else(|| anyhow::anyhow!("block {block_number} not found"))?;
+    Ok(block.header.hash)
 }
 
-async fn retry_rpc_call_with_config<T, F, Fut>(
-    operation: &str,
-    max_attempts: usize,
-    base_backoff_ms: u64,
-    mut call: F,
-) -> anyhow::Result<T>
-where
-    F: FnMut() -> Fut,
-    Fut: Future<Output = anyhow::Result<T>>,
-{
+/// Makes sure that another endpoint agrees on the hash of the block fetched from `endpoint`,
+/// so that a single misbehaving or lagging node can't make us prove a wrong block.
+async fn cross_check_block_hash(
+    rpc: &RpcPool,
+    endpoint: &RpcEndpoint,
+    block_number: u64,
+    block_hash: B256,
+) -> anyhow::Result<()> {
+    let Some(other) = rpc.select_other(endpoint) else {
+        anyhow::bail!("no RPC endpoint to cross-check block {block_number} against");
+    };
+    let result = fetch_canonical_hash(other.provider(), block_number).await;
+    other.record(&result);
+    let other_hash = result
+        .with_context(|| format!("failed to cross-check block {block_number} with {other}"))?;
     anyhow::ensure!(
-        max_attempts > 0,
-        "retry policy requires at least one attempt"
+        other_hash == block_hash,
+        "RPC endpoints disagree on block {block_number}: {endpoint} returned {block_hash}, {other} returned {other_hash}"
     );
-
-    for attempt in 1..=max_attempts {
-        match call().await {
-            Ok(value) => return Ok(value),
-            Err(err) if attempt < max_attempts => {
-                let backoff_ms = base_backoff_ms.saturating_mul(1_u64 << (attempt - 1));
-                tracing::warn!(
-                    "{operation} failed: {err}. Retrying attempt {}/{} in {}ms",
-                    attempt + 1,
-                    max_attempts,
-                    backoff_ms
-                );
-                tokio::time::sleep(Duration::from_millis(backoff_ms)).await;
-            }
-            Err(err) => return Err(err).with_context(|| operation.to_owned()),
-        }
-    }
-
-    unreachable!("retry loop always returns on success or on the final failure")
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use std::future;
-
-    use super::retry_rpc_call_with_config;
-
-    #[tokio::test]
-    async fn retry_rpc_call_retries_until_success() {
-        let mut attempts = 0;
-
-        let value = retry_rpc_call_with_config("fetch block", 3, 0, || {
-            attempts += 1;
-            future::ready(if attempts < 3 {
-                Err(anyhow::anyhow!("transient RPC error"))
-            } else {
-                Ok(42_u64)
-            })
-        })
+async fn fetch_input_with_retries(
+    rpc: &RpcPool,
+    block_number: u64,
+    witness_source: WitnessSource,
+    cache_policy: CachePolicy,
+    cache: &CacheStorage,
+) -> anyhow::Result<EthBlockInput> {
+    let operation = format!("fetch block input for block {block_number}");
+    retry_rpc_call(rpc, RpcRole::Witness, &operation, |endpoint| async move {
+        fetch_input(
+            rpc,
+            &endpoint,
+            block_number,
+            witness_source,
+            cache_policy,
+            cache,
+        )
         .await
-        .expect("retry succeeds");
+    })
+    .await
+}
 
-        assert_eq!(value, 42);
-        assert_eq!(attempts, 3);
+/// Loads the block input from the cache if it's there, and fetches it from RPC otherwise.
+async fn load_or_fetch_input(
+    rpc: Option<&RpcPool>,
+    block_number: u64,
+    witness_source: WitnessSource,
+    cache_policy: CachePolicy,
+    cache: &CacheStorage,
+) -> anyhow::Result<EthBlockInput> {
+    if cache.has_cached_block(block_number) {
+        tracing::info!("Loading block {block_number} from cache");
+        let Some((block, witness)) = cache
+            .load_block(block_number)
+            .with_context(|| format!("failed to load block {block_number} from cache"))?
+        else {
+            anyhow::bail!("cache indicated block {block_number} exists, but contents missing");
+        };
+        return Ok(EthBlockInput::new(block, witness));
     }
 
-    #[tokio::test]
-    async fn retry_rpc_call_returns_final_error() {
-        let mut attempts = 0;
-
-        let err = retry_rpc_call_with_config("fetch head", 3, 0, || {
-            attempts += 1;
-            future::ready(Err::<u64, _>(anyhow::anyhow!("still failing")))
-        })
-        .await
-        .expect_err("retry should fail");
-
-        assert_eq!(attempts, 3);
-        assert!(err.to_string().contains("fetch head"));
-        assert!(
-            err.chain()
-                .any(|cause| cause.to_string().contains("still failing"))
-        );
-    }
+    let Some(rpc) = rpc else {
+        anyhow::bail!("Block {block_number} not cached and no RPC URL provided");
+    };
+    tracing::info!("Fetching block {block_number}");
+    fetch_input_with_retries(rpc, block_number, witness_source, cache_policy, cache).await
 }
@@ -0,0 +1,160 @@
+use anyhow::Context as _;
+use futures::{StreamExt as _, stream};
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+
+use crate::metrics::METRICS;
+use crate::{
+    CacheStorage,
+    clients::rpc::RpcPool,
+    observability,
+    prover::types::EthBlockInput,
+    types::{CachePolicy, StageLimits, WitnessSource},
+};
+
+/// Streams a fixed list of (usually historical) blocks, e.g. to reprocess them after an STF fix.
+///
+/// Blocks that cannot be loaded are skipped, so that a single unavailable block doesn't
+/// stop the whole backfill; they are reported as failed in the range summary.
+/// Up to `fetch_concurrency` blocks are loaded at the same time, and they are sent in the order
+/// of the list.
+#[derive(Debug)]
+pub struct RangeBlockStream {
+    blocks: Vec<u64>,
+    fetch_concurrency: usize,
+    rpc: Option<RpcPool>,
+    cache: CacheStorage,
+    witness_source: WitnessSource,
+    cache_policy: CachePolicy,
+    sender: Sender<EthBlockInput>,
+}
+
+impl RangeBlockStream {
+    pub fn new(
+        blocks: Vec<u64>,
+        fetch_limits: StageLimits,
+        rpc: Option<RpcPool>,
+        cache: CacheStorage,
+        witness_source: WitnessSource,
+        cache_policy: CachePolicy,
+    ) -> (Self, Receiver<EthBlockInput>) {
+        // A small queue ensures that we don't fetch blocks too far ahead of the prover.
+        let (sender, receiver) = channel(fetch_limits.queue_size);
+        (
+            Self {
+                blocks,
+                fetch_concurrency: fetch_limits.concurrency,
+                rpc,
+                cache,
+                witness_source,
+                cache_policy,
+                sender,
+            },
+            receiver,
+        )
+    }
+
+    pub async fn run(self) -> anyhow::Result<()> {
+        let result = self.run_inner().await;
+        if let Err(ref err) = result {
+            observability::capture_anyhow(err);
+        }
+        result
+    }
+
+    async fn run_inner(self) -> anyhow::Result<()> {
+        tracing::info!(
+            "Running range block stream over {} blocks",
+            self.blocks.len()
+        );
+        let this = &self;
+        let mut inputs = stream::iter(self.blocks.iter().copied().enumerate())
+            .map(|(idx, block_number)| async move {
+                tracing::info!(
+                    "Loading block {block_number} ({}/{})",
+                    idx + 1,
+                    this.blocks.len()
+                );
+                let result = super::load_or_fetch_input(
+                    this.rpc.as_ref(),
+                    block_number,
+                    this.witness_source,
+                    this.cache_policy,
+                    &this.cache,
+                )
+                .await;
+                (block_number, result)
+            })
+            .buffered(self.fetch_concurrency);
+        while let Some((block_number, result)) = inputs.next().await {
+            let input = match result {
+                Ok(input) => input,
+                Err(err) => {
+                    tracing::error!(
+                        "Failed to load input for block {block_number}; skipping: {err:#}"
+                    );
+                    continue;
+                }
+            };
+
+            METRICS.blocks_received_total.inc();
+            METRICS.last_processed_block.set(block_number);
+            self.sender.send(input).await.with_context(|| {
+                format!("failed to send block {block_number} to the proving pipeline")
+            })?;
+        }
+
+        tracing::info!("All blocks of the range were sent to the proving pipeline");
+        Ok(())
+    }
+}
+
+/// Returns the blocks from `from` to `to` (both inclusive), taking every `step`-th block.
+pub(crate) fn range_blocks(from: u64, to: u64, step: u64) -> anyhow::Result<Vec<u64>> {
+    anyhow::ensure!(step > 0, "step must be greater than 0");
+    anyhow::ensure!(from <= to, "range start {from} is after range end {to}");
+    Ok((from..=to).step_by(step as usize).collect())
+}
+
+/// Parses a list of blocks: one block number per line. Empty lines and lines
+/// starting with `#` are ignored.
+pub(crate) fn parse_blocks_file(contents: &str) -> anyhow::Result<Vec<u64>> {
+    let blocks = contents
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(idx, line)| {
+            line.parse::<u64>()
+                .with_context(|| format!("invalid block number on line {}: {line:?}", idx + 1))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    anyhow::ensure!(!blocks.is_empty(), "blocks file contains no blocks");
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_blocks_file, range_blocks};
+
+    #[test]
+    fn range_blocks_respects_step() {
+        assert_eq!(range_blocks(10, 10, 1).unwrap(), vec![10]);
+        assert_eq!(range_blocks(10, 15, 2).unwrap(), vec![10, 12, 14]);
+        assert_eq!(range_blocks(10, 16, 3).unwrap(), vec![10, 13, 16]);
+        assert!(range_blocks(10, 15, 0).is_err());
+        assert!(range_blocks(15, 10, 1).is_err());
+    }
+
+    #[test]
+    fn blocks_file_skips_comments_and_blank_lines() {
+        let contents = "# reprocess after the fix\n24073997\n\n  24074000  \n";
+        assert_eq!(
+            parse_blocks_file(contents).unwrap(),
+            vec![24073997, 24074000]
+        );
+
+        let err = parse_blocks_file("1\nabc\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+        assert!(parse_blocks_file("# nothing\n").is_err());
+    }
+}
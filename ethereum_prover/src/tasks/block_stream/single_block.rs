@@ -1,16 +1,22 @@
-use alloy::providers::{DynProvider, Provider};
+use alloy::providers::Provider;
 use anyhow::Context as _;
 use tokio::sync::mpsc::{Receiver, Sender, channel};
-use url::Url;
 
 use crate::metrics::METRICS;
-use crate::{CacheStorage, observability, prover::types::EthBlockInput, types::CachePolicy};
+use crate::{
+    CacheStorage,
+    clients::rpc::{RpcPool, retry_rpc_call},
+    observability,
+    prover::types::EthBlockInput,
+    types::{CachePolicy, RpcRole, WitnessSource},
+};
 
 #[derive(Debug)]
 pub struct SingleBlockStream {
     block_number: Option<u64>,
-    rpc_url: Option<Url>,
+    rpc: Option<RpcPool>,
     cache: CacheStorage,
+    witness_source: WitnessSource,
     cache_policy: CachePolicy,
     sender: Sender<EthBlockInput>,
 }
@@ -18,16 +24,18 @@ pub struct SingleBlockStream {
 impl SingleBlockStream {
     pub fn new(
         block_number: Option<u64>,
-        rpc_url: Option<Url>,
+        rpc: Option<RpcPool>,
         cache: CacheStorage,
+        witness_source: WitnessSource,
         cache_policy: CachePolicy,
     ) -> (Self, Receiver<EthBlockInput>) {
         let (sender, receiver) = channel(1);
         (
             Self {
                 block_number,
-                rpc_url,
+                rpc,
                 cache,
+                witness_source,
                 cache_policy,
                 sender,
             },
@@ -46,46 +54,38 @@ impl SingleBlockStream {
     async fn run_inner(self) -> anyhow::Result<()> {
         tracing::info!("Running single block stream");
 
-        let input = if let Some(block_number) = self.block_number
-            && self.cache.has_cached_block(block_number)
-        {
-            tracing::info!("Loading block {block_number} from cache");
-            let Some((block, witness)) = self
-                .cache
-                .load_block(block_number)
-                .with_context(|| format!("failed to load block {block_number} from cache"))?
-            else {
-                anyhow::bail!("cache indicated block {block_number} exists, but contents missing");
-            };
-            EthBlockInput::new(block, witness)
-        } else {
-            tracing::info!("Block number is unknown or not cached, fetching from RPC");
-            let Some(rpc_url) = self.rpc_url else {
-                anyhow::bail!("Block number not cached and no RPC URL provided");
-            };
-
-            let provider = alloy::providers::ProviderBuilder::new().connect_http(rpc_url);
-            let provider = DynProvider::new(provider);
-
-            let block_number = match self.block_number {
-                Some(block_number) => block_number,
-                None => {
-                    super::retry_rpc_call("fetch latest L1 head", || async {
-                        provider
+        let block_number = match self.block_number {
+            Some(block_number) => block_number,
+            None => {
+                tracing::info!("Block number is unknown, fetching the latest one from RPC");
+                let Some(rpc) = &self.rpc else {
+                    anyhow::bail!("Block number not provided and no RPC URL provided");
+                };
+                retry_rpc_call(
+                    rpc,
+                    RpcRole::Head,
+                    "fetch latest L1 head",
+                    |endpoint| async move {
+                        endpoint
+                            .provider()
                             .get_block_number()
                             .await
                             .map_err(anyhow::Error::from)
-                    })
-                    .await?
-                }
-            };
-
-            tracing::info!("Fetching block {}", block_number);
-
-            super::fetch_input_with_retries(&provider, block_number, self.cache_policy, &self.cache)
+                    },
+                )
                 .await?
+            }
         };
 
+        let input = super::load_or_fetch_input(
+            self.rpc.as_ref(),
+            block_number,
+            self.witness_source,
+            self.cache_policy,
+            &self.cache,
+        )
+        .await?;
+
         tracing::info!(
             "Sending block input for block {}",
             input.block_header.number
@@ -108,7 +108,7 @@ impl SingleBlockStream {
 mod tests {
     use super::SingleBlockStream;
     use crate::cache::CacheStorage;
-    use crate::types::CachePolicy;
+    use crate::types::{CachePolicy, WitnessSource};
     use alloy::rpc::types::{Block, Header};
 
     #[tokio::test]
@@ -134,8 +134,13 @@ mod tests {
             .cache_block(block_number, &block, &witness)
             .expect("cache block");
 
-        let (stream, mut receiver) =
-            SingleBlockStream::new(Some(block_number), None, cache, CachePolicy::Off);
+        let (stream, mut receiver) = SingleBlockStream::new(
+            Some(block_number),
+            None,
+            cache,
+            WitnessSource::Rpc,
+            CachePolicy::Off,
+        );
         let task = tokio::spawn(stream.run());
 
         let input = receiver.recv().await.expect("receive input");
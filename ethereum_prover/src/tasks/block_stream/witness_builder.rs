@@ -0,0 +1,333 @@
+//! Builds execution witnesses from standard RPC methods, for nodes that don't expose
+//! `debug_executionWitness`.
+//!
+//! The touched accounts and storage slots are taken from the prestate tracer, their trie
+//! nodes from `eth_getProof` at the parent block, and the bytecodes from `eth_getCode`.
+//! Trie nodes that are only needed to restructure the trie after deletions are not known
+//! to `eth_getProof`, so the resulting witness may be insufficient for such blocks.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use alloy::{
+    consensus::Header,
+    eips::{
+        BlockNumberOrTag, eip2935::HISTORY_STORAGE_ADDRESS, eip4788::BEACON_ROOTS_ADDRESS,
+        eip7002::WITHDRAWAL_REQUEST_PREDEPLOY_ADDRESS,
+        eip7251::CONSOLIDATION_REQUEST_PREDEPLOY_ADDRESS,
+    },
+    primitives::{Address, B256, Bytes, KECCAK256_EMPTY, U256, keccak256},
+    providers::{DynProvider, Provider, ext::DebugApi as _},
+    rlp::Encodable as _,
+    rpc::types::{
+        Block, EIP1186AccountProofResponse,
+        debug::ExecutionWitness,
+        trace::geth::{GethDebugTracingOptions, PreStateConfig, PreStateFrame, TraceResult},
+    },
+};
+use anyhow::Context as _;
+use futures::{StreamExt as _, TryStreamExt as _, stream};
+
+/// Number of ancestor headers included in the witness: `BLOCKHASH` can access up to 256
+/// previous blocks, and we can't tell from the traces which of them are actually used.
+const ANCESTOR_HEADERS: u64 = 256;
+const MAX_CONCURRENT_REQUESTS: usize = 16;
+/// Ring buffer length of the EIP-4788 beacon roots contract and the EIP-2935 history contract.
+const SYSTEM_CONTRACT_BUFFER_LENGTH: u64 = 8191;
+
+pub(super) async fn build_execution_witness(
+    provider: &DynProvider,
+    block: &Block,
+) -> anyhow::Result<ExecutionWitness> {
+    let block_number = block.header.number;
+    anyhow::ensure!(
+        block_number > 0,
+        "execution witness can't be built for the genesis block"
+    );
+    let parent_hash = block.header.parent_hash;
+
+    let tracing_options = GethDebugTracingOptions::prestate_tracer(PreStateConfig {
+        disable_code: Some(true),
+        ..Default::default()
+    });
+    let traces = provider
+        .debug_trace_block_by_hash(block.header.hash, tracing_options)
+        .await
+        .with_context(|| format!("failed to trace block {block_number} with prestate tracer"))?;
+    let touched = touched_state(block, traces)?;
+    tracing::debug!(
+        "Block {block_number} touches {} accounts and {} storage slots",
+        touched.len(),
+        touched.values().map(BTreeSet::len).sum::<usize>()
+    );
+
+    let proofs: Vec<EIP1186AccountProofResponse> = stream::iter(touched)
+        .map(|(address, slots)| async move {
+            provider
+                .get_proof(address, slots.into_iter().collect())
+                .hash(parent_hash)
+                .await
+                .with_context(|| format!("failed to fetch proof for account {address}"))
+        })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .try_collect()
+        .await?;
+
+    let code_owners: BTreeMap<B256, Address> = proofs
+        .iter()
+        .filter(|proof| proof.code_hash != KECCAK256_EMPTY && !proof.code_hash.is_zero())
+        .map(|proof| (proof.code_hash, proof.address))
+        .collect();
+    let codes: Vec<Bytes> = stream::iter(code_owners)
+        .map(|(code_hash, address)| async move {
+            let code = provider
+                .get_code_at(address)
+                .hash(parent_hash)
+                .await
+                .with_context(|| format!("failed to fetch code of account {address}"))?;
+            anyhow::ensure!(
+                keccak256(&code) == code_hash,
+                "code of account {address} doesn't match its code hash {code_hash}"
+            );
+            Ok(code)
+        })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .try_collect()
+        .await?;
+
+    let headers: Vec<Header> =
+        stream::iter(block_number.saturating_sub(ANCESTOR_HEADERS)..block_number)
+            .map(|number| async move {
+                let ancestor = provider
+                    .get_block_by_number(BlockNumberOrTag::Number(number))
+                    .await
+                    .with_context(|| format!("failed to fetch header of block {number}"))?
+                    .ok_or_else(|| anyhow::anyhow!("block {number} not found"))?;
+                Ok::<_, anyhow::Error>(ancestor.header.inner)
+            })
+            .buffered(MAX_CONCURRENT_REQUESTS)
+            .try_collect()
+            .await?;
+
+    Ok(assemble_witness(&proofs, codes, &headers))
+}
+
+/// Collects the accounts and storage slots that the block reads or writes.
+///
+/// Besides the transactions, the block touches the fee recipient, withdrawal recipients
+/// and system contracts, which don't show up in the transaction traces.
+fn touched_state(
+    block: &Block,
+    traces: Vec<TraceResult>,
+) -> anyhow::Result<BTreeMap<Address, BTreeSet<B256>>> {
+    let mut touched: BTreeMap<Address, BTreeSet<B256>> = BTreeMap::new();
+    for trace in traces {
+        let frame = match trace {
+            TraceResult::Success { result, .. } => result
+                .try_into_pre_state_frame()
+                .context("unexpected trace format")?,
+            TraceResult::Error { error, tx_hash } => {
+                anyhow::bail!("failed to trace transaction {tx_hash:?}: {error}")
+            }
+        };
+        let PreStateFrame::Default(prestate) = frame else {
+            anyhow::bail!("prestate tracer returned diff mode trace");
+        };
+        for (address, account) in prestate.0 {
+            touched
+                .entry(address)
+                .or_default()
+                .extend(account.storage.into_keys());
+        }
+    }
+
+    let header = &block.header;
+    touched.entry(header.beneficiary).or_default();
+    for withdrawal in block.withdrawals.iter().flatten() {
+        touched.entry(withdrawal.address).or_default();
+    }
+
+    let slot = |index: u64| B256::from(U256::from(index));
+    if header.parent_beacon_block_root.is_some() {
+        let index = header.timestamp % SYSTEM_CONTRACT_BUFFER_LENGTH;
+        touched
+            .entry(BEACON_ROOTS_ADDRESS)
+            .or_default()
+            .extend([slot(index), slot(index + SYSTEM_CONTRACT_BUFFER_LENGTH)]);
+    }
+    if header.requests_hash.is_some() {
+        let index = (header.number - 1) % SYSTEM_CONTRACT_BUFFER_LENGTH;
+        touched
+            .entry(HISTORY_STORAGE_ADDRESS)
+            .or_default()
+            .insert(slot(index));
+        // Excess, count and queue head/tail of the request queues. Queued requests themselves
+        // are not included.
+        for address in [
+            WITHDRAWAL_REQUEST_PREDEPLOY_ADDRESS,
+            CONSOLIDATION_REQUEST_PREDEPLOY_ADDRESS,
+        ] {
+            touched.entry(address).or_default().extend((0..4).map(slot));
+        }
+    }
+    Ok(touched)
+}
+
+/// Assembles the witness in the format of `debug_executionWitness`, which is what the
+/// oracle expects: deduplicated trie nodes and codes, and headers sorted by number.
+fn assemble_witness(
+    proofs: &[EIP1186AccountProofResponse],
+    codes: Vec<Bytes>,
+    headers: &[Header],
+) -> ExecutionWitness {
+    let mut state = BTreeSet::new();
+    let mut keys = BTreeSet::new();
+    for proof in proofs {
+        state.extend(proof.account_proof.iter().cloned());
+        keys.insert(Bytes::copy_from_slice(proof.address.as_slice()));
+        for storage_proof in &proof.storage_proof {
+            state.extend(storage_proof.proof.iter().cloned());
+            keys.insert(Bytes::copy_from_slice(
+                storage_proof.key.as_b256().as_slice(),
+            ));
+        }
+    }
+
+    let mut headers = headers.to_vec();
+    headers.sort_by_key(|header| header.number);
+    let headers = headers
+        .iter()
+        .map(|header| {
+            let mut buffer = Vec::new();
+            header.encode(&mut buffer);
+            buffer.into()
+        })
+        .collect();
+
+    ExecutionWitness {
+        state: state.into_iter().collect(),
+        codes: codes
+            .into_iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect(),
+        keys: keys.into_iter().collect(),
+        headers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        consensus::Header,
+        eips::eip4788::BEACON_ROOTS_ADDRESS,
+        primitives::{Address, B256, Bytes, U256},
+        rlp::Decodable as _,
+        rpc::types::{
+            Block, BlockTransactions, EIP1186AccountProofResponse, EIP1186StorageProof,
+            trace::geth::{AccountState, GethTrace, PreStateFrame, PreStateMode, TraceResult},
+        },
+    };
+
+    use super::{assemble_witness, touched_state};
+
+    fn prestate_trace(accounts: &[(Address, &[B256])]) -> TraceResult {
+        let accounts = accounts
+            .iter()
+            .map(|&(address, slots)| {
+                let account = AccountState {
+                    storage: slots.iter().map(|&slot| (slot, B256::ZERO)).collect(),
+                    ..Default::default()
+                };
+                (address, account)
+            })
+            .collect();
+        TraceResult::Success {
+            result: GethTrace::PreStateTracer(PreStateFrame::Default(PreStateMode(accounts))),
+            tx_hash: None,
+        }
+    }
+
+    #[test]
+    fn touched_state_merges_traces_and_system_accounts() {
+        let beneficiary = Address::repeat_byte(0xfe);
+        let sender = Address::repeat_byte(1);
+        let token = Address::repeat_byte(2);
+        let block = Block {
+            header: alloy::rpc::types::Header {
+                inner: Header {
+                    number: 100,
+                    timestamp: 8191 + 5,
+                    beneficiary,
+                    parent_beacon_block_root: Some(B256::ZERO),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            uncles: Vec::new(),
+            transactions: BlockTransactions::Hashes(Vec::new()),
+            withdrawals: None,
+        };
+        let traces = vec![
+            prestate_trace(&[(sender, &[]), (token, &[B256::repeat_byte(1)])]),
+            prestate_trace(&[(token, &[B256::repeat_byte(1), B256::repeat_byte(2)])]),
+        ];
+
+        let touched = touched_state(&block, traces).unwrap();
+
+        assert_eq!(touched.len(), 4);
+        assert!(touched[&sender].is_empty());
+        assert_eq!(touched[&token].len(), 2);
+        assert!(touched[&beneficiary].is_empty());
+        let beacon_slots: Vec<_> = touched[&BEACON_ROOTS_ADDRESS].iter().copied().collect();
+        assert_eq!(
+            beacon_slots,
+            [B256::from(U256::from(5)), B256::from(U256::from(8196))]
+        );
+    }
+
+    #[test]
+    fn witness_is_deduplicated_and_headers_are_sorted() {
+        let node = Bytes::from_static(b"shared node");
+        let slot = B256::repeat_byte(3);
+        let proofs = vec![
+            EIP1186AccountProofResponse {
+                address: Address::repeat_byte(1),
+                account_proof: vec![node.clone(), Bytes::from_static(b"account 1")],
+                ..Default::default()
+            },
+            EIP1186AccountProofResponse {
+                address: Address::repeat_byte(2),
+                account_proof: vec![node.clone(), Bytes::from_static(b"account 2")],
+                storage_proof: vec![EIP1186StorageProof {
+                    key: slot.into(),
+                    value: U256::ZERO,
+                    proof: vec![Bytes::from_static(b"storage")],
+                }],
+                ..Default::default()
+            },
+        ];
+        let headers = [10, 8, 9].map(|number| Header {
+            number,
+            ..Default::default()
+        });
+        let code = Bytes::from_static(b"code");
+
+        let witness = assemble_witness(&proofs, vec![code.clone(), code], &headers);
+
+        assert_eq!(witness.state.len(), 4);
+        assert_eq!(witness.codes.len(), 1);
+        assert_eq!(witness.keys.len(), 3);
+        assert!(
+            witness
+                .keys
+                .iter()
+                .any(|key| key.as_ref() == slot.as_slice())
+        );
+        let numbers: Vec<u64> = witness
+            .headers
+            .iter()
+            .map(|encoded| Header::decode(&mut encoded.as_ref()).unwrap().number)
+            .collect();
+        assert_eq!(numbers, [8, 9, 10]);
+    }
+}
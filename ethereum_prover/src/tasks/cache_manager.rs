@@ -1,6 +1,7 @@
 use crate::cache::CacheStorage;
 use crate::observability;
 use crate::tasks::CalculationUpdate;
+use crate::tasks::checkpoint::CheckpointTracker;
 use crate::types::CachePolicy;
 use anyhow::Context as _;
 use tokio::sync::mpsc::{Receiver, Sender, channel};
@@ -11,6 +12,7 @@ pub(crate) struct CacheManagerTask {
     command_mode_sender: Sender<CalculationUpdate>,
     cache_storage: CacheStorage,
     cache_policy: CachePolicy,
+    checkpoint: Option<CheckpointTracker>,
 }
 
 impl CacheManagerTask {
@@ -26,11 +28,19 @@ impl CacheManagerTask {
                 command_mode_sender,
                 cache_storage,
                 cache_policy,
+                checkpoint: None,
             },
             command_mode_receiver,
         )
     }
 
+    /// Reports the blocks that are done to the tracker, so that the last processed block is
+    /// persisted.
+    pub fn with_checkpoint(mut self, checkpoint: CheckpointTracker) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
     pub async fn run(self) -> anyhow::Result<()> {
         let result = self.run_inner().await;
         if let Err(ref err) = result {
@@ -42,8 +52,10 @@ impl CacheManagerTask {
     async fn run_inner(mut self) -> anyhow::Result<()> {
         while let Some(command) = self.command_mode_receiver.recv().await {
             match &command {
+                // Dropped blocks are not failures, so their inputs are not worth keeping either.
                 CalculationUpdate::ProofProvided { block_number, .. }
-                | CalculationUpdate::WitnessCalculated { block_number, .. } => {
+                | CalculationUpdate::WitnessCalculated { block_number, .. }
+                | CalculationUpdate::BlockDropped { block_number } => {
                     if matches!(self.cache_policy, CachePolicy::OnFailure) {
                         self.cache_storage
                             .remove_cached_block(*block_number)
@@ -53,6 +65,10 @@ impl CacheManagerTask {
                                 )
                             })?;
                     }
+                    self.mark_finished(*block_number)?;
+                }
+                CalculationUpdate::BlockFailed { block_number } => {
+                    self.mark_finished(*block_number)?;
                 }
                 _ => {}
             }
@@ -64,4 +80,11 @@ impl CacheManagerTask {
 
         Ok(())
     }
+
+    fn mark_finished(&self, block_number: u64) -> anyhow::Result<()> {
+        match &self.checkpoint {
+            Some(checkpoint) => checkpoint.finished(block_number),
+            None => Ok(()),
+        }
+    }
 }
@@ -0,0 +1,93 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context as _;
+
+use crate::cache::CacheStorage;
+
+/// Tracks the blocks handed to the pipeline in `catch_up` mode and persists the last processed
+/// block once the blocks are done, so that blocks still in flight are processed again after
+/// a restart.
+///
+/// A block is done once it has a result or has failed. Blocks may finish out of order (e.g. with
+/// several device groups), so the checkpoint only moves past a block once every block dispatched
+/// before it is done as well.
+#[derive(Debug, Clone)]
+pub(crate) struct CheckpointTracker(Arc<Mutex<TrackerState>>);
+
+#[derive(Debug)]
+struct TrackerState {
+    cache: CacheStorage,
+    in_flight: BTreeSet<u64>,
+    /// Done blocks after the checkpoint that wait for an earlier block to finish.
+    done: BTreeSet<u64>,
+}
+
+impl CheckpointTracker {
+    pub fn new(cache: CacheStorage) -> Self {
+        Self(Arc::new(Mutex::new(TrackerState {
+            cache,
+            in_flight: BTreeSet::new(),
+            done: BTreeSet::new(),
+        })))
+    }
+
+    /// Registers a block handed to the pipeline.
+    pub fn dispatched(&self, block_number: u64) {
+        let mut state = self.0.lock().expect("checkpoint tracker lock poisoned");
+        state.in_flight.insert(block_number);
+    }
+
+    /// Marks a block as done, persisting the checkpoint if it can move forward.
+    /// Blocks that weren't dispatched through the tracker are ignored.
+    pub fn finished(&self, block_number: u64) -> anyhow::Result<()> {
+        let mut state = self.0.lock().expect("checkpoint tracker lock poisoned");
+        if !state.in_flight.remove(&block_number) {
+            return Ok(());
+        }
+        state.done.insert(block_number);
+
+        let checkpoint = match state.in_flight.first() {
+            Some(&oldest_in_flight) => state.done.range(..oldest_in_flight).next_back().copied(),
+            None => state.done.last().copied(),
+        };
+        let Some(checkpoint) = checkpoint else {
+            return Ok(());
+        };
+        state.done = state.done.split_off(&(checkpoint + 1));
+        state
+            .cache
+            .save_checkpoint(checkpoint)
+            .context("failed to persist the last processed block")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CheckpointTracker;
+    use crate::cache::CacheStorage;
+
+    #[test]
+    fn checkpoint_only_moves_past_finished_blocks() {
+        let dir = tempfile::tempdir().expect("create tempdir");
+        let cache = CacheStorage::new(dir.path()).expect("create cache");
+        let tracker = CheckpointTracker::new(cache.clone());
+        for block_number in [10, 12, 14] {
+            tracker.dispatched(block_number);
+        }
+
+        // Block 10 is still in flight, so a restart has to start from it.
+        tracker.finished(12).unwrap();
+        assert_eq!(cache.load_checkpoint().unwrap(), None);
+
+        tracker.finished(10).unwrap();
+        assert_eq!(cache.load_checkpoint().unwrap(), Some(12));
+
+        // Blocks that weren't dispatched (e.g. in another mode) don't affect the checkpoint.
+        tracker.finished(20).unwrap();
+        assert_eq!(cache.load_checkpoint().unwrap(), Some(12));
+
+        tracker.finished(14).unwrap();
+        assert_eq!(cache.load_checkpoint().unwrap(), Some(14));
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use tokio::sync::mpsc::{Receiver, channel};
+use tokio::task::JoinSet;
+
+use crate::observability;
+use crate::prover::backend::PreparedBlock;
+use crate::prover::backend::ProvingBackend;
+use crate::tasks::CalculationUpdate;
+use crate::tasks::proving::{ProvingTask, SharedBlockReceiver};
+use crate::tasks::stale_blocks::StalePolicy;
+use crate::types::OnFailure;
+
+/// Set of GPU devices a single prover runs on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DeviceGroup {
+    /// Used to tell the groups apart in logs and metrics, e.g. `0,1,2,3`.
+    pub label: String,
+    pub devices: Vec<usize>,
+}
+
+/// Validates the configured device groups: every group must have devices, and no device may
+/// belong to more than one group.
+pub(crate) fn device_groups(config: &[Vec<usize>]) -> anyhow::Result<Vec<DeviceGroup>> {
+    let mut seen = HashSet::new();
+    config
+        .iter()
+        .enumerate()
+        .map(|(index, devices)| {
+            anyhow::ensure!(!devices.is_empty(), "device group #{index} has no devices");
+            for device in devices {
+                anyhow::ensure!(
+                    seen.insert(*device),
+                    "device {device} belongs to more than one device group"
+                );
+            }
+            let label = devices
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",");
+            Ok(DeviceGroup {
+                label,
+                devices: devices.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Spawns a proving task for each of the backends, which run on separate device groups.
+///
+/// The tasks take blocks from the same receiver, so every block goes to the next group that is
+/// free, and report to the same channel. Updates of different blocks may interleave, but the
+/// updates of every block are in order.
+pub(crate) fn spawn_device_group_tasks<B>(
+    join_set: &mut JoinSet<anyhow::Result<()>>,
+    backends: Vec<(DeviceGroup, B)>,
+    block_receiver: Receiver<PreparedBlock>,
+    on_failure: OnFailure,
+    stale_policy: Option<Arc<StalePolicy>>,
+) -> Receiver<CalculationUpdate>
+where
+    B: ProvingBackend + 'static,
+{
+    let block_receiver = SharedBlockReceiver::new(block_receiver);
+    let (command_sender, command_receiver) = channel(10);
+    for (group, backend) in backends {
+        let task_name = backend.name();
+        tracing::info!("Starting {task_name} task for device group {}", group.label);
+        let mut task = ProvingTask::for_group(
+            backend,
+            group.label,
+            block_receiver.clone(),
+            command_sender.clone(),
+            on_failure,
+        );
+        if let Some(stale_policy) = &stale_policy {
+            task = task.with_stale_policy(stale_policy.clone());
+        }
+        join_set.spawn(observability::bind_task(task_name, task.run()));
+    }
+    command_receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use tokio::sync::Semaphore;
+    use tokio::sync::mpsc::{Sender, channel};
+    use tokio::task::JoinSet;
+
+    use super::{DeviceGroup, device_groups, spawn_device_group_tasks};
+    use crate::prover::{
+        backend::{BackendKind, BackendOutput, PreparedBlock, ProvingBackend},
+        gpu_prover::ProofResult,
+        types::EthBlockInput,
+    };
+    use crate::tasks::CalculationUpdate;
+    use crate::types::OnFailure;
+
+    /// Mock prover that reports when it starts proving a block and finishes once it's allowed to.
+    struct MockGroupProver {
+        group: usize,
+        started: Sender<(usize, u64)>,
+        permits: Arc<Semaphore>,
+    }
+
+    #[async_trait]
+    impl ProvingBackend for MockGroupProver {
+        fn name(&self) -> &'static str {
+            "mock_group"
+        }
+
+        fn kind(&self) -> BackendKind {
+            BackendKind::Proof
+        }
+
+        async fn process(&mut self, block: PreparedBlock) -> anyhow::Result<BackendOutput> {
+            let block_number = block.block_number();
+            self.started.send((self.group, block_number)).await?;
+            self.permits.acquire().await?.forget();
+            Ok(BackendOutput::Proof(ProofResult {
+                proof_bytes: vec![self.group as u8],
+                cycles: block_number,
+                proving_time_secs: 1.0,
+            }))
+        }
+    }
+
+    fn prepared_block(number: u64) -> PreparedBlock {
+        let header = alloy::consensus::Header {
+            number,
+            ..Default::default()
+        };
+        PreparedBlock::new(EthBlockInput::with_header(header, Default::default()))
+    }
+
+    #[test]
+    fn device_groups_are_validated() {
+        let groups = device_groups(&[vec![0, 1], vec![2, 3]]).expect("valid groups");
+        assert_eq!(
+            groups,
+            [
+                DeviceGroup {
+                    label: "0,1".to_owned(),
+                    devices: vec![0, 1],
+                },
+                DeviceGroup {
+                    label: "2,3".to_owned(),
+                    devices: vec![2, 3],
+                },
+            ]
+        );
+
+        let err = device_groups(&[vec![0, 1], vec![1, 2]]).unwrap_err();
+        assert!(err.to_string().contains("device 1"), "{err}");
+        assert!(device_groups(&[vec![0], vec![]]).is_err());
+    }
+
+    #[tokio::test]
+    async fn blocks_are_dispatched_to_free_groups() {
+        let (started_sender, mut started) = channel(10);
+        let permits = Arc::new(Semaphore::new(0));
+        let backends = (0..2)
+            .map(|group| {
+                let devices = vec![group];
+                let backend = MockGroupProver {
+                    group,
+                    started: started_sender.clone(),
+                    permits: permits.clone(),
+                };
+                (device_groups(&[devices]).unwrap().remove(0), backend)
+            })
+            .collect();
+        let (block_sender, block_receiver) = channel(10);
+        let mut join_set = JoinSet::new();
+        let mut updates = spawn_device_group_tasks(
+            &mut join_set,
+            backends,
+            block_receiver,
+            OnFailure::Exit,
+            None,
+        );
+
+        for block_number in 1..=3 {
+            block_sender
+                .send(prepared_block(block_number))
+                .await
+                .expect("send block");
+        }
+        drop(block_sender);
+
+        // Both groups are busy with the first two blocks at the same time...
+        let (first_group, first_block) = started.recv().await.expect("first block started");
+        let (second_group, second_block) = started.recv().await.expect("second block started");
+        assert_ne!(first_group, second_group);
+        let mut started_blocks = [first_block, second_block];
+        started_blocks.sort_unstable();
+        assert_eq!(started_blocks, [1, 2]);
+        // ...so the third block waits until one of them is done.
+        let waiting = tokio::time::timeout(Duration::from_millis(50), started.recv()).await;
+        assert!(
+            waiting.is_err(),
+            "third block started while all groups are busy"
+        );
+
+        permits.add_permits(1);
+        let (_, third_block) = started.recv().await.expect("third block started");
+        assert_eq!(third_block, 3);
+        permits.add_permits(2);
+
+        let mut proven = Vec::new();
+        while let Some(update) = updates.recv().await {
+            if let CalculationUpdate::ProofProvided { block_number, .. } = update {
+                proven.push(block_number);
+            }
+        }
+        proven.sort_unstable();
+        assert_eq!(proven, [1, 2, 3]);
+        while let Some(result) = join_set.join_next().await {
+            result.expect("task").expect("task ok");
+        }
+    }
+}
@@ -1,7 +1,14 @@
-use crate::clients::ethproofs::EthproofsClient;
-use crate::tasks::CalculationUpdate;
+use anyhow::Context as _;
 use tokio::sync::mpsc::Receiver;
 
+use crate::clients::ethproofs::outbox::{
+    Outbox, OutboxEntry, ProofDetails, UpdateKind, unix_millis_now,
+};
+use crate::clients::ethproofs::{EthProofsApi, is_retryable};
+use crate::metrics::METRICS;
+use crate::observability;
+use crate::tasks::CalculationUpdate;
+
 #[derive(Debug)]
 pub(crate) struct EthProofsNoOpTask {
     command_mode_receiver: Receiver<CalculationUpdate>,
@@ -23,68 +30,317 @@ impl EthProofsNoOpTask {
     }
 }
 
+/// Submits proof status updates to EthProofs.
+///
+/// Updates that could not be submitted are stored in the [`Outbox`] and retried until
+/// EthProofs acknowledges them, including across restarts.
 #[derive(Debug)]
-pub(crate) struct EthProofsUploadTask {
-    client: EthproofsClient,
+pub(crate) struct EthProofsUploadTask<C> {
+    client: C,
     command_mode_receiver: Receiver<CalculationUpdate>,
+    outbox: Outbox,
 }
 
-impl EthProofsUploadTask {
+impl<C: EthProofsApi> EthProofsUploadTask<C> {
     pub fn new(
-        client: EthproofsClient,
+        client: C,
         command_mode_receiver: Receiver<CalculationUpdate>,
+        outbox: Outbox,
     ) -> Self {
         Self {
             client,
             command_mode_receiver,
+            outbox,
         }
     }
 
     pub async fn run(mut self) -> anyhow::Result<()> {
-        while let Some(command) = self.command_mode_receiver.recv().await {
-            match command {
-                CalculationUpdate::ProofQueued { block_number } => {
-                    tracing::info!("Marking block {block_number} as queued");
-                    if let Err(err) = self.client.queue_proof(block_number).await {
-                        tracing::error!("Failed to mark block {block_number} as queued: {err}");
-                    } else {
-                        tracing::info!("Block {block_number} marked as queued");
-                    }
+        if !self.outbox.is_empty() {
+            tracing::info!(
+                "Found {} unsubmitted EthProofs updates in the outbox",
+                self.outbox.len()
+            );
+        }
+        self.update_backlog_metric();
+
+        loop {
+            let next_retry = self.outbox.next_due_in(unix_millis_now());
+            tokio::select! {
+                command = self.command_mode_receiver.recv() => {
+                    let Some(command) = command else {
+                        break;
+                    };
+                    self.handle_command(command).await?;
                 }
-                CalculationUpdate::ProofProving { block_number } => {
-                    tracing::info!("Marking block {block_number} as proving");
-                    if let Err(err) = self.client.proving_proof(block_number).await {
-                        tracing::error!("Failed to mark block {block_number} as proving: {err}");
-                    } else {
-                        tracing::info!("Block {block_number} marked as proving");
-                    }
+                _ = tokio::time::sleep(next_retry.unwrap_or_default()), if next_retry.is_some() => {
+                    self.retry_due_entries().await?;
                 }
-                CalculationUpdate::ProofProvided {
+            }
+        }
+
+        if !self.outbox.is_empty() {
+            tracing::warn!(
+                "Pipeline finished with {} unsubmitted EthProofs updates; they will be retried on the next start",
+                self.outbox.len()
+            );
+        }
+        Ok(())
+    }
+
+    async fn handle_command(&mut self, command: CalculationUpdate) -> anyhow::Result<()> {
+        match command {
+            CalculationUpdate::ProofQueued { block_number } => {
+                self.submit(block_number, UpdateKind::Queued, None).await
+            }
+            CalculationUpdate::ProofProving { block_number } => {
+                self.submit(block_number, UpdateKind::Proving, None).await
+            }
+            CalculationUpdate::ProofProvided {
+                block_number,
+                proof_result,
+                ..
+            } => {
+                let details = ProofDetails {
+                    cycles: proof_result.cycles,
+                    proving_time_secs: proof_result.proving_time_secs,
+                };
+                self.submit(
                     block_number,
-                    proof_result,
-                } => {
-                    tracing::info!("Uploading proof for block {block_number}");
-                    if let Err(err) = self
-                        .client
+                    UpdateKind::Proved,
+                    Some((details, proof_result.proof_bytes.as_slice())),
+                )
+                .await
+            }
+            CalculationUpdate::BlockReorged {
+                block_number,
+                orphaned_hash,
+                ..
+            } => {
+                // EthProofs has no way to retract a submitted proof, so only the updates that
+                // haven't been delivered yet can be dropped; the rest is flagged for operators.
+                let dropped = self.outbox.remove_block(block_number)?;
+                self.update_backlog_metric();
+                let err = anyhow::anyhow!(
+                    "Block {block_number} ({orphaned_hash}) was reorged out after being proven; \
+                     dropped {dropped} pending EthProofs updates"
+                );
+                observability::capture_anyhow(&err);
+                tracing::warn!("{err}");
+                Ok(())
+            }
+            _ => {
+                // Ignore other commands
+                Ok(())
+            }
+        }
+    }
+
+    /// Submits an update, moving it to the outbox if the submission fails.
+    async fn submit(
+        &mut self,
+        block_number: u64,
+        kind: UpdateKind,
+        proof: Option<(ProofDetails, &[u8])>,
+    ) -> anyhow::Result<()> {
+        // Updates of a single block must be delivered in order, so if an earlier update
+        // is still waiting in the outbox, the new one has to wait as well.
+        let attempts = if self.outbox.has_pending_for(block_number) {
+            tracing::info!(
+                "Block {block_number} has pending EthProofs updates, deferring {kind:?} update"
+            );
+            0
+        } else {
+            tracing::info!("Submitting {kind:?} update for block {block_number}");
+            let result = match &proof {
+                Some((details, proof_bytes)) => {
+                    self.client
                         .send_proof(
                             block_number,
-                            &proof_result.proof_bytes,
-                            proof_result.proving_time_secs,
-                            proof_result.cycles,
+                            proof_bytes,
+                            details.proving_time_secs,
+                            details.cycles,
                         )
                         .await
-                    {
-                        tracing::error!("Failed to upload proof for block {block_number}: {err}");
-                    } else {
-                        tracing::info!("Uploaded proof for block {block_number}");
-                    }
                 }
-                _ => {
-                    // Ignore other commands
+                None => self.send_status(block_number, kind).await,
+            };
+            match result {
+                Ok(()) => {
+                    tracing::info!("Submitted {kind:?} update for block {block_number}");
+                    return Ok(());
+                }
+                Err(err) if !is_retryable(&err) => {
+                    report_rejected(block_number, kind, &err);
+                    return Ok(());
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "Failed to submit {kind:?} update for block {block_number}, moving it to the outbox: {err:#}"
+                    );
+                    1
                 }
             }
-        }
+        };
 
+        self.outbox
+            .push(block_number, kind, proof, attempts, unix_millis_now())
+            .with_context(|| {
+                format!("failed to store {kind:?} update for block {block_number} in the outbox")
+            })?;
+        self.update_backlog_metric();
         Ok(())
     }
+
+    async fn retry_due_entries(&mut self) -> anyhow::Result<()> {
+        for entry in self.outbox.due_entries(unix_millis_now()) {
+            let block_number = entry.block_number;
+            let kind = entry.kind;
+            tracing::info!(
+                "Retrying {kind:?} update for block {block_number} (attempt {})",
+                entry.attempts + 1
+            );
+            match self.resend(&entry).await {
+                Ok(()) => {
+                    tracing::info!("Submitted {kind:?} update for block {block_number}");
+                    self.outbox.remove(&entry).with_context(|| {
+                        format!("failed to remove {kind:?} update for block {block_number} from the outbox")
+                    })?;
+                }
+                Err(err) if !is_retryable(&err) => {
+                    report_rejected(block_number, kind, &err);
+                    self.outbox.remove(&entry).with_context(|| {
+                        format!("failed to remove {kind:?} update for block {block_number} from the outbox")
+                    })?;
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "Failed to submit {kind:?} update for block {block_number} from the outbox: {err:#}"
+                    );
+                    if entry.attempts == 1 {
+                        // Report an update only once, when it fails for the second time,
+                        // to avoid flooding Sentry with transient errors.
+                        observability::capture_anyhow(&err);
+                    }
+                    self.outbox
+                        .record_failure(&entry, unix_millis_now())
+                        .with_context(|| {
+                            format!("failed to update {kind:?} update for block {block_number} in the outbox")
+                        })?;
+                }
+            }
+        }
+        self.update_backlog_metric();
+        Ok(())
+    }
+
+    async fn resend(&self, entry: &OutboxEntry) -> anyhow::Result<()> {
+        match &entry.proof {
+            Some(details) => {
+                let proof_bytes = self.outbox.load_proof(entry.block_number)?;
+                self.client
+                    .send_proof(
+                        entry.block_number,
+                        &proof_bytes,
+                        details.proving_time_secs,
+                        details.cycles,
+                    )
+                    .await
+            }
+            None => self.send_status(entry.block_number, entry.kind).await,
+        }
+    }
+
+    async fn send_status(&self, block_number: u64, kind: UpdateKind) -> anyhow::Result<()> {
+        match kind {
+            UpdateKind::Queued => self.client.queue_proof(block_number).await,
+            UpdateKind::Proving => self.client.proving_proof(block_number).await,
+            UpdateKind::Proved => {
+                anyhow::bail!("proved update for block {block_number} has no proof attached")
+            }
+        }
+    }
+
+    fn update_backlog_metric(&self) {
+        METRICS
+            .ethproofs_outbox_backlog
+            .set(self.outbox.len() as u64);
+    }
+}
+
+/// Reports an update that EthProofs rejected. It's dropped rather than retried, since it would
+/// fail the same way again and hold back every later update of the block.
+fn report_rejected(block_number: u64, kind: UpdateKind, err: &anyhow::Error) {
+    METRICS.ethproofs_rejected_updates_total.inc();
+    observability::capture_anyhow(err);
+    tracing::error!(
+        "EthProofs rejected {kind:?} update for block {block_number}, dropping it: {err:#}"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use reqwest::StatusCode;
+    use tokio::sync::mpsc::channel;
+
+    use super::EthProofsUploadTask;
+    use crate::clients::ethproofs::outbox::Outbox;
+    use crate::clients::ethproofs::{EthProofsApi, StatusError};
+    use crate::tasks::CalculationUpdate;
+
+    /// Fails every request with the given status.
+    struct FailingEthProofs(StatusCode);
+
+    #[async_trait]
+    impl EthProofsApi for FailingEthProofs {
+        async fn queue_proof(&self, _block_number: u64) -> anyhow::Result<()> {
+            Err(StatusError(self.0).into())
+        }
+
+        async fn proving_proof(&self, _block_number: u64) -> anyhow::Result<()> {
+            Err(StatusError(self.0).into())
+        }
+
+        async fn send_proof(
+            &self,
+            _block_number: u64,
+            _proof_bytes: &[u8],
+            _proving_time_secs: f64,
+            _cycles: u64,
+        ) -> anyhow::Result<()> {
+            Err(StatusError(self.0).into())
+        }
+    }
+
+    async fn outbox_len_after_failures(status: StatusCode) -> usize {
+        let dir = tempfile::tempdir().unwrap();
+        let (sender, receiver) = channel(2);
+        let outbox = Outbox::open(dir.path()).unwrap();
+        let task = EthProofsUploadTask::new(FailingEthProofs(status), receiver, outbox);
+        sender
+            .send(CalculationUpdate::ProofQueued { block_number: 1 })
+            .await
+            .unwrap();
+        sender
+            .send(CalculationUpdate::ProofProving { block_number: 1 })
+            .await
+            .unwrap();
+        drop(sender);
+        task.run().await.unwrap();
+
+        Outbox::open(dir.path()).unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_kept_for_retries() {
+        assert_eq!(
+            outbox_len_after_failures(StatusCode::SERVICE_UNAVAILABLE).await,
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_updates_are_dropped() {
+        assert_eq!(outbox_len_after_failures(StatusCode::BAD_REQUEST).await, 0);
+    }
 }
@@ -1,16 +1,26 @@
+use alloy::primitives::B256;
+
 use crate::prover::gpu_prover::ProofResult;
 
 pub(crate) mod block_stream;
 pub(crate) mod cache_manager;
-pub(crate) mod cpu_witness;
+pub(crate) mod checkpoint;
+pub(crate) mod device_groups;
 pub(crate) mod eth_proofs_upload;
-pub(crate) mod gpu_prove;
+pub(crate) mod preparation;
+pub(crate) mod proof_store;
+pub(crate) mod proof_verification;
+pub(crate) mod proving;
+pub(crate) mod range_summary;
+pub(crate) mod reorg_monitor;
+pub(crate) mod stale_blocks;
+pub(crate) mod witness_sink;
 
 #[derive(Debug)]
 pub(crate) enum CalculationUpdate {
     WitnessCalculated {
         block_number: u64,
-        _data: Vec<u32>,
+        data: Vec<u32>,
     },
     ProofQueued {
         block_number: u64,
@@ -20,6 +30,22 @@ pub(crate) enum CalculationUpdate {
     },
     ProofProvided {
         block_number: u64,
+        /// Hash of the proven block header, which the proof is expected to commit to.
+        block_hash: B256,
         proof_result: ProofResult,
     },
+    /// The block couldn't be processed, so no witness or proof will be provided for it.
+    BlockFailed {
+        block_number: u64,
+    },
+    /// The block was dropped as stale, so no witness or proof will be provided for it.
+    BlockDropped {
+        block_number: u64,
+    },
+    /// A proven block is no longer canonical: the proof is valid, but for an orphaned block.
+    BlockReorged {
+        block_number: u64,
+        orphaned_hash: B256,
+        canonical_hash: B256,
+    },
 }
@@ -0,0 +1,334 @@
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use futures::StreamExt as _;
+use futures::stream::FuturesOrdered;
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+
+use crate::{
+    metrics::{InflightGuard, METRICS},
+    observability,
+    prover::{backend::PreparedBlock, types::EthBlockInput, witness},
+    tasks::stale_blocks::StalePolicy,
+    types::{OnFailure, StageLimits, WitnessValidation},
+};
+
+/// Prepares blocks for the proving stage: checks their execution witnesses and builds their
+/// oracles on blocking threads, so that a backend can start proving a block as soon as it's
+/// done with the previous one.
+///
+/// Up to `concurrency` blocks are prepared at the same time; prepared blocks are handed over in
+/// the order they were received.
+#[derive(Debug)]
+pub(crate) struct PreparationTask {
+    block_receiver: Receiver<EthBlockInput>,
+    block_sender: Sender<PreparedBlock>,
+    concurrency: usize,
+    on_failure: OnFailure,
+    witness_validation: WitnessValidation,
+    stale_policy: Option<Arc<StalePolicy>>,
+    prepare: fn(EthBlockInput) -> PreparedBlock,
+}
+
+impl PreparationTask {
+    pub fn new(
+        block_receiver: Receiver<EthBlockInput>,
+        limits: StageLimits,
+        on_failure: OnFailure,
+        witness_validation: WitnessValidation,
+    ) -> (Self, Receiver<PreparedBlock>) {
+        let (block_sender, prepared_receiver) = channel(limits.queue_size);
+        (
+            Self {
+                block_receiver,
+                block_sender,
+                concurrency: limits.concurrency,
+                on_failure,
+                witness_validation,
+                stale_policy: None,
+                prepare: PreparedBlock::prepare,
+            },
+            prepared_receiver,
+        )
+    }
+
+    /// Drops stale blocks instead of preparing them.
+    pub fn with_stale_policy(mut self, stale_policy: Arc<StalePolicy>) -> Self {
+        self.stale_policy = Some(stale_policy);
+        self
+    }
+
+    /// Replaces the preparation of blocks, e.g. to skip building their oracles.
+    #[cfg(test)]
+    pub fn with_prepare(mut self, prepare: fn(EthBlockInput) -> PreparedBlock) -> Self {
+        self.prepare = prepare;
+        self
+    }
+
+    pub async fn run(self) -> anyhow::Result<()> {
+        let result = self.run_inner().await;
+        if let Err(ref err) = result {
+            observability::capture_anyhow(err);
+        }
+        result
+    }
+
+    async fn run_inner(mut self) -> anyhow::Result<()> {
+        let mut preparations = FuturesOrdered::new();
+        let mut receiver_closed = false;
+        loop {
+            tokio::select! {
+                Some((block_number, result)) = preparations.next(), if !preparations.is_empty() => {
+                    self.hand_over(block_number, result).await?;
+                }
+                input = self.block_receiver.recv(), if !receiver_closed && preparations.len() < self.concurrency => {
+                    let Some(input) = input else {
+                        receiver_closed = true;
+                        continue;
+                    };
+                    let stale = self.is_stale(&input);
+                    preparations.push_back(prepare_block(
+                        input,
+                        stale,
+                        self.witness_validation,
+                        self.prepare,
+                    ));
+                }
+                else => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn is_stale(&self, input: &EthBlockInput) -> bool {
+        self.stale_policy
+            .as_ref()
+            .is_some_and(|policy| policy.is_stale("prepare", input))
+    }
+
+    async fn hand_over(
+        &self,
+        block_number: u64,
+        result: anyhow::Result<PreparedBlock>,
+    ) -> anyhow::Result<()> {
+        let mut block = match result {
+            Ok(block) => block,
+            // The block went down with the panicked thread, so there's nothing to hand over.
+            Err(err) => return self.handle_failure(block_number, &err),
+        };
+        if let Some(err) = block.preparation_error() {
+            // Failed blocks are still handed over, so that the proving stage reports them.
+            self.handle_failure(block_number, err)?;
+        } else if !block.is_dropped() && self.is_stale(&block.input) {
+            // Preparation takes a while, so the block may have become stale in the meantime.
+            block = PreparedBlock::dropped(block.input);
+        }
+        self.block_sender.send(block).await.with_context(|| {
+            format!("failed to send prepared block {block_number} to the proving stage")
+        })
+    }
+
+    fn handle_failure(&self, block_number: u64, err: &anyhow::Error) -> anyhow::Result<()> {
+        METRICS.preparation_failure_total.inc();
+        match self.on_failure {
+            OnFailure::Exit => anyhow::bail!("Failed to prepare block {block_number}: {err:#}"),
+            OnFailure::Continue => {
+                observability::capture_anyhow(err);
+                tracing::error!("Failed to prepare block {block_number}: {err:#}");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Prepares the block; stale blocks are passed on as dropped without being prepared, so that
+/// the proving stage reports them in order.
+async fn prepare_block(
+    input: EthBlockInput,
+    stale: bool,
+    witness_validation: WitnessValidation,
+    prepare: fn(EthBlockInput) -> PreparedBlock,
+) -> (u64, anyhow::Result<PreparedBlock>) {
+    let block_number = input.block_header.number;
+    if stale {
+        return (block_number, Ok(PreparedBlock::dropped(input)));
+    }
+    let result = observability::bind_block("prepare", block_number, async move {
+        tracing::info!("Preparing block {block_number} for proving");
+        let _inflight = InflightGuard::new(&METRICS.inflight_preparations);
+        let latency = METRICS.preparation_duration.start();
+        let result = observability::spawn_blocking_on_current_hub(move || {
+            match validate_witness(&input, witness_validation) {
+                Ok(()) => prepare(input),
+                Err(err) => PreparedBlock::failed(input, err),
+            }
+        })
+        .await;
+        latency.observe();
+        match result {
+            Ok(block) => Ok(block),
+            Err(err) => {
+                let panic_msg = crate::utils::extract_panic_message(err);
+                Err(anyhow::anyhow!(
+                    "preparation task panicked while processing block {block_number}: {panic_msg}"
+                ))
+            }
+        }
+    })
+    .await;
+    (block_number, result)
+}
+
+/// Checks the execution witness before it's handed to the backend, so that incomplete
+/// witnesses are reported with what's missing rather than as an opaque STF failure.
+/// Returns an error only if the witness is incomplete and validation is enforced.
+fn validate_witness(
+    input: &EthBlockInput,
+    witness_validation: WitnessValidation,
+) -> anyhow::Result<()> {
+    if witness_validation == WitnessValidation::Off {
+        return Ok(());
+    }
+    let report = witness::validate(input);
+    if report.is_valid() {
+        tracing::debug!("Validated {report}");
+        return Ok(());
+    }
+    // The block hub is scoped to this block, so the report is attached to its events only.
+    observability::attach_json(
+        &format!("witness_report_{}.json", report.block_number),
+        &report,
+    );
+    tracing::warn!("Incomplete execution {report:#}");
+    match witness_validation {
+        WitnessValidation::Enforce => anyhow::bail!("incomplete execution {report}"),
+        WitnessValidation::Off | WitnessValidation::Warn => {
+            observability::capture_warning(&format!("Incomplete execution {report}"));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::sync::mpsc::channel;
+
+    use super::PreparationTask;
+    use crate::prover::{backend::PreparedBlock, types::EthBlockInput};
+    use crate::tasks::stale_blocks::StalePolicy;
+    use crate::types::{OnFailure, StageLimits, WitnessValidation};
+
+    fn block_input(number: u64, timestamp: u64) -> EthBlockInput {
+        let header = alloy::consensus::Header {
+            number,
+            timestamp,
+            ..Default::default()
+        };
+        EthBlockInput::with_header(header, Default::default())
+    }
+
+    fn now_secs() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn task(
+        block_receiver: tokio::sync::mpsc::Receiver<EthBlockInput>,
+        on_failure: OnFailure,
+        prepare: fn(EthBlockInput) -> PreparedBlock,
+    ) -> (PreparationTask, tokio::sync::mpsc::Receiver<PreparedBlock>) {
+        let (mut task, prepared) = PreparationTask::new(
+            block_receiver,
+            StageLimits::new(3, 10).unwrap(),
+            on_failure,
+            WitnessValidation::Off,
+        );
+        task.prepare = prepare;
+        (task, prepared)
+    }
+
+    #[tokio::test]
+    async fn blocks_are_prepared_concurrently_and_handed_over_in_order() {
+        let (block_sender, block_receiver) = channel(10);
+        let (task, mut prepared) = task(block_receiver, OnFailure::Exit, |input| {
+            // Earlier blocks take longer, so they finish last.
+            let delay = 40 - 10 * input.block_header.number;
+            std::thread::sleep(Duration::from_millis(delay));
+            PreparedBlock::new(input)
+        });
+        let handle = tokio::spawn(task.run());
+
+        for block_number in 1..=3 {
+            block_sender
+                .send(block_input(block_number, now_secs()))
+                .await
+                .expect("send block");
+        }
+        drop(block_sender);
+
+        let mut order = Vec::new();
+        while let Some(block) = prepared.recv().await {
+            order.push(block.block_number());
+        }
+        assert_eq!(order, [1, 2, 3]);
+        handle.await.expect("task").expect("task ok");
+    }
+
+    #[tokio::test]
+    async fn stale_and_failed_blocks_are_handed_over() {
+        let (block_sender, block_receiver) = channel(10);
+        let (task, mut prepared) = task(block_receiver, OnFailure::Continue, |input| {
+            if input.block_header.number == 2 {
+                return PreparedBlock::failed(input, anyhow::anyhow!("stub failure"));
+            }
+            PreparedBlock::new(input)
+        });
+        let task = task.with_stale_policy(Arc::new(StalePolicy::new(Duration::from_secs(60))));
+        let handle = tokio::spawn(task.run());
+
+        let now = now_secs();
+        block_sender.send(block_input(1, now)).await.unwrap();
+        block_sender.send(block_input(2, now)).await.unwrap();
+        // Two minutes old, so it can't be proven within a minute anymore.
+        block_sender.send(block_input(3, now - 120)).await.unwrap();
+        block_sender.send(block_input(4, now)).await.unwrap();
+        drop(block_sender);
+
+        let mut order = Vec::new();
+        while let Some(block) = prepared.recv().await {
+            order.push((
+                block.block_number(),
+                block.preparation_error().is_some(),
+                block.is_dropped(),
+            ));
+        }
+        assert_eq!(
+            order,
+            [
+                (1, false, false),
+                (2, true, false),
+                (3, false, true),
+                (4, false, false)
+            ]
+        );
+        handle.await.expect("task").expect("task ok");
+    }
+
+    #[tokio::test]
+    async fn failures_stop_the_task_in_exit_mode() {
+        let (block_sender, block_receiver) = channel(10);
+        let (task, _prepared) = task(block_receiver, OnFailure::Exit, |input| {
+            PreparedBlock::failed(input, anyhow::anyhow!("stub failure"))
+        });
+        let handle = tokio::spawn(task.run());
+
+        block_sender.send(block_input(1, now_secs())).await.unwrap();
+        let err = handle.await.expect("task").unwrap_err();
+        assert!(err.to_string().contains("block 1"), "{err}");
+    }
+}
@@ -0,0 +1,93 @@
+use alloy::primitives::B256;
+use anyhow::Context as _;
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+
+use crate::observability;
+use crate::proof_store::ProofStore;
+use crate::tasks::CalculationUpdate;
+
+/// Persists every generated proof before it is passed further down the pipeline
+/// (e.g. to be uploaded to EthProofs).
+#[derive(Debug)]
+pub(crate) struct ProofStoreTask {
+    command_mode_receiver: Receiver<CalculationUpdate>,
+    command_mode_sender: Sender<CalculationUpdate>,
+    proof_store: ProofStore,
+    app_bin_hash: B256,
+}
+
+impl ProofStoreTask {
+    pub fn new(
+        receiver: Receiver<CalculationUpdate>,
+        proof_store: ProofStore,
+        app_bin_hash: B256,
+    ) -> (Self, Receiver<CalculationUpdate>) {
+        let (command_mode_sender, command_mode_receiver) = channel(10);
+        (
+            Self {
+                command_mode_receiver: receiver,
+                command_mode_sender,
+                proof_store,
+                app_bin_hash,
+            },
+            command_mode_receiver,
+        )
+    }
+
+    pub async fn run(self) -> anyhow::Result<()> {
+        let result = self.run_inner().await;
+        if let Err(ref err) = result {
+            observability::capture_anyhow(err);
+        }
+        result
+    }
+
+    async fn run_inner(mut self) -> anyhow::Result<()> {
+        while let Some(command) = self.command_mode_receiver.recv().await {
+            match &command {
+                CalculationUpdate::ProofProvided {
+                    block_number,
+                    proof_result,
+                    ..
+                } => {
+                    // Failing to persist a proof should not prevent it from being submitted.
+                    match self
+                        .proof_store
+                        .save(*block_number, proof_result, self.app_bin_hash)
+                        .with_context(|| format!("failed to store proof for block {block_number}"))
+                    {
+                        Ok(_) => tracing::info!("Stored proof for block {block_number}"),
+                        Err(err) => {
+                            observability::capture_anyhow(&err);
+                            tracing::error!("{err:#}");
+                        }
+                    }
+                }
+                CalculationUpdate::BlockReorged { block_number, .. } => {
+                    match self
+                        .proof_store
+                        .mark_orphaned(*block_number)
+                        .with_context(|| {
+                            format!("failed to mark proof for block {block_number} as orphaned")
+                        }) {
+                        Ok(true) => {
+                            tracing::info!("Marked proof for block {block_number} as orphaned")
+                        }
+                        Ok(false) => {}
+                        Err(err) => {
+                            observability::capture_anyhow(&err);
+                            tracing::error!("{err:#}");
+                        }
+                    }
+                }
+                _ => {}
+            }
+            self.command_mode_sender
+                .send(command)
+                .await
+                .context("failed to forward proof store command")?;
+        }
+
+        Ok(())
+    }
+}
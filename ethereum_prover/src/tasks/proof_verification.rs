@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use alloy::primitives::B256;
+use anyhow::Context as _;
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+
+use crate::cache::CacheStorage;
+use crate::metrics::METRICS;
+use crate::observability;
+use crate::tasks::CalculationUpdate;
+use crate::types::OnFailure;
+use crate::verifier::ProofVerifier;
+
+/// Verifies every generated proof and withholds the invalid ones, so that they are
+/// never stored or submitted to EthProofs; their blocks are reported as failed instead.
+#[derive(Debug)]
+pub(crate) struct ProofVerificationTask {
+    command_mode_receiver: Receiver<CalculationUpdate>,
+    command_mode_sender: Sender<CalculationUpdate>,
+    verifier: Arc<ProofVerifier>,
+    cache_storage: CacheStorage,
+    on_failure: OnFailure,
+}
+
+impl ProofVerificationTask {
+    pub fn new(
+        receiver: Receiver<CalculationUpdate>,
+        verifier: ProofVerifier,
+        cache_storage: CacheStorage,
+        on_failure: OnFailure,
+    ) -> (Self, Receiver<CalculationUpdate>) {
+        let (command_mode_sender, command_mode_receiver) = channel(10);
+        (
+            Self {
+                command_mode_receiver: receiver,
+                command_mode_sender,
+                verifier: Arc::new(verifier),
+                cache_storage,
+                on_failure,
+            },
+            command_mode_receiver,
+        )
+    }
+
+    pub async fn run(self) -> anyhow::Result<()> {
+        let result = self.run_inner().await;
+        if let Err(ref err) = result {
+            observability::capture_anyhow(err);
+        }
+        result
+    }
+
+    async fn run_inner(mut self) -> anyhow::Result<()> {
+        while let Some(mut command) = self.command_mode_receiver.recv().await {
+            if let CalculationUpdate::ProofProvided {
+                block_number,
+                block_hash,
+                proof_result,
+            } = &command
+            {
+                let block_number = *block_number;
+                let verification = observability::bind_block(
+                    "proof_verification",
+                    block_number,
+                    verify_proof(
+                        self.verifier.clone(),
+                        block_number,
+                        *block_hash,
+                        proof_result.proof_bytes.clone(),
+                    ),
+                )
+                .await;
+
+                if let Err(err) = verification {
+                    METRICS.proof_verification_failure_total.inc();
+                    match self
+                        .cache_storage
+                        .save_invalid_proof(block_number, &proof_result.proof_bytes)
+                    {
+                        Ok(path) => tracing::info!(
+                            "Saved invalid proof for block {block_number} to {}",
+                            path.display()
+                        ),
+                        Err(err) => tracing::error!(
+                            "Failed to save invalid proof for block {block_number}: {err:#}"
+                        ),
+                    }
+                    match self.on_failure {
+                        OnFailure::Exit => return Err(err),
+                        OnFailure::Continue => {
+                            observability::capture_anyhow(&err);
+                            tracing::error!("Withholding proof: {err:#}");
+                            command = CalculationUpdate::BlockFailed { block_number };
+                        }
+                    }
+                } else {
+                    tracing::info!("Verified proof for block {block_number}");
+                }
+            }
+            self.command_mode_sender
+                .send(command)
+                .await
+                .context("failed to forward proof verification command")?;
+        }
+
+        Ok(())
+    }
+}
+
+async fn verify_proof(
+    verifier: Arc<ProofVerifier>,
+    block_number: u64,
+    block_hash: B256,
+    proof_bytes: Vec<u8>,
+) -> anyhow::Result<()> {
+    observability::spawn_blocking_on_current_hub(move || {
+        verifier.verify_bytes(&proof_bytes, Some(block_hash))
+    })
+    .await
+    .map_err(|err| {
+        anyhow::anyhow!(
+            "proof verifier panicked: {}",
+            crate::utils::extract_panic_message(err)
+        )
+    })
+    .and_then(|result| result)
+    .with_context(|| format!("proof for block {block_number} failed verification"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use alloy::primitives::B256;
+    use tokio::sync::mpsc::channel;
+
+    use super::ProofVerificationTask;
+    use crate::cache::CacheStorage;
+    use crate::prover::gpu_prover::ProofResult;
+    use crate::tasks::CalculationUpdate;
+    use crate::types::OnFailure;
+    use crate::verifier::{LAYOUTS_FILE_NAME, ProofVerifier, SETUP_FILE_NAME};
+
+    #[tokio::test]
+    async fn invalid_proofs_are_withheld() {
+        let artifacts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../artifacts");
+        let verifier = ProofVerifier::load(
+            &artifacts_dir.join(SETUP_FILE_NAME),
+            &artifacts_dir.join(LAYOUTS_FILE_NAME),
+        )
+        .expect("load verifier");
+        let dir = tempfile::tempdir().expect("create tempdir");
+        let cache_storage = CacheStorage::new(dir.path()).expect("create cache");
+
+        let (sender, receiver) = channel(10);
+        let (task, mut output) =
+            ProofVerificationTask::new(receiver, verifier, cache_storage, OnFailure::Continue);
+        let handle = tokio::spawn(task.run());
+
+        sender
+            .send(CalculationUpdate::ProofQueued { block_number: 1 })
+            .await
+            .expect("send update");
+        sender
+            .send(CalculationUpdate::ProofProvided {
+                block_number: 1,
+                block_hash: B256::ZERO,
+                proof_result: ProofResult {
+                    proof_bytes: b"not a proof".to_vec(),
+                    cycles: 1,
+                    proving_time_secs: 1.0,
+                },
+            })
+            .await
+            .expect("send update");
+        drop(sender);
+
+        handle.await.expect("join task").expect("task succeeds");
+        assert!(matches!(
+            output.recv().await,
+            Some(CalculationUpdate::ProofQueued { block_number: 1 })
+        ));
+        assert!(matches!(
+            output.recv().await,
+            Some(CalculationUpdate::BlockFailed { block_number: 1 })
+        ));
+        assert!(output.recv().await.is_none());
+        assert_eq!(
+            std::fs::read(dir.path().join("blocks/1/invalid_proof.bin")).expect("read proof"),
+            b"not a proof"
+        );
+    }
+}
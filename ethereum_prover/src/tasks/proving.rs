@@ -0,0 +1,472 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+use vise::{Counter, Gauge, Histogram};
+
+use crate::{
+    metrics::{InflightGuard, METRICS},
+    observability,
+    prover::backend::{BackendKind, BackendOutput, PreparedBlock, ProvingBackend},
+    tasks::{CalculationUpdate, stale_blocks::StalePolicy},
+    types::OnFailure,
+};
+
+/// Receiver of the blocks to process that can be shared between several proving tasks.
+///
+/// Tasks only wait for a block once they are free, and the lock is fair, so every block is taken
+/// by the task that became free first.
+#[derive(Debug, Clone)]
+pub(crate) struct SharedBlockReceiver(Arc<Mutex<Receiver<PreparedBlock>>>);
+
+impl SharedBlockReceiver {
+    pub fn new(receiver: Receiver<PreparedBlock>) -> Self {
+        Self(Arc::new(Mutex::new(receiver)))
+    }
+
+    async fn recv(&self) -> Option<PreparedBlock> {
+        self.0.lock().await.recv().await
+    }
+}
+
+/// Drives a [`ProvingBackend`]: feeds it blocks from the preparation stage and reports
+/// the results to the rest of the pipeline.
+#[derive(Debug)]
+pub(crate) struct ProvingTask<B> {
+    backend: B,
+    block_receiver: SharedBlockReceiver,
+    command_sender: Sender<CalculationUpdate>,
+    on_failure: OnFailure,
+    stale_policy: Option<Arc<StalePolicy>>,
+    /// Label of the device group the backend runs on, if the blocks are shared between groups.
+    group: Option<String>,
+}
+
+impl<B: ProvingBackend> ProvingTask<B> {
+    pub fn new(
+        backend: B,
+        block_receiver: Receiver<PreparedBlock>,
+        on_failure: OnFailure,
+    ) -> (Self, Receiver<CalculationUpdate>) {
+        let (command_sender, command_receiver) = channel(10);
+        (
+            Self {
+                backend,
+                block_receiver: SharedBlockReceiver::new(block_receiver),
+                command_sender,
+                on_failure,
+                stale_policy: None,
+                group: None,
+            },
+            command_receiver,
+        )
+    }
+
+    /// Creates a task for one of several device groups, which take blocks from the same receiver
+    /// and report to the same sender.
+    pub fn for_group(
+        backend: B,
+        group: String,
+        block_receiver: SharedBlockReceiver,
+        command_sender: Sender<CalculationUpdate>,
+        on_failure: OnFailure,
+    ) -> Self {
+        Self {
+            backend,
+            block_receiver,
+            command_sender,
+            on_failure,
+            stale_policy: None,
+            group: Some(group),
+        }
+    }
+
+    /// Drops blocks that became stale while waiting for the backend, and reports proving times
+    /// to the policy.
+    pub fn with_stale_policy(mut self, stale_policy: Arc<StalePolicy>) -> Self {
+        self.stale_policy = Some(stale_policy);
+        self
+    }
+
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        let metrics = StageMetrics::for_kind(self.backend.kind());
+        while let Some(block) = self.block_receiver.recv().await {
+            let block_number = block.block_number();
+            // The preparation stage has already reported the failure.
+            if block.preparation_error().is_some() {
+                self.report_failed(block_number).await?;
+                continue;
+            }
+            if block.is_dropped()
+                || self
+                    .stale_policy
+                    .as_ref()
+                    .is_some_and(|policy| policy.is_stale("prove", &block.input))
+            {
+                self.report_dropped(block_number).await?;
+                continue;
+            }
+            observability::bind_block(self.backend.name(), block_number, async {
+                let result = self.process_block(block, &metrics).await;
+
+                if let Err(ref err) = result {
+                    observability::capture_anyhow(err);
+                }
+
+                result
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn process_block(
+        &mut self,
+        block: PreparedBlock,
+        metrics: &StageMetrics,
+    ) -> anyhow::Result<()> {
+        let block_number = block.block_number();
+        let block_hash = block.input.block_header.hash_slow();
+        let kind = self.backend.kind();
+        let artifact = kind.artifact();
+        match &self.group {
+            Some(group) => tracing::info!(
+                "Generating {artifact} for block {block_number} using the {} backend on device group {group}",
+                self.backend.name()
+            ),
+            None => tracing::info!(
+                "Generating {artifact} for block {block_number} using the {} backend",
+                self.backend.name()
+            ),
+        }
+        let _inflight = InflightGuard::new(metrics.inflight);
+        let _group_busy = self
+            .group
+            .as_ref()
+            .map(|group| InflightGuard::new(&METRICS.device_group_busy[group]));
+        let latency = metrics.duration.start();
+        let group_latency = self
+            .group
+            .as_ref()
+            .map(|group| METRICS.device_group_duration[group].start());
+        if kind == BackendKind::Proof {
+            send_update(
+                &self.command_sender,
+                CalculationUpdate::ProofQueued { block_number },
+                || format!("failed to mark block {block_number} as queued in the pipeline"),
+            )
+            .await?;
+            send_update(
+                &self.command_sender,
+                CalculationUpdate::ProofProving { block_number },
+                || format!("failed to mark block {block_number} as proving in the pipeline"),
+            )
+            .await?;
+        }
+
+        let started_at = Instant::now();
+        let result = self.backend.process(block).await;
+        if let (Some(stale_policy), Ok(_)) = (&self.stale_policy, &result) {
+            stale_policy.record_proving_time(started_at.elapsed());
+        }
+        if let Some(group) = &self.group {
+            let total = if result.is_ok() {
+                &METRICS.device_group_success_total
+            } else {
+                &METRICS.device_group_failure_total
+            };
+            total[group].inc();
+        }
+        if let Some(group_latency) = group_latency {
+            group_latency.observe();
+        }
+        match result {
+            Ok(output) => {
+                metrics.success_total.inc();
+                latency.observe();
+                let update = match output {
+                    BackendOutput::Witness(witness_result) => {
+                        tracing::info!(
+                            "Generated witness for block {}. Number of cycles: {}, gas used: {}, transactions: {}",
+                            block_number,
+                            witness_result.cycles,
+                            witness_result.gas_used,
+                            witness_result.tx_count
+                        );
+                        METRICS.witness_cycles.observe(witness_result.cycles);
+                        if let Some(cycles_per_gas) = witness_result.cycles_per_gas() {
+                            METRICS.witness_cycles_per_gas.observe(cycles_per_gas);
+                        }
+                        CalculationUpdate::WitnessCalculated {
+                            block_number,
+                            data: witness_result.witness,
+                        }
+                    }
+                    BackendOutput::Proof(proof_result) => {
+                        tracing::info!(
+                            "Generated proof for block {}. Number of cycles: {}, proving time: {}s",
+                            block_number,
+                            proof_result.cycles,
+                            proof_result.proving_time_secs
+                        );
+                        CalculationUpdate::ProofProvided {
+                            block_number,
+                            block_hash,
+                            proof_result,
+                        }
+                    }
+                };
+                send_update(&self.command_sender, update, || {
+                    format!("failed to forward {artifact} result for block {block_number}")
+                })
+                .await?;
+            }
+            Err(err) => {
+                metrics.failure_total.inc();
+                latency.observe();
+                match self.on_failure {
+                    OnFailure::Exit => {
+                        return Err(err).with_context(|| {
+                            format!("Failed to generate {artifact} for the block {block_number}")
+                        });
+                    }
+                    OnFailure::Continue => {
+                        observability::capture_anyhow(&err);
+                        tracing::error!(
+                            "Failed to generate {artifact} for the block {block_number}: {err}"
+                        );
+                        self.report_failed(block_number).await?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn report_failed(&self, block_number: u64) -> anyhow::Result<()> {
+        send_update(
+            &self.command_sender,
+            CalculationUpdate::BlockFailed { block_number },
+            || format!("failed to report the failure of block {block_number} to the pipeline"),
+        )
+        .await
+    }
+
+    async fn report_dropped(&self, block_number: u64) -> anyhow::Result<()> {
+        send_update(
+            &self.command_sender,
+            CalculationUpdate::BlockDropped { block_number },
+            || format!("failed to report that block {block_number} was dropped to the pipeline"),
+        )
+        .await
+    }
+}
+
+async fn send_update(
+    sender: &Sender<CalculationUpdate>,
+    update: CalculationUpdate,
+    context: impl FnOnce() -> String,
+) -> anyhow::Result<()> {
+    sender.send(update).await.with_context(context)
+}
+
+/// Metrics reported for a pipeline stage, depending on the kind of the backend.
+struct StageMetrics {
+    success_total: &'static Counter<u64>,
+    failure_total: &'static Counter<u64>,
+    duration: &'static Histogram<Duration>,
+    inflight: &'static Gauge<u64>,
+}
+
+impl StageMetrics {
+    fn for_kind(kind: BackendKind) -> Self {
+        match kind {
+            BackendKind::Witness => Self {
+                success_total: &METRICS.witness_success_total,
+                failure_total: &METRICS.witness_failure_total,
+                duration: &METRICS.witness_duration,
+                inflight: &METRICS.inflight_witness_tasks,
+            },
+            BackendKind::Proof => Self {
+                success_total: &METRICS.proof_success_total,
+                failure_total: &METRICS.proof_failure_total,
+                duration: &METRICS.proof_duration,
+                inflight: &METRICS.inflight_proof_tasks,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use tokio::sync::mpsc::channel;
+
+    use super::ProvingTask;
+    use crate::prover::{
+        backend::{BackendKind, BackendOutput, PreparedBlock, ProvingBackend},
+        cpu_witness::WitnessResult,
+        gpu_prover::ProofResult,
+        types::EthBlockInput,
+    };
+    use crate::tasks::CalculationUpdate;
+    use crate::types::OnFailure;
+
+    #[derive(Debug)]
+    struct StubBackend {
+        kind: BackendKind,
+        failing_block: Option<u64>,
+    }
+
+    #[async_trait]
+    impl ProvingBackend for StubBackend {
+        fn name(&self) -> &'static str {
+            "stub"
+        }
+
+        fn kind(&self) -> BackendKind {
+            self.kind
+        }
+
+        async fn process(&mut self, block: PreparedBlock) -> anyhow::Result<BackendOutput> {
+            let block_number = block.block_number();
+            anyhow::ensure!(
+                self.failing_block != Some(block_number),
+                "stub failure for block {block_number}"
+            );
+            Ok(match self.kind {
+                BackendKind::Witness => BackendOutput::Witness(WitnessResult {
+                    witness: vec![block_number as u32],
+                    cycles: block_number,
+                    gas_used: 21_000,
+                    tx_count: 1,
+                }),
+                BackendKind::Proof => BackendOutput::Proof(ProofResult {
+                    proof_bytes: vec![1, 2, 3],
+                    cycles: block_number,
+                    proving_time_secs: 1.0,
+                }),
+            })
+        }
+    }
+
+    fn prepared_block(number: u64) -> PreparedBlock {
+        let header = alloy::consensus::Header {
+            number,
+            ..Default::default()
+        };
+        PreparedBlock::new(EthBlockInput::with_header(header, Default::default()))
+    }
+
+    #[tokio::test]
+    async fn proof_backend_emits_status_updates_in_order() {
+        let (block_sender, block_receiver) = channel(1);
+        let backend = StubBackend {
+            kind: BackendKind::Proof,
+            failing_block: None,
+        };
+        let (task, mut updates) = ProvingTask::new(backend, block_receiver, OnFailure::Exit);
+        let handle = tokio::spawn(task.run());
+
+        block_sender
+            .send(prepared_block(7))
+            .await
+            .expect("send block");
+        drop(block_sender);
+
+        assert!(matches!(
+            updates.recv().await,
+            Some(CalculationUpdate::ProofQueued { block_number: 7 })
+        ));
+        assert!(matches!(
+            updates.recv().await,
+            Some(CalculationUpdate::ProofProving { block_number: 7 })
+        ));
+        assert!(matches!(
+            updates.recv().await,
+            Some(CalculationUpdate::ProofProvided {
+                block_number: 7,
+                ..
+            })
+        ));
+        assert!(updates.recv().await.is_none());
+        handle.await.expect("task").expect("task ok");
+    }
+
+    #[tokio::test]
+    async fn failures_are_reported_in_continue_mode() {
+        let (block_sender, block_receiver) = channel(4);
+        let backend = StubBackend {
+            kind: BackendKind::Witness,
+            failing_block: Some(1),
+        };
+        let (task, mut updates) = ProvingTask::new(backend, block_receiver, OnFailure::Continue);
+        let handle = tokio::spawn(task.run());
+
+        block_sender
+            .send(prepared_block(1))
+            .await
+            .expect("send block");
+        block_sender
+            .send(prepared_block(2))
+            .await
+            .expect("send block");
+        block_sender
+            .send(PreparedBlock::failed(
+                prepared_block(3).input,
+                anyhow::anyhow!("stub preparation failure"),
+            ))
+            .await
+            .expect("send block");
+        block_sender
+            .send(PreparedBlock::dropped(prepared_block(4).input))
+            .await
+            .expect("send block");
+        drop(block_sender);
+
+        assert!(matches!(
+            updates.recv().await,
+            Some(CalculationUpdate::BlockFailed { block_number: 1 })
+        ));
+        assert!(matches!(
+            updates.recv().await,
+            Some(CalculationUpdate::WitnessCalculated {
+                block_number: 2,
+                ..
+            })
+        ));
+        assert!(matches!(
+            updates.recv().await,
+            Some(CalculationUpdate::BlockFailed { block_number: 3 })
+        ));
+        assert!(matches!(
+            updates.recv().await,
+            Some(CalculationUpdate::BlockDropped { block_number: 4 })
+        ));
+        assert!(updates.recv().await.is_none());
+        handle.await.expect("task").expect("task ok");
+    }
+
+    #[tokio::test]
+    async fn failures_stop_the_task_in_exit_mode() {
+        let (block_sender, block_receiver) = channel(1);
+        let backend = StubBackend {
+            kind: BackendKind::Witness,
+            failing_block: Some(3),
+        };
+        let (task, _updates) = ProvingTask::new(backend, block_receiver, OnFailure::Exit);
+        let handle = tokio::spawn(task.run());
+
+        block_sender
+            .send(prepared_block(3))
+            .await
+            .expect("send block");
+
+        let err = handle.await.expect("task").expect_err("task should fail");
+        assert!(err.to_string().contains("block 3"));
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeSet;
+
+use anyhow::Context as _;
+use tokio::sync::{
+    mpsc::{Receiver, Sender, channel},
+    oneshot,
+};
+
+use crate::observability;
+use crate::tasks::CalculationUpdate;
+
+/// Outcome of processing a range of blocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RangeSummary {
+    pub succeeded: Vec<u64>,
+    pub failed: Vec<u64>,
+}
+
+/// Tracks which blocks of a range made it through the pipeline and reports a summary
+/// once the range is exhausted.
+///
+/// A block is considered failed if it never produced a witness or a proof, regardless of
+/// the stage it failed at (loading, proving or verification).
+#[derive(Debug)]
+pub(crate) struct RangeSummaryTask {
+    command_mode_receiver: Receiver<CalculationUpdate>,
+    command_mode_sender: Sender<CalculationUpdate>,
+    blocks: Vec<u64>,
+    summary_sender: oneshot::Sender<RangeSummary>,
+}
+
+impl RangeSummaryTask {
+    pub fn new(
+        receiver: Receiver<CalculationUpdate>,
+        blocks: Vec<u64>,
+    ) -> (
+        Self,
+        Receiver<CalculationUpdate>,
+        oneshot::Receiver<RangeSummary>,
+    ) {
+        let (command_mode_sender, command_mode_receiver) = channel(10);
+        let (summary_sender, summary_receiver) = oneshot::channel();
+        (
+            Self {
+                command_mode_receiver: receiver,
+                command_mode_sender,
+                blocks,
+                summary_sender,
+            },
+            command_mode_receiver,
+            summary_receiver,
+        )
+    }
+
+    pub async fn run(self) -> anyhow::Result<()> {
+        let result = self.run_inner().await;
+        if let Err(ref err) = result {
+            observability::capture_anyhow(err);
+        }
+        result
+    }
+
+    async fn run_inner(mut self) -> anyhow::Result<()> {
+        let mut completed = BTreeSet::new();
+        while let Some(command) = self.command_mode_receiver.recv().await {
+            match &command {
+                CalculationUpdate::WitnessCalculated { block_number, .. }
+                | CalculationUpdate::ProofProvided { block_number, .. } => {
+                    completed.insert(*block_number);
+                }
+                _ => {}
+            }
+            self.command_mode_sender
+                .send(command)
+                .await
+                .context("failed to forward range summary command")?;
+        }
+
+        let summary = summarize(&self.blocks, &completed);
+        tracing::info!(
+            "Range processed: {} of {} blocks succeeded",
+            summary.succeeded.len(),
+            self.blocks.len()
+        );
+        if !summary.failed.is_empty() {
+            tracing::error!(
+                "{} blocks failed: {:?}",
+                summary.failed.len(),
+                summary.failed
+            );
+        }
+        // The receiver is only gone if the runner is shutting down anyway.
+        let _ = self.summary_sender.send(summary);
+        Ok(())
+    }
+}
+
+fn summarize(blocks: &[u64], completed: &BTreeSet<u64>) -> RangeSummary {
+    let (succeeded, failed) = blocks
+        .iter()
+        .partition(|block_number| completed.contains(block_number));
+    RangeSummary { succeeded, failed }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::channel;
+
+    use super::{RangeSummary, RangeSummaryTask};
+    use crate::tasks::CalculationUpdate;
+
+    #[tokio::test]
+    async fn blocks_without_results_are_reported_as_failed() {
+        let (sender, receiver) = channel(10);
+        let (task, mut updates, summary) = RangeSummaryTask::new(receiver, vec![1, 2, 3]);
+        let handle = tokio::spawn(task.run());
+
+        for block_number in [1, 3] {
+            sender
+                .send(CalculationUpdate::WitnessCalculated {
+                    block_number,
+                    data: Vec::new(),
+                })
+                .await
+                .expect("send update");
+        }
+        sender
+            .send(CalculationUpdate::ProofQueued { block_number: 2 })
+            .await
+            .expect("send update");
+        drop(sender);
+
+        let mut forwarded = 0;
+        while updates.recv().await.is_some() {
+            forwarded += 1;
+        }
+        handle.await.expect("join task").expect("task succeeds");
+
+        assert_eq!(forwarded, 3);
+        assert_eq!(
+            summary.await.expect("summary"),
+            RangeSummary {
+                succeeded: vec![1, 3],
+                failed: vec![2],
+            }
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use alloy::primitives::B256;
+use alloy::providers::Provider;
+use anyhow::Context as _;
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+
+use crate::clients::rpc::{RpcPool, retry_rpc_call};
+use crate::metrics::METRICS;
+use crate::observability;
+use crate::tasks::CalculationUpdate;
+use crate::tasks::block_stream::fetch_canonical_hash;
+use crate::types::RpcRole;
+
+/// Interval between checks of the proven blocks (roughly one slot).
+const CHECK_INTERVAL: Duration = Duration::from_secs(12);
+/// Blocks deeper than this below the head are considered final and are no longer checked.
+const REORG_TRACKING_DEPTH: u64 = 64;
+
+/// Watches proven blocks and emits [`CalculationUpdate::BlockReorged`] once a proven
+/// block is no longer part of the canonical chain.
+#[derive(Debug)]
+pub(crate) struct ReorgMonitorTask {
+    command_mode_receiver: Receiver<CalculationUpdate>,
+    command_mode_sender: Sender<CalculationUpdate>,
+    rpc: RpcPool,
+    /// Proven blocks that are not final yet, with the hashes they were proven for.
+    proven_blocks: BTreeMap<u64, B256>,
+}
+
+impl ReorgMonitorTask {
+    pub fn new(
+        receiver: Receiver<CalculationUpdate>,
+        rpc: RpcPool,
+    ) -> (Self, Receiver<CalculationUpdate>) {
+        let (command_mode_sender, command_mode_receiver) = channel(10);
+        (
+            Self {
+                command_mode_receiver: receiver,
+                command_mode_sender,
+                rpc,
+                proven_blocks: BTreeMap::new(),
+            },
+            command_mode_receiver,
+        )
+    }
+
+    pub async fn run(self) -> anyhow::Result<()> {
+        let result = self.run_inner().await;
+        if let Err(ref err) = result {
+            observability::capture_anyhow(err);
+        }
+        result
+    }
+
+    async fn run_inner(mut self) -> anyhow::Result<()> {
+        let mut check_interval = tokio::time::interval(CHECK_INTERVAL);
+        check_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                command = self.command_mode_receiver.recv() => {
+                    let Some(command) = command else {
+                        break;
+                    };
+                    if let CalculationUpdate::ProofProvided { block_number, block_hash, .. } = &command {
+                        self.proven_blocks.insert(*block_number, *block_hash);
+                    }
+                    self.forward(command).await?;
+                }
+                _ = check_interval.tick(), if !self.proven_blocks.is_empty() => {
+                    // RPC failures are not fatal: the blocks are simply checked again on the next tick.
+                    if let Err(err) = self.check_proven_blocks().await {
+                        tracing::warn!("Failed to check proven blocks for reorgs: {err:#}");
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn check_proven_blocks(&mut self) -> anyhow::Result<()> {
+        let head = retry_rpc_call(
+            &self.rpc,
+            RpcRole::Head,
+            "fetch latest L1 head",
+            |endpoint| async move {
+                endpoint
+                    .provider()
+                    .get_block_number()
+                    .await
+                    .map_err(anyhow::Error::from)
+            },
+        )
+        .await?;
+        let mut canonical_hashes = BTreeMap::new();
+        // Blocks above the head (e.g. after the chain got shorter in a reorg) can't be checked
+        // yet; they are checked again once the chain grows past them.
+        for &block_number in self.proven_blocks.range(..=head).map(|(number, _)| number) {
+            let operation = format!("fetch canonical hash of block {block_number}");
+            let result =
+                retry_rpc_call(
+                    &self.rpc,
+                    RpcRole::Head,
+                    &operation,
+                    |endpoint| async move {
+                        fetch_canonical_hash(endpoint.provider(), block_number).await
+                    },
+                )
+                .await;
+            // A single block that can't be fetched must not prevent checking the other ones.
+            match result {
+                Ok(hash) => {
+                    canonical_hashes.insert(block_number, hash);
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "Failed to check proven block {block_number} for reorgs, checking it again later: {err:#}"
+                    );
+                }
+            }
+        }
+
+        for update in apply_canonical_hashes(&mut self.proven_blocks, &canonical_hashes, head) {
+            if let CalculationUpdate::BlockReorged {
+                block_number,
+                orphaned_hash,
+                canonical_hash,
+            } = &update
+            {
+                tracing::warn!(
+                    "Proven block {block_number} ({orphaned_hash}) was reorged out, canonical block is {canonical_hash}"
+                );
+                METRICS.reorged_proofs_total.inc();
+            }
+            self.forward(update).await?;
+        }
+        Ok(())
+    }
+
+    async fn forward(&self, command: CalculationUpdate) -> anyhow::Result<()> {
+        self.command_mode_sender
+            .send(command)
+            .await
+            .context("failed to forward reorg monitor command")
+    }
+}
+
+/// Compares proven blocks against the canonical chain, returning updates for the reorged ones.
+/// Reorged blocks and blocks deep enough to be considered final are no longer tracked.
+fn apply_canonical_hashes(
+    proven_blocks: &mut BTreeMap<u64, B256>,
+    canonical_hashes: &BTreeMap<u64, B256>,
+    head: u64,
+) -> Vec<CalculationUpdate> {
+    let mut updates = Vec::new();
+    proven_blocks.retain(|&block_number, &mut proven_hash| {
+        if let Some(&canonical_hash) = canonical_hashes.get(&block_number)
+            && canonical_hash != proven_hash
+        {
+            updates.push(CalculationUpdate::BlockReorged {
+                block_number,
+                orphaned_hash: proven_hash,
+                canonical_hash,
+            });
+            return false;
+        }
+        block_number + REORG_TRACKING_DEPTH > head
+    });
+    updates
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use alloy::primitives::B256;
+
+    use super::{REORG_TRACKING_DEPTH, apply_canonical_hashes};
+    use crate::tasks::CalculationUpdate;
+
+    #[test]
+    fn reorged_and_final_blocks_stop_being_tracked() {
+        let head = 1_000;
+        let final_block = head - REORG_TRACKING_DEPTH;
+        let mut proven_blocks = BTreeMap::from([
+            (final_block, B256::repeat_byte(1)),
+            (990, B256::repeat_byte(2)),
+            (995, B256::repeat_byte(3)),
+        ]);
+        let canonical_hashes = BTreeMap::from([
+            (final_block, B256::repeat_byte(1)),
+            (990, B256::repeat_byte(2)),
+            (995, B256::repeat_byte(4)),
+        ]);
+
+        let updates = apply_canonical_hashes(&mut proven_blocks, &canonical_hashes, head);
+
+        assert_eq!(updates.len(), 1);
+        assert!(matches!(
+            updates[0],
+            CalculationUpdate::BlockReorged {
+                block_number: 995,
+                orphaned_hash,
+                canonical_hash,
+            } if orphaned_hash == B256::repeat_byte(3) && canonical_hash == B256::repeat_byte(4)
+        ));
+        assert_eq!(proven_blocks.keys().copied().collect::<Vec<_>>(), vec![990]);
+    }
+}
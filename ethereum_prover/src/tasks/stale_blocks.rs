@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::metrics::METRICS;
+use crate::prover::types::EthBlockInput;
+
+/// Weight of the latest proving time in the expected proving time.
+const PROVING_TIME_WEIGHT: f64 = 0.25;
+
+/// Drops blocks that can no longer be proven within the latency target.
+///
+/// A block is stale if, with proving starting now and taking as long as recent proofs did, its
+/// proof would be ready more than `latency_target` after the block timestamp. Dropping such
+/// blocks lets the pipeline get back to fresh blocks instead of falling further behind.
+/// The policy is shared between the stages, so that the proving times recorded by the proving
+/// stage are also taken into account when preparing blocks.
+#[derive(Debug)]
+pub(crate) struct StalePolicy {
+    latency_target: Duration,
+    /// Moving average of the recent proving times, in milliseconds; `0` until the first proof.
+    expected_proving_ms: AtomicU64,
+}
+
+impl StalePolicy {
+    pub fn new(latency_target: Duration) -> Self {
+        Self {
+            latency_target,
+            expected_proving_ms: AtomicU64::new(0),
+        }
+    }
+
+    pub fn expected_proving_time(&self) -> Duration {
+        Duration::from_millis(self.expected_proving_ms.load(Ordering::Relaxed))
+    }
+
+    pub fn record_proving_time(&self, proving_time: Duration) {
+        let sample = u64::try_from(proving_time.as_millis())
+            .unwrap_or(u64::MAX)
+            .max(1);
+        // The closure always returns `Some`, so the update can't fail.
+        let _ = self.expected_proving_ms.fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |expected| {
+                if expected == 0 {
+                    return Some(sample);
+                }
+                let updated = expected as f64 * (1.0 - PROVING_TIME_WEIGHT)
+                    + sample as f64 * PROVING_TIME_WEIGHT;
+                Some(updated as u64)
+            },
+        );
+    }
+
+    /// Returns by how much the proof of a block with the given timestamp would miss the latency
+    /// target if proving started at `now`, or `None` if it can still be ready in time.
+    pub fn lateness(&self, block_timestamp: u64, now: SystemTime) -> Option<Duration> {
+        let deadline = UNIX_EPOCH + Duration::from_secs(block_timestamp) + self.latency_target;
+        let ready_at = now + self.expected_proving_time();
+        ready_at
+            .duration_since(deadline)
+            .ok()
+            .filter(|lateness| !lateness.is_zero())
+    }
+
+    /// Checks whether the block should be dropped by the given stage, reporting it if so.
+    pub fn is_stale(&self, stage: &'static str, input: &EthBlockInput) -> bool {
+        let block_number = input.block_header.number;
+        let Some(lateness) = self.lateness(input.block_header.timestamp, SystemTime::now()) else {
+            return false;
+        };
+        tracing::warn!(
+            "Dropping block {block_number} in the {stage} stage: its proof would miss the latency target of {:?} by {lateness:?}",
+            self.latency_target
+        );
+        METRICS.stale_blocks_total[&stage].inc();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::StalePolicy;
+
+    #[test]
+    fn blocks_are_stale_once_they_cannot_meet_the_target() {
+        let policy = StalePolicy::new(Duration::from_secs(60));
+        let block_timestamp = 1_700_000_000;
+        let now = UNIX_EPOCH + Duration::from_secs(block_timestamp + 50);
+        // Nothing is known about the proving time yet, so only the age of the block counts.
+        assert_eq!(policy.lateness(block_timestamp, now), None);
+
+        policy.record_proving_time(Duration::from_secs(8));
+        assert_eq!(policy.lateness(block_timestamp, now), None);
+        policy.record_proving_time(Duration::from_secs(16));
+        assert_eq!(policy.expected_proving_time(), Duration::from_secs(10));
+        assert_eq!(policy.lateness(block_timestamp, now), None);
+        policy.record_proving_time(Duration::from_secs(30));
+        assert_eq!(policy.expected_proving_time(), Duration::from_secs(15));
+        assert_eq!(
+            policy.lateness(block_timestamp, now),
+            Some(Duration::from_secs(5))
+        );
+    }
+}
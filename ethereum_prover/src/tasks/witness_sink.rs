@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use alloy::primitives::B256;
+use anyhow::Context as _;
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+
+use crate::cache::CacheStorage;
+use crate::observability;
+use crate::tasks::CalculationUpdate;
+use crate::witness_file::WitnessFile;
+
+/// Persists every generated CPU witness next to the cached block, so that it can be proven
+/// elsewhere or compared against witnesses produced by other `app.bin` versions.
+#[derive(Debug)]
+pub(crate) struct WitnessSinkTask {
+    command_mode_receiver: Receiver<CalculationUpdate>,
+    command_mode_sender: Sender<CalculationUpdate>,
+    cache_storage: CacheStorage,
+    app_bin_hash: B256,
+}
+
+impl WitnessSinkTask {
+    pub fn new(
+        receiver: Receiver<CalculationUpdate>,
+        cache_storage: CacheStorage,
+        app_bin_hash: B256,
+    ) -> (Self, Receiver<CalculationUpdate>) {
+        let (command_mode_sender, command_mode_receiver) = channel(10);
+        (
+            Self {
+                command_mode_receiver: receiver,
+                command_mode_sender,
+                cache_storage,
+                app_bin_hash,
+            },
+            command_mode_receiver,
+        )
+    }
+
+    pub async fn run(self) -> anyhow::Result<()> {
+        let result = self.run_inner().await;
+        if let Err(ref err) = result {
+            observability::capture_anyhow(err);
+        }
+        result
+    }
+
+    async fn run_inner(mut self) -> anyhow::Result<()> {
+        while let Some(command) = self.command_mode_receiver.recv().await {
+            let command = match command {
+                CalculationUpdate::WitnessCalculated { block_number, data } => {
+                    let witness = WitnessFile {
+                        block_number,
+                        app_bin_hash: self.app_bin_hash,
+                        words: data,
+                    };
+                    let (data, result) = self.store(witness).await;
+                    // Failing to persist a witness should not stop the pipeline.
+                    match result {
+                        Ok(path) => tracing::info!(
+                            "Stored CPU witness for block {block_number} at {}",
+                            path.display()
+                        ),
+                        Err(err) => {
+                            observability::capture_anyhow(&err);
+                            tracing::error!("{err:#}");
+                        }
+                    }
+                    CalculationUpdate::WitnessCalculated { block_number, data }
+                }
+                command => command,
+            };
+            self.command_mode_sender
+                .send(command)
+                .await
+                .context("failed to forward witness sink command")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the witness on a blocking thread, handing the words back for forwarding.
+    /// The words are shared with the writing thread, so they survive a panic while writing.
+    async fn store(&self, witness: WitnessFile) -> (Vec<u32>, anyhow::Result<PathBuf>) {
+        let block_number = witness.block_number;
+        let cache_storage = self.cache_storage.clone();
+        let witness = Arc::new(witness);
+        let result = observability::spawn_blocking_on_current_hub({
+            let witness = witness.clone();
+            move || cache_storage.save_cpu_witness(&witness)
+        })
+        .await
+        .map_err(|err| {
+            anyhow::anyhow!(
+                "witness sink panicked: {}",
+                crate::utils::extract_panic_message(err)
+            )
+        })
+        .and_then(|result| result)
+        .with_context(|| format!("failed to store CPU witness for block {block_number}"));
+        let words = Arc::try_unwrap(witness)
+            .map_or_else(|witness| witness.words.clone(), |witness| witness.words);
+        (words, result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WitnessSinkTask;
+    use crate::cache::CacheStorage;
+    use crate::tasks::CalculationUpdate;
+    use alloy::primitives::B256;
+    use tokio::sync::mpsc::channel;
+
+    #[tokio::test]
+    async fn stores_witness_and_forwards_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CacheStorage::new(dir.path()).unwrap();
+        let app_bin_hash = B256::repeat_byte(2);
+        let (sender, receiver) = channel(1);
+        let (task, mut output) = WitnessSinkTask::new(receiver, cache.clone(), app_bin_hash);
+        let handle = tokio::spawn(task.run());
+
+        sender
+            .send(CalculationUpdate::WitnessCalculated {
+                block_number: 5,
+                data: vec![4, 5, 6],
+            })
+            .await
+            .unwrap();
+        drop(sender);
+
+        let Some(CalculationUpdate::WitnessCalculated { block_number, data }) = output.recv().await
+        else {
+            panic!("witness update was not forwarded");
+        };
+        assert_eq!((block_number, data), (5, vec![4, 5, 6]));
+        handle.await.unwrap().unwrap();
+
+        let stored = cache.load_cpu_witness(5).unwrap().expect("witness stored");
+        assert_eq!(stored.app_bin_hash, app_bin_hash);
+        assert_eq!(stored.words, vec![4, 5, 6]);
+    }
+}
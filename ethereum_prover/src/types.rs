@@ -1,10 +1,13 @@
 use serde::{Deserialize, Serialize};
+use smart_config::value::SecretString;
 
 #[derive(Debug, Deserialize, Serialize, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum Mode {
     CpuWitness,
     GpuProve,
+    CpuProve,
+    MockProve,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Copy)]
@@ -15,6 +18,76 @@ pub enum CachePolicy {
     Always,
 }
 
+/// How the continuous block stream picks the next block to process.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockSelection {
+    /// Always jump to the newest matching block, skipping the ones missed in between.
+    Head,
+    /// Process every matching block after the last processed one, unless the lag gets too big.
+    CatchUp,
+}
+
+/// Where execution witnesses come from.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WitnessSource {
+    /// `debug_executionWitness` of the RPC.
+    Rpc,
+    /// Built locally from the prestate tracer, `eth_getProof` and `eth_getCode`.
+    Local,
+    /// `debug_executionWitness`, falling back to building the witness locally if it fails.
+    Auto,
+}
+
+/// How incomplete execution witnesses are handled before proving.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WitnessValidation {
+    /// Witnesses are not validated.
+    Off,
+    /// Problems are logged and reported, and the block is proven anyway.
+    Warn,
+    /// Blocks with incomplete witnesses are treated as failed.
+    Enforce,
+}
+
+/// Where generated CPU witnesses are persisted.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WitnessSink {
+    /// Witnesses are dropped once generated.
+    Off,
+    /// Witnesses are stored as `cpu_witness.bin` in the block cache directory.
+    Cache,
+}
+
+/// What an RPC endpoint is used for.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcRole {
+    /// Tracking the chain head: latest block numbers, `newHeads` subscriptions and reorg checks.
+    Head,
+    /// Fetching blocks and their execution witnesses.
+    Witness,
+}
+
+/// Additional RPC endpoint.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RpcEndpointConfig {
+    /// May contain credentials, so it's neither printed nor serialized.
+    #[serde(with = "redacted")]
+    pub url: SecretString,
+    #[serde(default = "RpcEndpointConfig::all_roles")]
+    pub roles: Vec<RpcRole>,
+}
+
+impl RpcEndpointConfig {
+    pub fn all_roles() -> Vec<RpcRole> {
+        vec![RpcRole::Head, RpcRole::Witness]
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum EthProofsSubmission {
@@ -42,3 +115,45 @@ pub enum OnFailure {
     Exit,
     Continue,
 }
+
+/// Limits of a pipeline stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageLimits {
+    /// Number of blocks the stage processes at the same time.
+    pub concurrency: usize,
+    /// Number of processed blocks that may wait for the next stage.
+    pub queue_size: usize,
+}
+
+impl StageLimits {
+    /// One block at a time, with at most one block waiting for the next stage.
+    pub const SEQUENTIAL: Self = Self {
+        concurrency: 1,
+        queue_size: 1,
+    };
+
+    pub fn new(concurrency: usize, queue_size: usize) -> anyhow::Result<Self> {
+        anyhow::ensure!(concurrency > 0, "stage concurrency must be greater than 0");
+        anyhow::ensure!(queue_size > 0, "stage queue size must be greater than 0");
+        Ok(Self {
+            concurrency,
+            queue_size,
+        })
+    }
+}
+
+/// Serde adapter for secrets in config lists, which are only deserialized.
+mod redacted {
+    use serde::{Deserialize as _, Deserializer, Serializer};
+    use smart_config::value::SecretString;
+
+    pub fn serialize<S: Serializer>(_: &SecretString, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("[REDACTED]")
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SecretString, D::Error> {
+        String::deserialize(deserializer).map(SecretString::from)
+    }
+}
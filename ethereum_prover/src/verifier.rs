@@ -0,0 +1,140 @@
+//! Native verification of the proofs produced by the prover.
+
+use std::io::Read as _;
+use std::path::Path;
+
+use alloy::primitives::B256;
+use anyhow::Context as _;
+use base64::Engine as _;
+use proof_verifier::{PublicOutput, UnrolledProgramProof, VerifierContext};
+
+/// File name of the recursion layer setup within the artifacts directory.
+pub const SETUP_FILE_NAME: &str = "recursion_unified_setup.bin";
+/// File name of the recursion layer circuit layouts within the artifacts directory.
+pub const LAYOUTS_FILE_NAME: &str = "recursion_unified_layouts.bin";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+pub struct ProofVerifier {
+    context: VerifierContext,
+}
+
+impl std::fmt::Debug for ProofVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProofVerifier").finish()
+    }
+}
+
+impl ProofVerifier {
+    pub fn load(setup_path: &Path, layouts_path: &Path) -> anyhow::Result<Self> {
+        let setup_bin = std::fs::read(setup_path)
+            .with_context(|| format!("failed to read verifier setup {}", setup_path.display()))?;
+        let layouts_bin = std::fs::read(layouts_path).with_context(|| {
+            format!("failed to read verifier layouts {}", layouts_path.display())
+        })?;
+        let context = VerifierContext::parse(&setup_bin, &layouts_bin)
+            .map_err(|err| anyhow::anyhow!(err))
+            .context("failed to parse verifier artifacts")?;
+        Ok(Self { context })
+    }
+
+    /// Verifies the proof, returning the public values it commits to.
+    /// If `expected_block_hash` is provided, the proof must have been produced for that block.
+    pub fn verify(
+        &self,
+        proof: &UnrolledProgramProof,
+        expected_block_hash: Option<B256>,
+    ) -> anyhow::Result<PublicOutput> {
+        self.context
+            .verify_for_block(proof, expected_block_hash.as_ref().map(|hash| &hash.0))
+            .map_err(|err| anyhow::anyhow!(err))
+    }
+
+    /// Verifies a raw bincode-encoded proof, as produced by the GPU and CPU provers.
+    pub fn verify_bytes(
+        &self,
+        proof_bytes: &[u8],
+        expected_block_hash: Option<B256>,
+    ) -> anyhow::Result<PublicOutput> {
+        let proof =
+            proof_verifier::decode_proof(proof_bytes).map_err(|err| anyhow::anyhow!(err))?;
+        self.verify(&proof, expected_block_hash)
+    }
+}
+
+/// Decodes a proof, accepting any of the formats the prover produces:
+/// raw bincode (as stored in the proof store), gzip-compressed bincode,
+/// or base64-encoded gzip (as submitted to EthProofs).
+pub fn decode_proof_file(contents: &[u8]) -> anyhow::Result<UnrolledProgramProof> {
+    let proof_bytes = unwrap_proof_encoding(contents)?;
+    proof_verifier::decode_proof(&proof_bytes).map_err(|err| anyhow::anyhow!(err))
+}
+
+/// Strips the transport encodings of the proof, returning raw bincode bytes.
+fn unwrap_proof_encoding(contents: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if contents.starts_with(&GZIP_MAGIC) {
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(contents)
+            .read_to_end(&mut decompressed)
+            .context("gzip decode failed")?;
+        return Ok(decompressed);
+    }
+
+    if let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(contents.trim_ascii())
+        && decoded.starts_with(&GZIP_MAGIC)
+    {
+        return unwrap_proof_encoding(&decoded);
+    }
+
+    Ok(contents.to_vec())
+}
+
+/// Verifies the proof stored at `proof_path`, logging the outcome.
+pub(crate) fn verify_proof_file(
+    proof_path: &Path,
+    setup_path: &Path,
+    layouts_path: &Path,
+    expected_block_hash: Option<B256>,
+) -> anyhow::Result<()> {
+    let verifier = ProofVerifier::load(setup_path, layouts_path)?;
+    let contents = std::fs::read(proof_path)
+        .with_context(|| format!("failed to read proof {}", proof_path.display()))?;
+    let proof = decode_proof_file(&contents)
+        .with_context(|| format!("failed to decode proof {}", proof_path.display()))?;
+
+    tracing::info!("Verifying proof {}", proof_path.display());
+    let output = verifier
+        .verify(&proof, expected_block_hash)
+        .with_context(|| format!("proof {} is invalid", proof_path.display()))?;
+    tracing::info!(
+        "Proof is valid. Block hash: {}, recursion chain hash: {}",
+        B256::from(output.block_hash),
+        B256::from(output.recursion_chain_hash)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unwrap_proof_encoding;
+    use crate::clients::ethproofs::proof_payload_for_tests;
+
+    #[test]
+    fn proof_encodings_are_unwrapped() {
+        let raw = b"bincode-proof-bytes".to_vec();
+        assert_eq!(unwrap_proof_encoding(&raw).expect("raw"), raw);
+
+        let base64_gzip = proof_payload_for_tests(&raw);
+        assert_eq!(
+            unwrap_proof_encoding(base64_gzip.as_bytes()).expect("base64"),
+            raw
+        );
+
+        let gzip = base64::Engine::decode(
+            &base64::engine::general_purpose::STANDARD,
+            base64_gzip.as_bytes(),
+        )
+        .expect("decode base64");
+        assert_eq!(unwrap_proof_encoding(&gzip).expect("gzip"), raw);
+    }
+}
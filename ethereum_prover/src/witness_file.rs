@@ -0,0 +1,223 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use alloy::primitives::{B256, Keccak256, keccak256};
+use anyhow::Context as _;
+
+/// Identifies a CPU witness file.
+const MAGIC: [u8; 4] = *b"EPWT";
+/// Version of the file layout; bumped on every incompatible change.
+const FORMAT_VERSION: u32 = 1;
+/// magic + version + block number + app.bin hash + word count + checksum.
+const HEADER_LEN: usize = 4 + 4 + 8 + 32 + 8 + 32;
+
+/// CPU witness of a block, as produced by [`CpuWitnessGenerator`], together with the
+/// information needed to check that it's used with the right `app.bin`.
+///
+/// On disk, the witness is a fixed-size little-endian header followed by the witness words:
+///
+/// | field          | size      |
+/// |----------------|-----------|
+/// | magic `EPWT`   | 4         |
+/// | format version | 4         |
+/// | block number   | 8         |
+/// | app.bin hash   | 32        |
+/// | word count     | 8         |
+/// | checksum       | 32        |
+/// | words          | 4 * count |
+///
+/// The checksum is the Keccak256 hash of the encoded words.
+///
+/// [`CpuWitnessGenerator`]: crate::prover::cpu_witness::CpuWitnessGenerator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WitnessFile {
+    pub block_number: u64,
+    /// Keccak256 hash of the `app.bin` the witness was generated with.
+    pub app_bin_hash: B256,
+    pub words: Vec<u32>,
+}
+
+impl WitnessFile {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.words.len() * 4);
+        self.encode_to(&mut bytes)
+            .expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    /// Streams the encoded witness to `writer`, without holding the whole encoding in memory.
+    pub fn encode_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        let mut checksum = Keccak256::new();
+        for_each_encoded_chunk(&self.words, |chunk| {
+            checksum.update(chunk);
+            Ok(())
+        })?;
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&self.block_number.to_le_bytes())?;
+        writer.write_all(self.app_bin_hash.as_slice())?;
+        writer.write_all(&(self.words.len() as u64).to_le_bytes())?;
+        writer.write_all(checksum.finalize().as_slice())?;
+        for_each_encoded_chunk(&self.words, |chunk| writer.write_all(chunk))
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            bytes.len() >= HEADER_LEN,
+            "witness file is truncated: {} bytes, header alone is {HEADER_LEN}",
+            bytes.len()
+        );
+        let (header, payload) = bytes.split_at(HEADER_LEN);
+        anyhow::ensure!(header[..4] == MAGIC, "not a witness file");
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        anyhow::ensure!(
+            version == FORMAT_VERSION,
+            "unsupported witness file version {version}, expected {FORMAT_VERSION}"
+        );
+        let block_number = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let app_bin_hash = B256::from_slice(&header[16..48]);
+        let word_count = u64::from_le_bytes(header[48..56].try_into().unwrap());
+        let checksum = B256::from_slice(&header[56..88]);
+
+        anyhow::ensure!(
+            payload.len() as u64 == word_count.saturating_mul(4),
+            "witness file declares {word_count} words, but contains {} bytes of them",
+            payload.len()
+        );
+        let actual_checksum = keccak256(payload);
+        anyhow::ensure!(
+            actual_checksum == checksum,
+            "witness checksum mismatch: expected {checksum}, got {actual_checksum}"
+        );
+        let words = payload
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        Ok(Self {
+            block_number,
+            app_bin_hash,
+            words,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        // Write to a temporary file first, so that a partially written witness is never picked up.
+        let tmp_path = path.with_extension("tmp");
+        File::create(&tmp_path)
+            .and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                self.encode_to(&mut writer)?;
+                writer
+                    .into_inner()
+                    .map_err(|err| err.into_error())?
+                    .sync_all()
+            })
+            .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("failed to move witness to {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let bytes =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        Self::decode(&bytes).with_context(|| format!("invalid witness file {}", path.display()))
+    }
+
+    /// Returns the witness words, if the witness was generated with the expected `app.bin`.
+    pub fn into_words_for(self, app_bin_hash: B256) -> anyhow::Result<Vec<u32>> {
+        anyhow::ensure!(
+            self.app_bin_hash == app_bin_hash,
+            "witness for block {} was generated with app.bin {}, expected {app_bin_hash}",
+            self.block_number,
+            self.app_bin_hash
+        );
+        Ok(self.words)
+    }
+}
+
+/// Calls `f` with the little-endian encoding of `words`, a bounded chunk at a time.
+fn for_each_encoded_chunk(
+    words: &[u32],
+    mut f: impl FnMut(&[u8]) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    const CHUNK_WORDS: usize = 16 * 1024;
+
+    let mut buffer = Vec::with_capacity(CHUNK_WORDS.min(words.len()) * 4);
+    for chunk in words.chunks(CHUNK_WORDS) {
+        buffer.clear();
+        for word in chunk {
+            buffer.extend_from_slice(&word.to_le_bytes());
+        }
+        f(&buffer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HEADER_LEN, WitnessFile};
+    use alloy::primitives::B256;
+
+    fn witness() -> WitnessFile {
+        WitnessFile {
+            block_number: 21_000_000,
+            app_bin_hash: B256::repeat_byte(0xab),
+            words: vec![0, 1, u32::MAX, 0xdead_beef],
+        }
+    }
+
+    #[test]
+    fn witness_file_roundtrips() {
+        let witness = witness();
+        let bytes = witness.encode();
+        assert_eq!(bytes.len(), HEADER_LEN + 4 * witness.words.len());
+        assert_eq!(WitnessFile::decode(&bytes).unwrap(), witness);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("witness.bin");
+        witness.write(&path).unwrap();
+        assert_eq!(WitnessFile::read(&path).unwrap(), witness);
+    }
+
+    #[test]
+    fn witness_file_roundtrips_across_chunks() {
+        let witness = WitnessFile {
+            words: (0..40_000).collect(),
+            ..witness()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("witness.bin");
+        witness.write(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), witness.encode());
+        assert_eq!(WitnessFile::read(&path).unwrap(), witness);
+    }
+
+    #[test]
+    fn witness_file_rejects_corruption() {
+        let mut bytes = witness().encode();
+        *bytes.last_mut().unwrap() ^= 1;
+        let err = WitnessFile::decode(&bytes).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"), "{err}");
+
+        let bytes = witness().encode();
+        let err = WitnessFile::decode(&bytes[..bytes.len() - 4]).unwrap_err();
+        assert!(err.to_string().contains("declares 4 words"), "{err}");
+    }
+
+    #[test]
+    fn witness_file_checks_app_bin_hash() {
+        let witness = witness();
+        assert_eq!(
+            witness
+                .clone()
+                .into_words_for(witness.app_bin_hash)
+                .unwrap(),
+            witness.words
+        );
+        assert!(witness.into_words_for(B256::ZERO).is_err());
+    }
+}
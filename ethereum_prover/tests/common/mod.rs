@@ -29,6 +29,15 @@ pub fn fixture_witness_path(fixture: &str) -> PathBuf {
         .join("execution_witness.json")
 }
 
+/// Proof of the fixture block generated by the CPU prover, written by `cpu_prover_from_fixture_block`
+/// with `UPDATE_PROOF_FIXTURE=1`. It has to be regenerated whenever the artifacts change.
+pub fn fixture_proof_path(fixture: &str) -> PathBuf {
+    fixture_root()
+        .join("blocks")
+        .join(fixture)
+        .join("proof.bin")
+}
+
 pub fn app_bin_path() -> PathBuf {
     manifest_dir().join("../artifacts/app.bin")
 }
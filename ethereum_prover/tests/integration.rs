@@ -6,9 +6,12 @@
 
 mod common;
 
+use ethereum_prover::prover::cpu_prover::CpuProver;
 use ethereum_prover::prover::cpu_witness::CpuWitnessGenerator;
 use ethereum_prover::prover::gpu_prover::Prover;
+use ethereum_prover::prover::mock_prover::{MOCK_PROOF_CYCLES, MockProver};
 use ethereum_prover::prover::oracle::build_oracle;
+use ethereum_prover::verifier::{LAYOUTS_FILE_NAME, ProofVerifier, SETUP_FILE_NAME};
 
 macro_rules! require_gpu_tests {
     () => {
@@ -19,6 +22,24 @@ macro_rules! require_gpu_tests {
     };
 }
 
+macro_rules! require_cpu_prover_tests {
+    () => {
+        if std::env::var("RUN_CPU_PROVER_TESTS").ok().as_deref() != Some("1") {
+            eprintln!("Skipping CPU prover test. Set RUN_CPU_PROVER_TESTS=1 to enable.");
+            return;
+        }
+    };
+}
+
+macro_rules! require_proof_fixture_tests {
+    () => {
+        if std::env::var("RUN_PROOF_FIXTURE_TESTS").ok().as_deref() != Some("1") {
+            eprintln!("Skipping proof fixture test. Set RUN_PROOF_FIXTURE_TESTS=1 to enable.");
+            return;
+        }
+    };
+}
+
 #[tokio::test]
 async fn cpu_witness_from_fixture_block() {
     common::init_tracing();
@@ -27,16 +48,34 @@ async fn cpu_witness_from_fixture_block() {
     let oracle = build_oracle(input.clone()).expect("build oracle");
     let generator = CpuWitnessGenerator::new(common::app_bin_path());
 
-    generator
-        .forward_run(block_number, oracle)
+    let outcome = generator
+        .forward_run(block_number, &input.transactions, oracle)
         .await
         .expect("forward run");
-    let witness = generator
+    outcome
+        .check_against(&input.block_header)
+        .expect("forward run matches the canonical header");
+    let (witness, cycles) = generator
         .generate_witness(block_number, build_oracle(input).expect("build oracle"))
         .await
         .expect("generate witness");
 
     assert!(!witness.is_empty());
+    assert!(cycles > 0);
+}
+
+#[tokio::test]
+async fn mock_prover_from_fixture_block() {
+    common::init_tracing();
+    let input = common::load_fixture_input("24073997");
+    let prover = MockProver::new(common::app_bin_path(), std::time::Duration::ZERO);
+
+    let first = prover.prove(input.clone()).await.expect("mock prove block");
+    let second = prover.prove(input).await.expect("mock prove block");
+
+    assert!(!first.proof_bytes.is_empty());
+    assert_eq!(first.proof_bytes, second.proof_bytes);
+    assert_eq!(first.cycles, MOCK_PROOF_CYCLES);
 }
 
 #[tokio::test]
@@ -57,3 +96,133 @@ async fn gpu_prover_from_fixture_block() {
     assert!(result.cycles > 0);
     assert!(result.proving_time_secs > 0.0);
 }
+
+/// Memory used on every CUDA device, in MiB, as reported by `nvidia-smi`.
+fn gpu_memory_used_mib() -> Vec<u64> {
+    let output = std::process::Command::new("nvidia-smi")
+        .args(["--query-gpu=memory.used", "--format=csv,noheader,nounits"])
+        .output()
+        .expect("run nvidia-smi");
+    assert!(output.status.success(), "nvidia-smi failed: {output:?}");
+    String::from_utf8(output.stdout)
+        .expect("nvidia-smi output is UTF-8")
+        .lines()
+        .map(|line| line.trim().parse().expect("parse used memory"))
+        .collect()
+}
+
+#[tokio::test]
+async fn gpu_device_groups_use_their_own_devices() {
+    require_gpu_tests!();
+
+    let baseline = gpu_memory_used_mib();
+    if baseline.len() < 2 {
+        eprintln!("Skipping GPU device group test: it needs at least two GPUs.");
+        return;
+    }
+
+    common::init_tracing();
+    let input = common::load_fixture_input("24073997");
+    let block_number = input.block_header.number;
+    let app_bin_path = common::app_bin_path();
+    let mut first = Prover::for_devices(app_bin_path.as_path(), None, vec![0])
+        .expect("create prover on device 0");
+    let after_first = gpu_memory_used_mib();
+    let mut second = Prover::for_devices(app_bin_path.as_path(), None, vec![1])
+        .expect("create prover on device 1");
+    let after_second = gpu_memory_used_mib();
+
+    // Each prover allocates its memory when it's created, so it must show up on its own device only.
+    assert!(
+        after_first[0] > baseline[0],
+        "{baseline:?} -> {after_first:?}"
+    );
+    assert_eq!(
+        after_first[1], baseline[1],
+        "{baseline:?} -> {after_first:?}"
+    );
+    assert!(
+        after_second[1] > after_first[1],
+        "{after_first:?} -> {after_second:?}"
+    );
+    assert_eq!(
+        after_second[0], after_first[0],
+        "{after_first:?} -> {after_second:?}"
+    );
+
+    let (first_result, second_result) = tokio::join!(
+        first.prove(
+            block_number,
+            build_oracle(input.clone()).expect("build oracle")
+        ),
+        second.prove(block_number, build_oracle(input).expect("build oracle")),
+    );
+    let first_result = first_result.expect("prove block on device 0");
+    let second_result = second_result.expect("prove block on device 1");
+    assert_eq!(first_result.cycles, second_result.cycles);
+}
+
+#[tokio::test]
+async fn cpu_prover_from_fixture_block() {
+    require_cpu_prover_tests!();
+
+    common::init_tracing();
+    let input = common::load_fixture_input("24073997");
+    let block_hash = input.block_header.hash_slow();
+    let oracle = build_oracle(input.clone()).expect("build oracle");
+    let app_bin_path = common::app_bin_path();
+    let mut prover = CpuProver::new(app_bin_path.as_path(), None).expect("create prover");
+
+    let result = prover
+        .prove(input.block_header.number, oracle)
+        .await
+        .expect("prove block");
+    assert!(result.cycles > 0);
+
+    let verifier = ProofVerifier::load(
+        &app_bin_path.with_file_name(SETUP_FILE_NAME),
+        &app_bin_path.with_file_name(LAYOUTS_FILE_NAME),
+    )
+    .expect("load verifier");
+    let output = verifier
+        .verify_bytes(&result.proof_bytes, Some(block_hash))
+        .expect("CPU proof verifies");
+    // The program output is the hash of the proven block.
+    assert_eq!(output.block_hash, block_hash.0);
+    if std::env::var("UPDATE_PROOF_FIXTURE").ok().as_deref() == Some("1") {
+        std::fs::write(common::fixture_proof_path("24073997"), &result.proof_bytes)
+            .expect("write proof fixture");
+    }
+}
+
+#[test]
+fn public_output_of_fixture_proof() {
+    require_proof_fixture_tests!();
+
+    let proof_path = common::fixture_proof_path("24073997");
+    assert!(
+        proof_path.exists(),
+        "{} is missing; generate it with RUN_CPU_PROVER_TESTS=1 UPDATE_PROOF_FIXTURE=1",
+        proof_path.display()
+    );
+    let input = common::load_fixture_input("24073997");
+    let block_hash = input.block_header.hash_slow();
+    let app_bin_path = common::app_bin_path();
+    let verifier = ProofVerifier::load(
+        &app_bin_path.with_file_name(SETUP_FILE_NAME),
+        &app_bin_path.with_file_name(LAYOUTS_FILE_NAME),
+    )
+    .expect("load verifier");
+    let proof_bytes = std::fs::read(&proof_path).expect("read proof fixture");
+
+    let output = verifier
+        .verify_bytes(&proof_bytes, None)
+        .expect("proof fixture verifies");
+    // The program output is the hash of the proven block.
+    assert_eq!(output.block_hash, block_hash.0);
+    assert_ne!(output.recursion_chain_hash, [0; 32]);
+    let err = verifier
+        .verify_bytes(&proof_bytes, Some(input.block_header.parent_hash))
+        .unwrap_err();
+    assert!(err.to_string().contains("expected block"), "{err}");
+}
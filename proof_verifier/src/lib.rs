@@ -0,0 +1,152 @@
+//! Verifier for Airbender proofs of the Ethereum STF.
+//!
+//! This crate is shared between the native `ethereum_prover` binary and the WASM verifier,
+//! so both of them always agree on the proof format and on the verification logic.
+
+use std::io::Read;
+
+mod unified_verifier;
+
+pub use unified_verifier::{
+    CompiledCircuitsSet, FinalRegisterValue, UnrolledProgramProof, UnrolledProgramSetup,
+    verify_proof_in_unified_layer,
+};
+
+/// Decodes a bincode-encoded value, requiring the whole input to be consumed.
+pub fn decode_exact<T: serde::de::DeserializeOwned>(bytes: &[u8], what: &str) -> Result<T, String> {
+    let (value, bytes_read): (T, usize) =
+        bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+            .map_err(|err| format!("failed to parse {what}: {err}"))?;
+
+    if bytes_read != bytes.len() {
+        return Err(format!(
+            "failed to parse {what}: trailing {} byte(s) indicate an incompatible format",
+            bytes.len() - bytes_read
+        ));
+    }
+
+    Ok(value)
+}
+
+/// Decodes a proof in the format submitted to EthProofs (gzip-compressed bincode).
+pub fn decode_compressed_proof(proof_bytes: &[u8]) -> Result<UnrolledProgramProof, String> {
+    let mut decoder = flate2::read::GzDecoder::new(proof_bytes);
+    let mut decompressed = Vec::new();
+    decoder
+        .read_to_end(&mut decompressed)
+        .map_err(|err| format!("gzip decode failed: {err}"))?;
+
+    decode_proof(&decompressed)
+}
+
+/// Decodes an uncompressed bincode-encoded proof.
+pub fn decode_proof(proof_bytes: &[u8]) -> Result<UnrolledProgramProof, String> {
+    // This verifier is intentionally tied to the current airbender proof layout.
+    // If the proof schema changes again, fail here with a format error instead of
+    // panicking later while the verifier reads from the ND-source stream.
+    decode_exact::<UnrolledProgramProof>(proof_bytes, "proof")
+}
+
+/// Public values committed to by a proof, decoded from the output of the recursion chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicOutput {
+    /// Output of the proven program: the hash of the block produced by the STF.
+    pub block_hash: [u8; 32],
+    /// Hash chain of the verification keys along the recursion. It depends on the proven program
+    /// and the recursion circuits, so it can be compared against the value of a known release, but
+    /// it is not the hash of `app.bin`.
+    pub recursion_chain_hash: [u8; 32],
+}
+
+impl PublicOutput {
+    /// Decodes the registers returned by the recursion layer verifier.
+    /// The first 8 words hold the program output, the last 8 words hold the recursion chain hash.
+    pub fn from_words(words: &[u32; 16]) -> Self {
+        Self {
+            block_hash: words_to_bytes(&words[..8]),
+            recursion_chain_hash: words_to_bytes(&words[8..]),
+        }
+    }
+
+    /// Fails if the proof was produced for a block other than `expected_block_hash`.
+    pub fn check_block_hash(&self, expected_block_hash: &[u8; 32]) -> Result<(), String> {
+        if &self.block_hash != expected_block_hash {
+            return Err(format!(
+                "proof is for block 0x{}, expected block 0x{}",
+                to_hex(&self.block_hash),
+                to_hex(expected_block_hash)
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Converts program output words to bytes; each word holds 4 bytes in big-endian order.
+fn words_to_bytes(words: &[u32]) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    for (chunk, word) in bytes.chunks_exact_mut(4).zip(words) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    bytes
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Verification keys for the recursion layer the proofs are produced at.
+pub struct VerifierContext {
+    pub setup: UnrolledProgramSetup,
+    pub layout: CompiledCircuitsSet,
+}
+
+impl VerifierContext {
+    /// Parses the contents of `recursion_unified_setup.bin` and `recursion_unified_layouts.bin`.
+    pub fn parse(setup_bin: &[u8], layout_bin: &[u8]) -> Result<Self, String> {
+        let setup = decode_exact::<UnrolledProgramSetup>(setup_bin, "setup.bin")?;
+        let layout = decode_exact::<CompiledCircuitsSet>(layout_bin, "layouts.bin")?;
+        Ok(Self { setup, layout })
+    }
+
+    /// Verifies the proof, returning the public values it commits to.
+    pub fn verify(&self, proof: &UnrolledProgramProof) -> Result<PublicOutput, String> {
+        let words = verify_proof_in_unified_layer(proof, &self.setup, &self.layout, false)
+            .map_err(|()| "Failed to verify proof".to_string())?;
+        Ok(PublicOutput::from_words(&words))
+    }
+
+    /// Verifies the proof and checks that it was produced for the expected block, if provided.
+    pub fn verify_for_block(
+        &self,
+        proof: &UnrolledProgramProof,
+        expected_block_hash: Option<&[u8; 32]>,
+    ) -> Result<PublicOutput, String> {
+        let output = self.verify(proof)?;
+        if let Some(expected_block_hash) = expected_block_hash {
+            output.check_block_hash(expected_block_hash)?;
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PublicOutput;
+
+    #[test]
+    fn public_output_is_decoded_from_words() {
+        let mut words = [0u32; 16];
+        words[0] = 0x0102_0304;
+        words[7] = 0xaabb_ccdd;
+        words[8] = 0xdead_beef;
+        let output = PublicOutput::from_words(&words);
+
+        assert_eq!(output.block_hash[..4], [0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(output.block_hash[28..], [0xaa, 0xbb, 0xcc, 0xdd]);
+        assert_eq!(output.recursion_chain_hash[..4], [0xde, 0xad, 0xbe, 0xef]);
+
+        assert!(output.check_block_hash(&output.block_hash).is_ok());
+        let err = output.check_block_hash(&[0u8; 32]).unwrap_err();
+        assert!(err.contains("expected block 0x0000"), "{err}");
+    }
+}
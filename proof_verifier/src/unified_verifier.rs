@@ -8,8 +8,8 @@ use full_statement_verifier::definitions::{
 use prover::common_constants;
 use prover::common_constants::TimestampScalar;
 use prover::cs::utils::split_timestamp;
-use prover::prover_stages::unrolled_prover::UnrolledModeProof;
 use prover::prover_stages::Proof;
+use prover::prover_stages::unrolled_prover::UnrolledModeProof;
 use verifier_common::field::Mersenne31Field;
 use verifier_common::proof_flattener;
 use verifier_common::prover::definitions::MerkleTreeCap;
@@ -165,15 +165,18 @@ fn flatten_proof_into_responses_for_unified_recursion(
         OP_VERIFY_UNROLLED_RECURSION_LAYER_IN_UNIFIED_CIRCUIT
     } else {
         assert_eq!(setup.circuit_families_setups.len(), 1);
-        assert!(setup
-            .circuit_families_setups
-            .contains_key(&common_constants::REDUCED_MACHINE_CIRCUIT_FAMILY_IDX));
+        assert!(
+            setup
+                .circuit_families_setups
+                .contains_key(&common_constants::REDUCED_MACHINE_CIRCUIT_FAMILY_IDX)
+        );
 
         assert_eq!(proof.circuit_families_proofs.len(), 1);
         assert!(proof.inits_and_teardowns_proofs.is_empty());
-        assert!(!proof.circuit_families_proofs
-            [&common_constants::REDUCED_MACHINE_CIRCUIT_FAMILY_IDX]
-            .is_empty());
+        assert!(
+            !proof.circuit_families_proofs[&common_constants::REDUCED_MACHINE_CIRCUIT_FAMILY_IDX]
+                .is_empty()
+        );
 
         OP_VERIFY_UNIFIED_RECURSION_LAYER_IN_UNIFIED_CIRCUIT
     };
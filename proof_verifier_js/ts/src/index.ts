@@ -19,6 +19,13 @@ export type VerificationResult = {
   success: boolean;
   /** Error details reported by the verifier, or null on success. */
   error: string | null;
+  /** Hash of the block the proof commits to (0x-prefixed hex), or null on failure. */
+  blockHash: string | null;
+  /**
+   * Hash chain of the recursion verification keys (0x-prefixed hex), or null on failure.
+   * Identifies the proven program together with the recursion circuits; it's not the hash of app.bin.
+   */
+  recursionChainHash: string | null;
 };
 
 /**
@@ -49,9 +56,10 @@ export type Verifier = {
    * Verifies a previously deserialized proof handle.
    * 
    * @param handle ProofHandle obtained from deserializeProofBytes.
+   * @param expectedBlockHash Optional 32-byte block hash the proof must commit to.
    * @returns VerificationResult describing success/failure.
    */
-  verifyProof: (handle: ProofHandle) => VerificationResult;
+  verifyProof: (handle: ProofHandle, expectedBlockHash?: Uint8Array) => VerificationResult;
 };
 
 let initPromise: Promise<InitOutput> | null = null;
@@ -68,15 +76,19 @@ class VerifierImpl implements Verifier {
     return deserialize_proof_bytes(proofBytes);
   }
 
-  verifyProof(handle: ProofHandle): VerificationResult {
-    const result = verify_proof(handle) as unknown as {
+  verifyProof(handle: ProofHandle, expectedBlockHash?: Uint8Array): VerificationResult {
+    const result = verify_proof(handle, expectedBlockHash) as unknown as {
       success: boolean;
       error: () => string | null;
+      block_hash: () => string | null;
+      recursion_chain_hash: () => string | null;
     };
 
     return {
       success: result.success,
-      error: result.error()
+      error: result.error(),
+      blockHash: result.block_hash() ?? null,
+      recursionChainHash: result.recursion_chain_hash() ?? null
     };
   }
 
@@ -1,51 +1,19 @@
 use std::cell::RefCell;
-use std::io::Read;
 
 use console_error_panic_hook::set_once as set_panic_hook;
-use wasm_bindgen::prelude::*;
-
-mod unified_verifier;
-
-use unified_verifier::{
-    verify_proof_in_unified_layer, CompiledCircuitsSet, UnrolledProgramProof, UnrolledProgramSetup,
+use proof_verifier::{
+    decode_compressed_proof, to_hex, PublicOutput, UnrolledProgramProof, VerifierContext,
 };
+use wasm_bindgen::prelude::*;
 
 const DEFAULT_SETUP_BIN: &[u8] = include_bytes!("../../../artifacts/recursion_unified_setup.bin");
 const DEFAULT_LAYOUT_BIN: &[u8] =
     include_bytes!("../../../artifacts/recursion_unified_layouts.bin");
 
-struct VerifierContext {
-    setup: UnrolledProgramSetup,
-    layout: CompiledCircuitsSet,
-}
-
-fn decode_exact<T: serde::de::DeserializeOwned>(bytes: &[u8], what: &str) -> Result<T, String> {
-    let (value, bytes_read): (T, usize) =
-        bincode::serde::decode_from_slice(bytes, bincode::config::standard())
-            .map_err(|err| format!("failed to parse {what}: {err}"))?;
-
-    if bytes_read != bytes.len() {
-        return Err(format!(
-            "failed to parse {what}: trailing {} byte(s) indicate an incompatible format",
-            bytes.len() - bytes_read
-        ));
-    }
-
-    Ok(value)
-}
-
-impl VerifierContext {
-    fn parse(setup_bin: &[u8], layout_bin: &[u8]) -> Result<Self, String> {
-        let setup = decode_exact::<UnrolledProgramSetup>(setup_bin, "setup.bin")?;
-        let layout = decode_exact::<CompiledCircuitsSet>(layout_bin, "layouts.bin")?;
-        Ok(Self { setup, layout })
-    }
-
-    fn set_global(self) {
-        CONTEXT.with(|slot| {
-            slot.borrow_mut().replace(self);
-        });
-    }
+fn set_global(context: VerifierContext) {
+    CONTEXT.with(|slot| {
+        slot.borrow_mut().replace(context);
+    });
 }
 
 thread_local! {
@@ -62,7 +30,7 @@ pub fn init_with(setup_bin: &[u8], layout_bin: &[u8]) -> Result<(), JsValue> {
     set_panic_hook();
     let context =
         VerifierContext::parse(setup_bin, layout_bin).map_err(|err| JsValue::from_str(&err))?;
-    context.set_global();
+    set_global(context);
     Ok(())
 }
 
@@ -73,17 +41,7 @@ pub struct ProofHandle {
 
 #[wasm_bindgen]
 pub fn deserialize_proof_bytes(proof_bytes: &[u8]) -> Result<ProofHandle, JsValue> {
-    let mut decoder = flate2::read::GzDecoder::new(proof_bytes);
-    let mut decompressed = Vec::new();
-    decoder
-        .read_to_end(&mut decompressed)
-        .map_err(|err| JsValue::from_str(&format!("gzip decode failed: {err}")))?;
-
-    // This verifier is intentionally tied to the current airbender proof layout.
-    // If the proof schema changes again, fail here with a format error instead of
-    // panicking later while the verifier reads from the ND-source stream.
-    let proof = decode_exact::<UnrolledProgramProof>(&decompressed, "proof")
-        .map_err(|err| JsValue::from_str(&err))?;
+    let proof = decode_compressed_proof(proof_bytes).map_err(|err| JsValue::from_str(&err))?;
 
     Ok(ProofHandle { proof })
 }
@@ -92,6 +50,17 @@ pub fn deserialize_proof_bytes(proof_bytes: &[u8]) -> Result<ProofHandle, JsValu
 pub struct VerifyResult {
     success: bool,
     error: Option<String>,
+    output: Option<PublicOutput>,
+}
+
+impl VerifyResult {
+    fn failure(error: String) -> Self {
+        Self {
+            success: false,
+            error: Some(error),
+            output: None,
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -105,30 +74,53 @@ impl VerifyResult {
     pub fn error(&self) -> Option<JsValue> {
         self.error.as_ref().map(|e| JsValue::from_str(e))
     }
+
+    /// Hash of the block the proof commits to, as a `0x`-prefixed hex string.
+    #[wasm_bindgen]
+    pub fn block_hash(&self) -> Option<String> {
+        self.output
+            .as_ref()
+            .map(|output| format!("0x{}", to_hex(&output.block_hash)))
+    }
+
+    /// Hash chain of the recursion verification keys, as a `0x`-prefixed hex string.
+    /// Identifies the proven program together with the recursion circuits; it's not the hash of
+    /// `app.bin`.
+    #[wasm_bindgen]
+    pub fn recursion_chain_hash(&self) -> Option<String> {
+        self.output
+            .as_ref()
+            .map(|output| format!("0x{}", to_hex(&output.recursion_chain_hash)))
+    }
 }
 
+/// Verifies the proof. If `expected_block_hash` (32 bytes) is provided, the proof must
+/// commit to that block.
 #[wasm_bindgen]
-pub fn verify_proof(handle: &ProofHandle) -> VerifyResult {
+pub fn verify_proof(handle: &ProofHandle, expected_block_hash: Option<Vec<u8>>) -> VerifyResult {
+    let expected_block_hash = match expected_block_hash
+        .map(|hash| <[u8; 32]>::try_from(hash.as_slice()))
+        .transpose()
+    {
+        Ok(hash) => hash,
+        Err(_) => return VerifyResult::failure("expected block hash must be 32 bytes".to_string()),
+    };
+
     CONTEXT.with(|slot| {
         let context = slot.borrow();
         let Some(context) = context.as_ref() else {
-            return VerifyResult {
-                success: false,
-                error: Some(
-                    "verifier not initialized (call init_defaults or init_with)".to_string(),
-                ),
-            };
+            return VerifyResult::failure(
+                "verifier not initialized (call init_defaults or init_with)".to_string(),
+            );
         };
 
-        match verify_proof_in_unified_layer(&handle.proof, &context.setup, &context.layout, false) {
-            Ok(_result) => VerifyResult {
+        match context.verify_for_block(&handle.proof, expected_block_hash.as_ref()) {
+            Ok(output) => VerifyResult {
                 success: true,
                 error: None,
+                output: Some(output),
             },
-            Err(()) => VerifyResult {
-                success: false,
-                error: Some("Failed to verify proof".to_string()),
-            },
+            Err(err) => VerifyResult::failure(err),
         }
     })
 }